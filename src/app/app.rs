@@ -1,7 +1,189 @@
-use crate::util::{StatefulList, TabsState};
-use crate::disk::Disk;
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::bench::{estimate_wipe, Throughputs};
+use crate::util::{find_images, StatefulList, TabsState};
+use crate::disk::direct::IoOptions;
+use crate::disk::{Disk, PartitionInfo};
+use crate::hexview::{HexViewer, Prompt};
+use crate::logging::LogBuffer;
+use crate::job::{Job, JobKind, JobReport, JobStatus, WipeScope};
+use crate::report::{default_operator, write_session, ErasureReport, SignedReport};
+use crate::sanitize::{supported_commands, SanitizeCommand};
+use crate::wipe::WipeMethod;
 //Where state is handeled 
 
+//How many characters of the serial number have to be typed to confirm overwriting a drive
+const CODE_LENGTH: usize = 4;
+//Lines the log pane moves per page key
+const LOG_PAGE: usize = 10;
+
+
+/// The three things the app can do, picked on the first screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Clone,
+    Burn,
+    Destroy,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Clone, Mode::Burn, Mode::Destroy];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Clone => "Clone",
+            Mode::Burn => "Burn",
+            Mode::Destroy => "Destroy",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Mode::Clone => "Clone an external disk into an image file",
+            Mode::Burn => "Burn an image file to an external disk",
+            Mode::Destroy => "Securely wipe an external disk, its free space or leftover metadata",
+        }
+    }
+
+    //Label of the button which starts the job on the confirmation screen
+    fn action(&self) -> &'static str {
+        match self {
+            Mode::Clone => "<CLONE>",
+            Mode::Burn => "<BURN>",
+            Mode::Destroy => "<DELETE>",
+        }
+    }
+}
+
+impl JobKind {
+    /// Mode a job belongs to
+    pub fn mode(&self) -> Mode {
+        match self {
+            JobKind::Clone { .. } => Mode::Clone,
+            JobKind::Burn { .. } => Mode::Burn,
+            JobKind::Wipe { .. } => Mode::Destroy,
+        }
+    }
+}
+
+/// Screen the app is on, together with everything chosen on the screens before it. A job can only be
+/// started from `Confirm`, which already holds the fully built job.
+#[allow(clippy::large_enum_variant)] //There is only ever one
+#[derive(Clone, Debug)]
+pub enum State {
+    SelectMode,
+    SelectImage, //Burn only, the image is picked before the drive
+    SelectDrive { mode: Mode, image: Option<PathBuf> },
+    SelectScope { disks: Vec<Disk> }, //Every drive marked for wiping
+    SelectMethod { disks: Vec<Disk>, scope: WipeScope },
+    Options { job: JobKind }, //Clone and burn only
+    //One job per drive from here on, only wipes ever run several at once
+    Confirm { jobs: Vec<JobKind> },
+    Countdown { jobs: Vec<JobKind>, until: Instant }, //Last chance to back out before a drive is overwritten
+    Progress { jobs: Vec<JobKind> },
+    Result { jobs: Vec<JobKind> },
+    HexView { back: Box<State> }, //Read only look at a drive or image, goes back to where it was opened from
+}
+
+impl State {
+    pub fn title(&self) -> &'static str {
+        match self {
+            State::SelectMode => "Select Mode",
+            State::SelectImage => "Select Image",
+            State::SelectDrive { mode: Mode::Clone, .. } => "Select Source Drive",
+            State::SelectDrive { mode: Mode::Burn, .. } => "Select Target Drive",
+            State::SelectDrive { mode: Mode::Destroy, .. } => "Select Drive",
+            State::SelectScope { .. } => "Select What To Wipe",
+            State::SelectMethod { .. } => "Select Deletion Method",
+            State::Options { .. } => "Options",
+            State::Confirm { .. } => "Confirm",
+            State::Countdown { .. } => "Starting",
+            State::Progress { jobs } => match jobs_mode(jobs) {
+                Some(Mode::Clone) => "Clone In progress",
+                Some(Mode::Burn) => "Burn In progress",
+                _ => "Deletion In progress",
+            },
+            State::Result { .. } => "Complete",
+            State::HexView { .. } => "Hex Viewer",
+        }
+    }
+
+    /// Mode being walked through, `None` on the mode selection screen
+    pub fn mode(&self) -> Option<Mode> {
+        match self {
+            State::SelectMode => None,
+            State::SelectImage => Some(Mode::Burn),
+            State::SelectDrive { mode, .. } => Some(*mode),
+            State::SelectScope { .. } | State::SelectMethod { .. } => Some(Mode::Destroy),
+            State::Options { job } => Some(job.mode()),
+            State::Confirm { jobs } | State::Countdown { jobs, .. } | State::Progress { jobs } | State::Result { jobs } => {
+                jobs_mode(jobs)
+            }
+            State::HexView { back } => back.mode(),
+        }
+    }
+
+    //Every transition the app may take, anything else is a bug and gets refused
+    fn can_transition_to(&self, next: &State) -> bool {
+        use State::*;
+        match (self, next) {
+            //There is always something to confirm and run
+            (_, Confirm { jobs }) | (_, Countdown { jobs, .. }) | (_, Progress { jobs }) if jobs.is_empty() => false,
+            //A running job can only end up on the result screen
+            (Progress { .. }, Result { .. }) => true,
+            (Progress { .. }, _) => false,
+            //The viewer opens from the lists and the results and only goes back where it came from
+            (SelectDrive { .. }, HexView { .. }) | (SelectImage, HexView { .. }) | (Result { .. }, HexView { .. }) => true,
+            (HexView { back }, next) => std::mem::discriminant(&**back) == std::mem::discriminant(next),
+            //The only way into a job is through the confirmation screen, and the countdown for jobs
+            //which overwrite a drive
+            (Confirm { jobs }, Progress { .. }) => jobs.iter().all(|j| j.target().is_none()),
+            (Confirm { jobs }, Countdown { .. }) => jobs.iter().all(|j| j.target().is_some()),
+            (Countdown { .. }, Progress { .. }) => true,
+            (Countdown { .. }, Confirm { .. }) => true,
+            (_, Progress { .. }) | (_, Result { .. }) => false,
+            (_, SelectMode) => true,
+            (SelectMode, SelectImage) | (SelectMode, SelectDrive { .. }) => true,
+            (SelectImage, SelectDrive { mode: Mode::Burn, image: Some(_) }) => true,
+            (SelectDrive { mode: Mode::Burn, .. }, SelectImage) => true,
+            (SelectDrive { mode: Mode::Destroy, .. }, SelectScope { .. }) => true,
+            (SelectDrive { mode, .. }, Options { job }) => *mode != Mode::Destroy && job.mode() == *mode,
+            (SelectScope { .. }, SelectDrive { mode: Mode::Destroy, .. }) => true,
+            (SelectScope { .. }, SelectMethod { .. }) => true,
+            (SelectScope { .. }, Confirm { jobs }) => wipe_scope(jobs) == Some(WipeScope::Slack),
+            (SelectMethod { .. }, SelectScope { .. }) => true,
+            (SelectMethod { .. }, Confirm { jobs }) => wipe_scope(jobs).is_some(),
+            (Options { job }, SelectDrive { mode, .. }) => job.mode() == *mode,
+            (Options { .. }, Options { .. }) => true,
+            (Options { .. }, Confirm { jobs }) => jobs.len() == 1,
+            (Confirm { jobs }, SelectScope { .. }) => wipe_scope(jobs) == Some(WipeScope::Slack),
+            (Confirm { jobs }, SelectMethod { .. }) => wipe_scope(jobs).is_some(),
+            (Confirm { jobs }, Options { .. }) => wipe_scope(jobs).is_none(),
+            _ => false,
+        }
+    }
+}
+
+//Mode of the jobs of a state, they all share the same one
+fn jobs_mode(jobs: &[JobKind]) -> Option<Mode> {
+    jobs.first().map(|j| j.mode())
+}
+
+//Scope shared by a batch of wipe jobs, `None` for clone and burn jobs
+fn wipe_scope(jobs: &[JobKind]) -> Option<WipeScope> {
+    let scope = match jobs.first() {
+        Some(JobKind::Wipe { scope, .. }) => *scope,
+        _ => return None,
+    };
+    let same = jobs.iter().all(|j| matches!(j, JobKind::Wipe { scope: s, .. } if *s == scope));
+    if same {
+        Some(scope)
+    } else {
+        None
+    }
+}
 
 
 /// This struct holds the current state of the app. In particular, it has the `items` field which is a wrapper
@@ -11,116 +193,1106 @@ use crate::disk::Disk;
 /// Check the event handling at the bottom to see how to change the state on incoming events.
 /// Check the drawing logic for items on how to specify the highlighting style for selected items.
 pub struct App<'a> {
-    pub debug_mode: bool,
+    pub debug_mode: bool, //Dry runs only, jobs never write to a device
     pub title: &'a str,
+    pub enhanced_graphics: bool, //Unicode symbols in lists
     pub should_quit: bool,
-    pub is_deleting: bool,
     pub deletion_progress: f64,
+    pub state: State,
+    pub modes: StatefulList<Mode>,
     pub drives: StatefulList<Disk>,
-    pub deletion_methods: StatefulList<&'a str>,
-    pub confirmation: TabsState<'a>, //yes no
-    pub status: TabsState<'a>, //Which phase of cli state is shown
+    pub marked_drives: Vec<usize>, //Drives picked with space to be wiped together, sorted
+    pub images: StatefulList<PathBuf>,
+    pub scopes: StatefulList<WipeScope>,
+    pub deletion_methods: StatefulList<WipeMethod>,
+    pub all_deletion_methods: Vec<WipeMethod>, //Unfiltered list, firmware methods are narrowed down per drive
+    pub sanitize_support: Vec<SanitizeCommand>, //Firmware erase commands every selected drive accepts
+    pub jobs: Vec<Job>, //Running jobs, in the order of the jobs of the state
+    pub job_statuses: Vec<JobStatus>,
+    pub session_summary: Vec<String>, //Shown above the per drive results when several ran
+    pub confirmation: TabsState<'a>, //cancel or go
+    pub typed: String, //Confirmation code typed so far
+    pub target_partitions: Vec<PartitionInfo>, //Partitions of the drives on the confirmation screen
+    pub countdown: Duration, //Delay between confirming and the first write
+    pub io: IoOptions,
+    pub throughputs: Throughputs, //Read speeds of the drives picked for wiping
+    pub viewer: Option<Result<HexViewer>>, //Open on the hex viewer, or why it couldn't be
+    pub log: LogBuffer,
+    pub show_log: bool, //Log pane in place of the partition layout
+    pub log_scroll: usize, //Lines the log pane is scrolled up from the latest one
+    pub launcher: fn(JobKind) -> Job, //Starts jobs, swapped out in tests
+    pub report_dir: Option<PathBuf>, //Where certificates of erasure are written after a wipe
 }
 
 
 impl<'a> App<'a> {
-    pub fn new(drives: Vec<Disk>, deletion_methods: Vec<&'a str>, title: &'a str) -> App<'a> {
+    pub fn new(drives: Vec<Disk>, deletion_methods: Vec<WipeMethod>, title: &'a str) -> App<'a> {
         App {
-            debug_mode: true, //Prevent anything destructive from happening
-            title: title,
-            status: TabsState::new(vec!["Select Drive", "Select Deletion Method", "Confirm", "Deletion In progress", "Verify in progress", "Complete", "Error"]),
+            debug_mode: false,
+            title,
+            enhanced_graphics: true,
             should_quit: false,
-            is_deleting: false,
-            confirmation: TabsState::new(vec!["<CANCEL>", "<DELETE>"]),
             deletion_progress: 0.00,
+            state: State::SelectMode,
+            modes: StatefulList::with_items(Mode::ALL.to_vec()),
+            images: StatefulList::with_items(find_images(&PathBuf::from("."))),
+            scopes: StatefulList::with_items(WipeScope::ALL.to_vec()),
+            all_deletion_methods: deletion_methods.clone(),
+            sanitize_support: Vec::new(),
             deletion_methods: StatefulList::with_items(deletion_methods),
             drives: StatefulList::with_items(drives),
+            marked_drives: Vec::new(),
+            jobs: Vec::new(),
+            job_statuses: Vec::new(),
+            session_summary: Vec::new(),
+            confirmation: TabsState::new(vec!["<CANCEL>", Mode::Destroy.action()]),
+            typed: String::new(),
+            target_partitions: Vec::new(),
+            countdown: Duration::from_secs(5),
+            io: IoOptions::default(),
+            throughputs: Throughputs::default(),
+            viewer: None,
+            log: LogBuffer::default(),
+            show_log: false,
+            log_scroll: 0,
+            launcher: Job::spawn,
+            report_dir: None,
+        }
+    }
+
+    /// Whether a job is running, quitting and going back are blocked meanwhile
+    pub fn is_busy(&self) -> bool {
+        matches!(self.state, State::Progress { .. })
+    }
+
+    //Moves to `next` if that is a valid transition, returns whether it did
+    fn transition(&mut self, next: State) -> bool {
+        if !self.state.can_transition_to(&next) {
+            return false;
+        }
+        //Always land on cancel, confirming has to be a deliberate choice
+        if let State::Confirm { jobs } = &next {
+            let action = jobs_mode(jobs).unwrap_or(Mode::Destroy).action();
+            self.confirmation = TabsState::new(vec!["<CANCEL>", action]);
+            self.typed.clear();
+            self.target_partitions = jobs.iter().flat_map(|j| j.disk().describe_partitions()).collect();
         }
+        //Starting over must not leave the previous drives picked
+        if !matches!(next, State::HexView { .. }) {
+            self.viewer = None;
+        }
+        if matches!(self.state, State::Result { .. }) && !matches!(next, State::HexView { .. }) {
+            self.drives.unselect();
+            self.marked_drives.clear();
+            self.deletion_methods.unselect();
+        }
+        self.state = next;
+        true
     }
 
     pub fn on_up(&mut self) {
-        match self.status.index {
-            0 => { self.drives.previous() }
-            1 => { self.deletion_methods.previous() }
+        match self.state {
+            State::SelectMode => self.modes.previous(),
+            State::SelectDrive { .. } => self.drives.previous(),
+            State::SelectImage => self.images.previous(),
+            State::SelectScope { .. } => self.scopes.previous(),
+            State::SelectMethod { .. } => self.deletion_methods.previous(),
+            State::HexView { .. } => {
+                if let Some(viewer) = self.viewer_mut() {
+                    viewer.scroll(-1);
+                }
+            }
             _ => {}
         }
     }
 
 
     pub fn on_down(&mut self) {
-        match self.status.index {
-            0 => { self.drives.next() }
-            1 => { self.deletion_methods.next() }
+        match self.state {
+            State::SelectMode => self.modes.next(),
+            State::SelectDrive { .. } => self.drives.next(),
+            State::SelectImage => self.images.next(),
+            State::SelectScope { .. } => self.scopes.next(),
+            State::SelectMethod { .. } => self.deletion_methods.next(),
+            State::HexView { .. } => {
+                if let Some(viewer) = self.viewer_mut() {
+                    viewer.scroll(1);
+                }
+            }
             _ => {}
         }
     }
 
+    //Page keys scroll the log pane while it's shown, the hex viewer otherwise
+    pub fn on_page_up(&mut self) {
+        if self.show_log {
+            self.log_scroll += LOG_PAGE;
+        } else if let Some(viewer) = self.viewer_mut() {
+            viewer.page(-1);
+        }
+    }
+
+    pub fn on_page_down(&mut self) {
+        if self.show_log {
+            self.log_scroll = self.log_scroll.saturating_sub(LOG_PAGE);
+        } else if let Some(viewer) = self.viewer_mut() {
+            viewer.page(1);
+        }
+    }
+
+    //Open viewer, if it could be opened
+    fn viewer_mut(&mut self) -> Option<&mut HexViewer> {
+        match &mut self.viewer {
+            Some(Ok(viewer)) => Some(viewer),
+            _ => None,
+        }
+    }
+
+    /// Opens the hex viewer on the highlighted drive or image, or on the drive of the first job once done
+    pub fn on_view(&mut self) {
+        let path = match &self.state {
+            State::SelectDrive { .. } => self.selected_drive().map(|d| d.name.clone()),
+            State::SelectImage => self.selected_image().cloned(),
+            State::Result { jobs } => jobs.first().map(|j| j.disk().name.clone()),
+            _ => None,
+        };
+        if let Some(path) = path {
+            let back = Box::new(self.state.clone());
+            if self.transition(State::HexView { back }) {
+                let viewer = HexViewer::open(&path).map_err(|e| anyhow!("Could not open {}: {}", path.display(), e));
+                if let Err(e) = &viewer {
+                    log::warn!("{}", e);
+                }
+                self.viewer = Some(viewer);
+            }
+        }
+    }
+
+    /// Letter keys that aren't taken by anything else, l toggles the log pane, v opens the viewer and
+    /// the rest act in it
+    pub fn on_shortcut(&mut self, c: char) {
+        match c {
+            'l' => {
+                self.show_log = !self.show_log;
+                self.log_scroll = 0;
+                return;
+            }
+            'v' => {
+                self.on_view();
+                return;
+            }
+            _ => {}
+        }
+        if let Some(viewer) = self.viewer_mut() {
+            match c {
+                'g' => viewer.start_prompt(Prompt::Offset),
+                's' => viewer.start_prompt(Prompt::Sector),
+                '/' => viewer.start_prompt(Prompt::Search),
+                'p' => viewer.next_partition(),
+                'n' => viewer.search_next(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Code that has to be typed before the job on the confirmation screen may start, `None` when
+    /// nothing gets overwritten
+    pub fn confirmation_code(&self) -> Option<String> {
+        let targets: Vec<&Disk> = match &self.state {
+            State::Confirm { jobs } => jobs.iter().filter_map(|j| j.target()).collect(),
+            _ => return None,
+        };
+        if targets.is_empty() {
+            return None;
+        }
+        //Every drive's own code, in order, so each one about to be overwritten gets looked at
+        Some(targets.iter().map(|disk| confirmation_code(disk)).collect::<Vec<String>>().join(" "))
+    }
+
+    /// Whether what was typed matches `code`, case and extra spaces don't matter
+    pub fn code_matches(&self, code: &str) -> bool {
+        let typed: Vec<&str> = self.typed.split_whitespace().collect();
+        typed.join(" ").eq_ignore_ascii_case(code)
+    }
+
+    /// Whether keys are typed into the confirmation code rather than acting as shortcuts
+    pub fn is_typing(&self) -> bool {
+        self.confirmation_code().is_some() || matches!(&self.viewer, Some(Ok(viewer)) if viewer.prompt.is_some())
+    }
+
+    pub fn on_char(&mut self, c: char) {
+        if let Some(viewer) = self.viewer_mut() {
+            if viewer.prompt.is_some() && viewer.input.len() < 64 {
+                viewer.input.push(c);
+            }
+            return;
+        }
+        if self.is_typing() && self.typed.len() < 256 {
+            self.typed.push(c);
+        }
+    }
+
+    pub fn on_backspace(&mut self) {
+        if let Some(viewer) = self.viewer_mut() {
+            viewer.input.pop();
+            return;
+        }
+        if self.is_typing() {
+            self.typed.pop();
+        }
+    }
+
     pub fn on_left(&mut self) {
-        if self.status.index == 2 {
+        if let State::Confirm { .. } = self.state {
             self.confirmation.previous();
         }
     }
 
     pub fn on_right(&mut self) {
-        if self.status.index == 2 {
+        if let State::Confirm { .. } = self.state {
             self.confirmation.next();
         }
     }
 
-    
+    //Space marks drives to wipe together and flips the verify option on the options screen
+    pub fn on_toggle(&mut self) {
+        if let State::SelectDrive { mode: Mode::Destroy, .. } = self.state {
+            if let Some(index) = self.drives.state.selected() {
+                match self.marked_drives.binary_search(&index) {
+                    Ok(position) => {
+                        self.marked_drives.remove(position);
+                    }
+                    Err(position) => self.marked_drives.insert(position, index),
+                }
+            }
+            return;
+        }
+        //Discarding after the passes only makes sense when they went over the whole drive
+        if let State::SelectMethod { scope: WipeScope::EntireDrive, .. } = self.state {
+            if let Some(index) = self.deletion_methods.state.selected() {
+                let method = &mut self.deletion_methods.items[index];
+                if !method.is_firmware() {
+                    method.discard = !method.discard;
+                }
+            }
+            return;
+        }
+        if let State::Options { job } = &self.state {
+            let mut job = job.clone();
+            match &mut job {
+                JobKind::Clone { verify, .. } | JobKind::Burn { verify, .. } => *verify = !*verify,
+                JobKind::Wipe { .. } => {}
+            }
+            self.transition(State::Options { job });
+        }
+    }
 
     //The key "e" is what continues the state 
     pub fn on_continue(&mut self) {
-        match self.status.index {
-            0 => {
-                if self.drives.state.selected() != None {
-                    self.status.next();
+        let next = match &self.state {
+            State::SelectMode => match self.modes.state.selected().map(|i| self.modes.items[i]) {
+                Some(Mode::Burn) => Some(State::SelectImage),
+                Some(mode) => Some(State::SelectDrive { mode, image: None }),
+                None => None,
+            },
+            State::SelectImage => self.selected_image().map(|image| State::SelectDrive {
+                mode: Mode::Burn,
+                image: Some(image.clone()),
+            }),
+            State::SelectDrive { mode: Mode::Destroy, .. } => {
+                let disks = self.marked_disks();
+                self.select_drives(&disks);
+                if disks.is_empty() {
+                    None
+                } else {
+                    Some(State::SelectScope { disks })
                 }
             }
-            1 => {
-                if self.deletion_methods.state.selected() != None {
-                    self.status.next();
-                }
+            State::SelectDrive { mode, image } => {
+                let (mode, image) = (*mode, image.clone());
+                self.selected_drive().cloned().and_then(|disk| match mode {
+                    Mode::Destroy => None,
+                    Mode::Clone => Some(State::Options {
+                        job: JobKind::Clone {
+                            destination: clone_destination(&disk),
+                            disk,
+                            verify: true,
+                            io: self.io,
+                        },
+                    }),
+                    Mode::Burn => image.map(|image| State::Options {
+                        job: JobKind::Burn { image, disk, verify: true, io: self.io },
+                    }),
+                })
             }
-            2 => {
-                if self.confirmation.titles[self.confirmation.index] == String::from("<DELETE>") {
-                    self.is_deleting = true;
-                    self.status.next();
+            State::SelectScope { disks } => match self.selected_scope() {
+                Some(scope) if scope.uses_method() => Some(State::SelectMethod { disks: disks.clone(), scope }),
+                Some(scope) => Some(State::Confirm {
+                    jobs: disks
+                        .iter()
+                        .map(|disk| JobKind::Wipe {
+                            disk: disk.clone(),
+                            //Deep cleaning only zeroes, the method isn't used
+                            method: WipeMethod::new("Zero slack", "", Vec::new()),
+                            scope,
+                            sanitize: Vec::new(),
+                            io: self.io,
+                        })
+                        .collect(),
+                }),
+                None => None,
+            },
+            State::SelectMethod { disks, scope } => self.selected_method().map(|method| State::Confirm {
+                jobs: disks
+                    .iter()
+                    .map(|disk| JobKind::Wipe {
+                        disk: disk.clone(),
+                        method: WipeMethod { discard: method.discard && *scope == WipeScope::EntireDrive, ..method.clone() },
+                        scope: *scope,
+                        sanitize: self.sanitize_support.clone(),
+                        io: self.io,
+                    })
+                    .collect(),
+            }),
+            State::Options { job } => Some(State::Confirm { jobs: vec![job.clone()] }),
+            State::Confirm { jobs } => {
+                if self.confirmation.index == 0 {
+                    self.on_back();
+                    None
                 } else {
-                    self.status.previous();
+                    match self.confirmation_code() {
+                        None => {
+                            let jobs = jobs.clone();
+                            self.start_jobs(jobs);
+                            None
+                        }
+                        Some(code) if self.code_matches(&code) => Some(State::Countdown {
+                            jobs: jobs.clone(),
+                            until: Instant::now() + self.countdown,
+                        }),
+                        //Wrong code, stay until it is fixed or cancelled
+                        Some(_) => None,
+                    }
                 }
             }
-            3 => {
-                //This is probably where you match up the task and what to do e.g. delete or clone
-                self.is_deleting = true;
+            State::Countdown { .. } | State::Progress { .. } => None,
+            State::HexView { .. } => {
+                if let Some(viewer) = self.viewer_mut() {
+                    viewer.submit();
+                }
+                None
             }
-            _ => {}
+            State::Result { .. } => Some(State::SelectMode),
+        };
+        if let Some(next) = next {
+            self.transition(next);
         }
-
     }
 
     pub fn on_back(&mut self) {
-        match self.status.index {
-            0 => {}
-            1 => { self.status.previous() }
-            2 => {
-                self.status.previous();
-                self.is_deleting = true;
-            }
-            3 => {
-                if !self.is_deleting {
-                    self.status.previous();
+        //Backing out of a prompt or search first, then out of the viewer
+        if let Some(viewer) = self.viewer_mut() {
+            if viewer.is_busy() {
+                viewer.cancel();
+                return;
+            }
+        }
+        let previous = match &self.state {
+            State::HexView { back } => Some((**back).clone()),
+            State::SelectMode | State::Progress { .. } => None,
+            State::Countdown { jobs, .. } => Some(State::Confirm { jobs: jobs.clone() }),
+            State::SelectImage | State::Result { .. } => Some(State::SelectMode),
+            State::SelectDrive { mode: Mode::Burn, .. } => Some(State::SelectImage),
+            State::SelectDrive { .. } => Some(State::SelectMode),
+            State::SelectScope { .. } => Some(State::SelectDrive {
+                mode: Mode::Destroy,
+                image: None,
+            }),
+            State::SelectMethod { disks, .. } => Some(State::SelectScope { disks: disks.clone() }),
+            State::Options { job } => Some(State::SelectDrive {
+                mode: job.mode(),
+                image: match job {
+                    JobKind::Burn { image, .. } => Some(image.clone()),
+                    _ => None,
+                },
+            }),
+            State::Confirm { jobs } => {
+                let disks = jobs.iter().map(|j| j.disk().clone()).collect();
+                match wipe_scope(jobs) {
+                    Some(scope) if scope.uses_method() => Some(State::SelectMethod { disks, scope }),
+                    Some(_) => Some(State::SelectScope { disks }),
+                    None => jobs.first().map(|job| State::Options { job: job.clone() }),
                 }
             }
-            4 => { self.status.previous() }
-            _ => {}
+        };
+        if let Some(previous) = previous {
+            self.transition(previous);
+        }
+    }
+
+    //Polls the running job and moves on to the result screen once it is done
+    pub fn on_tick(&mut self) {
+        if let Some(viewer) = self.viewer_mut() {
+            viewer.on_tick();
+        }
+        if let State::Countdown { jobs, until } = &self.state {
+            if Instant::now() >= *until {
+                let jobs = jobs.clone();
+                self.start_jobs(jobs);
+            }
+            return;
+        }
+        if self.jobs.is_empty() {
+            return;
+        }
+        self.job_statuses = self.jobs.iter().map(|j| j.status()).collect();
+        self.deletion_progress =
+            self.job_statuses.iter().map(|s| s.progress).sum::<f64>() / self.job_statuses.len() as f64;
+        //Every drive keeps going on its own, the session only ends once the last one is done
+        if self.job_statuses.iter().all(|s| s.finished) {
+            self.jobs.clear();
+            if let State::Progress { jobs } = &self.state {
+                let jobs = jobs.clone();
+                for (job, status) in jobs.iter().zip(self.job_statuses.iter_mut()) {
+                    if let Some(line) = write_report(self.report_dir.as_deref(), job, status) {
+                        status.summary.push(line);
+                    }
+                }
+                self.summarize_session(&jobs);
+                self.transition(State::Result { jobs });
+            }
         }
     }
 
-    //TODO Quit the app but only allow that if it not in process of wiping drive
+    fn start_jobs(&mut self, jobs: Vec<JobKind>) {
+        if self.transition(State::Progress { jobs: jobs.clone() }) {
+            self.job_statuses = vec![JobStatus::default(); jobs.len()];
+            self.session_summary.clear();
+            self.deletion_progress = 0.0;
+            //Dry runs must never reach the real launcher, whichever mode started the jobs
+            let launch = if self.debug_mode { Job::simulate } else { self.launcher };
+            self.jobs = jobs.into_iter().map(launch).collect();
+        }
+    }
+
+    //Tally of a session of several jobs, written next to the certificates as well
+    fn summarize_session(&mut self, jobs: &[JobKind]) {
+        if jobs.len() < 2 {
+            return;
+        }
+        let reports: Vec<JobReport> = jobs.iter().zip(self.job_statuses.iter()).map(|(j, s)| JobReport::new(j, s)).collect();
+        let failed = reports.iter().filter(|r| !r.success).count();
+        self.session_summary.push(format!("{} of {} drives finished successfully", reports.len() - failed, reports.len()));
+        if failed > 0 {
+            let names: Vec<String> = reports.iter().filter(|r| !r.success).map(|r| r.device.display().to_string()).collect();
+            self.session_summary.push(format!("Failed: {}", names.join(", ")));
+        }
+        if let Some(directory) = &self.report_dir {
+            self.session_summary.push(match write_session(directory, &reports) {
+                Ok(path) => format!("Session summary written to {}", path.display()),
+                Err(e) => format!("Could not write session summary: {}", e),
+            });
+        }
+    }
+
+    /// Currently highlighted wipe method, if any
+    pub fn selected_method(&self) -> Option<&WipeMethod> {
+        self.deletion_methods.state.selected().map(|i| &self.deletion_methods.items[i])
+    }
+
+    /// How long wiping the drives being set up with `method` takes, once their speed is known. Drives are
+    /// wiped in parallel so the slowest one counts.
+    pub fn wipe_estimate(&self, method: &WipeMethod) -> Option<Duration> {
+        let (disks, scope) = match &self.state {
+            State::SelectMethod { disks, scope } => (disks, *scope),
+            _ => return None,
+        };
+        let estimates: Option<Vec<Duration>> = disks
+            .iter()
+            .map(|disk| {
+                let size = match scope {
+                    WipeScope::FreeSpace => disk.partitions.iter().map(|p| p.free).sum(),
+                    _ => disk.capacity,
+                };
+                estimate_wipe(method, size, self.throughputs.get(&disk.name)?)
+            })
+            .collect();
+        estimates?.into_iter().max()
+    }
+
+    /// Currently highlighted drive, if any
+    pub fn selected_drive(&self) -> Option<&Disk> {
+        self.drives.state.selected().map(|i| &self.drives.items[i])
+    }
+
+    /// Currently highlighted image file, if any
+    pub fn selected_image(&self) -> Option<&PathBuf> {
+        self.images.state.selected().map(|i| &self.images.items[i])
+    }
+
+    /// Currently highlighted part of the drive to wipe, if any
+    pub fn selected_scope(&self) -> Option<WipeScope> {
+        self.scopes.state.selected().map(|i| self.scopes.items[i])
+    }
+
+    /// Drives marked for wiping, the highlighted one when none are marked
+    pub fn marked_disks(&self) -> Vec<Disk> {
+        if self.marked_drives.is_empty() {
+            return self.selected_drive().cloned().into_iter().collect();
+        }
+        self.marked_drives.iter().filter_map(|i| self.drives.items.get(*i)).cloned().collect()
+    }
+
+    //Probe what the chosen drives support and only offer firmware methods all of them will accept
+    fn select_drives(&mut self, disks: &[Disk]) {
+        let mut support: Option<Vec<SanitizeCommand>> = None;
+        for disk in disks.iter() {
+            let commands = supported_commands(disk);
+            support = Some(match support {
+                Some(common) => common.into_iter().filter(|c| commands.contains(c)).collect(),
+                None => commands,
+            });
+        }
+        self.sanitize_support = support.unwrap_or_default();
+        //Measured while the user picks a scope and method, for the estimates on the method screen
+        for disk in disks.iter() {
+            self.throughputs.measure(&disk.name, self.io);
+        }
+        let support = &self.sanitize_support;
+        let methods = self
+            .all_deletion_methods
+            .iter()
+            .filter(|m| !m.is_firmware() || m.sanitize_command(support).is_some())
+            .cloned()
+            .collect();
+        self.deletion_methods = StatefulList::with_items(methods);
+    }
+
+    //Quit the app but only allow that if it not in process of wiping drive
     pub fn quit(&mut self) {
-        if !self.is_deleting {
+        if !self.is_busy() {
             self.should_quit = true;
         }
     }
 }
+
+//Certificates from the ui are unsigned, sign them with the wipe subcommand instead. Returns the line to
+//add to the job's summary.
+fn write_report(directory: Option<&Path>, job: &JobKind, status: &JobStatus) -> Option<String> {
+    let report = ErasureReport::new(job, status, &default_operator())?;
+    let directory = directory?;
+    let line = match SignedReport::new(report, None).and_then(|r| r.write(directory)) {
+        Ok(files) => format!("Certificate of erasure written to {} (also .txt and .html)", files[0].display()),
+        Err(e) => {
+            log::error!("Could not write the certificate of erasure for {}: {}", job.disk().name.display(), e);
+            format!("Could not write certificate of erasure: {}", e)
+        }
+    };
+    Some(line)
+}
+
+//What has to be typed to confirm overwriting a drive, the end of its serial number or the device name
+//for drives which don't report one
+fn confirmation_code(disk: &Disk) -> String {
+    let serial: Vec<char> = disk.serial_number.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    if serial.len() >= CODE_LENGTH {
+        return serial[serial.len() - CODE_LENGTH..].iter().collect();
+    }
+    disk.name.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+//Clones are written next to where the app was started, named after the drive and time
+fn clone_destination(disk: &Disk) -> PathBuf {
+    let name = disk.name.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let directory = std::env::current_dir().unwrap_or_default();
+    directory.join(format!("{}-{}.img", name, timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::test_disk;
+    use crate::wipe::{Pass, Pattern};
+    use std::cell::Cell;
+
+    thread_local! {
+        static LAUNCHED: Cell<usize> = const { Cell::new(0) };
+        static FINISH: Cell<bool> = const { Cell::new(false) };
+    }
+
+    //Stands in for `Job::spawn`, nothing touches a drive
+    fn fake_launcher(kind: JobKind) -> Job {
+        LAUNCHED.with(|l| l.set(l.get() + 1));
+        let finished = FINISH.with(|f| f.get());
+        //Drives named test-fail break, the rest of a session has to carry on regardless
+        let error = if kind.disk().name.ends_with("test-fail") { Some(String::from("broken")) } else { None };
+        Job::with_status(JobStatus { finished, error, ..JobStatus::default() })
+    }
+
+    fn launched() -> usize {
+        LAUNCHED.with(|l| l.get())
+    }
+
+    fn app<'a>() -> App<'a> {
+        LAUNCHED.with(|l| l.set(0));
+        FINISH.with(|f| f.set(false));
+        let methods = vec![
+            WipeMethod::new("Zero fill", "", Vec::new()),
+            WipeMethod::new("Random", "", Vec::new()),
+        ];
+        let mut app = App::new(vec![test_disk("/dev/test-a"), test_disk("/dev/test-b")], methods, "Test");
+        app.images = StatefulList::with_items(vec![PathBuf::from("test.img")]);
+        app.launcher = fake_launcher;
+        app.countdown = Duration::ZERO;
+        app
+    }
+
+    //Feeds keys the way main does: arrows as u/d/l/r, e continue (enter while typing), c back, space
+    //toggle, q quit, t tick and digits typed into the confirmation code
+    fn press(app: &mut App, keys: &str) {
+        for key in keys.chars() {
+            match key {
+                'u' => app.on_up(),
+                'd' => app.on_down(),
+                'l' => app.on_left(),
+                'r' => app.on_right(),
+                'e' => app.on_continue(),
+                'c' => app.on_back(),
+                ' ' => app.on_toggle(),
+                'q' => app.quit(),
+                't' => app.on_tick(),
+                '0'..='9' => app.on_char(key),
+                'v' | 'g' | 'p' | 'n' | '/' => app.on_shortcut(key),
+                _ => panic!("unknown key {}", key),
+            }
+        }
+    }
+
+    //Mode list is Clone, Burn, Destroy
+    const DESTROY: &str = "ddde";
+    const CLONE: &str = "de";
+    const BURN: &str = "dde";
+
+    #[test]
+    fn starts_on_mode_selection() {
+        let app = app();
+        assert!(matches!(app.state, State::SelectMode));
+        assert!(app.jobs.is_empty());
+    }
+
+    #[test]
+    fn continue_without_selection_stays_put() {
+        let mut app = app();
+        press(&mut app, "eeee");
+        assert!(matches!(app.state, State::SelectMode));
+        press(&mut app, DESTROY);
+        press(&mut app, "eeee");
+        assert!(matches!(app.state, State::SelectDrive { mode: Mode::Destroy, .. }));
+    }
+
+    #[test]
+    fn destroy_reaches_confirm_with_built_job() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "dde"); //second drive
+        press(&mut app, "de"); //entire drive
+        press(&mut app, "dde"); //random
+        match &app.state {
+            State::Confirm { jobs } => {
+                let (disk, method, scope) = match jobs.as_slice() {
+                    [JobKind::Wipe { disk, method, scope, .. }] => (disk, method, scope),
+                    jobs => panic!("unexpected jobs {:?}", jobs),
+                };
+                assert_eq!(disk.name, PathBuf::from("/dev/test-b"));
+                assert_eq!(method.name, "Random");
+                assert_eq!(*scope, WipeScope::EntireDrive);
+            }
+            state => panic!("unexpected state {:?}", state),
+        }
+        assert_eq!(launched(), 0);
+    }
+
+    #[test]
+    fn method_estimates_wait_for_every_drive() {
+        let mut app = app();
+        app.drives.items[0].capacity = 1000;
+        app.drives.items[1].capacity = 4000;
+        press(&mut app, DESTROY);
+        press(&mut app, "d d e de"); //both drives, entire drive
+        let method = WipeMethod::new("One pass", "", vec![Pass { pattern: Pattern::Byte(0), verify: false }]);
+        app.throughputs.set(Path::new("/dev/test-a"), 100.0);
+        assert_eq!(app.wipe_estimate(&method), None);
+        app.throughputs.set(Path::new("/dev/test-b"), 100.0);
+        assert_eq!(app.wipe_estimate(&method), Some(Duration::from_secs(40)));
+    }
+
+    #[test]
+    fn confirm_defaults_to_cancel() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "de de de");
+        assert!(matches!(app.state, State::Confirm { .. }));
+        assert_eq!(app.confirmation.index, 0);
+        press(&mut app, "e");
+        assert!(matches!(app.state, State::SelectMethod { .. }));
+        assert_eq!(launched(), 0);
+        assert!(!app.is_busy());
+    }
+
+    #[test]
+    fn only_confirming_starts_a_wipe() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "de de de");
+        press(&mut app, "re");
+        assert!(matches!(app.state, State::Confirm { .. }));
+        press(&mut app, "0001e");
+        assert!(matches!(app.state, State::Countdown { .. }));
+        assert_eq!(launched(), 0);
+        press(&mut app, "t");
+        assert!(matches!(app.state, State::Progress { ref jobs } if matches!(jobs.as_slice(), [JobKind::Wipe { .. }])));
+        assert_eq!(launched(), 1);
+        assert!(app.is_busy());
+    }
+
+    #[test]
+    fn debug_mode_only_simulates() {
+        //Every mode ends up in start_jobs, none of them may reach the real launcher
+        for (mode, keys) in [(CLONE, "de eret"), (BURN, "de de er0001et"), (DESTROY, "de de der0001et")] {
+            let mut app = app();
+            app.debug_mode = true;
+            press(&mut app, mode);
+            press(&mut app, keys);
+            assert!(matches!(app.state, State::Progress { .. }), "{}: {:?}", mode, app.state);
+            assert_eq!(launched(), 0);
+            assert!(app.jobs.iter().all(|job| job.status().simulated));
+        }
+    }
+
+    #[test]
+    fn wrong_code_does_not_start() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "de de de");
+        press(&mut app, "r0002e");
+        assert!(matches!(app.state, State::Confirm { .. }));
+        app.on_backspace();
+        press(&mut app, "1ett");
+        assert!(matches!(app.state, State::Progress { .. }));
+        assert_eq!(launched(), 1);
+    }
+
+    #[test]
+    fn countdown_can_be_cancelled() {
+        let mut app = app();
+        app.countdown = Duration::from_secs(60);
+        press(&mut app, DESTROY);
+        press(&mut app, "de de de r0001e");
+        press(&mut app, "tt");
+        assert!(matches!(app.state, State::Countdown { .. }));
+        press(&mut app, "c");
+        assert!(matches!(app.state, State::Confirm { .. }));
+        //Coming back has to be confirmed from scratch
+        assert_eq!(app.confirmation.index, 0);
+        assert!(app.typed.is_empty());
+        assert_eq!(launched(), 0);
+    }
+
+    #[test]
+    fn code_falls_back_to_device_name() {
+        let mut nameless = test_disk("/dev/test-a");
+        nameless.serial_number = String::from(" 1 ");
+        assert_eq!(confirmation_code(&nameless), "test-a");
+        nameless.serial_number = String::from("WD-WX21A9");
+        assert_eq!(confirmation_code(&nameless), "21A9");
+    }
+
+    #[test]
+    fn clone_needs_no_code() {
+        let mut app = app();
+        press(&mut app, CLONE);
+        press(&mut app, "de e");
+        assert!(matches!(app.state, State::Confirm { .. }));
+        assert!(!app.is_typing());
+        press(&mut app, "re");
+        assert!(matches!(app.state, State::Progress { ref jobs } if matches!(jobs.as_slice(), [JobKind::Clone { .. }])));
+        assert_eq!(launched(), 1);
+    }
+
+    #[test]
+    fn marked_drives_are_wiped_together() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "d d e"); //mark both drives
+        match &app.state {
+            State::SelectScope { disks } => assert_eq!(disks.len(), 2),
+            state => panic!("unexpected state {:?}", state),
+        }
+        press(&mut app, "de de");
+        assert_eq!(app.confirmation_code().as_deref(), Some("0001 0001"));
+        //One drive's code or the number of drives isn't enough
+        press(&mut app, "r0001e");
+        assert!(matches!(app.state, State::Confirm { .. }));
+        app.typed.clear();
+        press(&mut app, "2e");
+        assert!(matches!(app.state, State::Confirm { .. }));
+        app.typed.clear();
+        " 0001  0001".chars().for_each(|c| app.on_char(c));
+        press(&mut app, "et");
+        assert!(matches!(app.state, State::Progress { ref jobs } if jobs.len() == 2));
+        assert_eq!(launched(), 2);
+        assert_eq!(app.jobs.len(), 2);
+    }
+
+    #[test]
+    fn unmarking_falls_back_to_the_highlighted_drive() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "d  de");
+        match &app.state {
+            State::SelectScope { disks } => assert_eq!(disks[0].name, PathBuf::from("/dev/test-b")),
+            state => panic!("unexpected state {:?}", state),
+        }
+    }
+
+    #[test]
+    fn failed_drive_does_not_stop_the_session() {
+        let mut app = app();
+        app.drives.items.push(test_disk("/dev/test-fail"));
+        FINISH.with(|f| f.set(true));
+        press(&mut app, DESTROY);
+        press(&mut app, "d d d e de de r");
+        "0001 0001 0001".chars().for_each(|c| app.on_char(c));
+        press(&mut app, "et");
+        assert_eq!(launched(), 3);
+        press(&mut app, "t");
+        assert!(matches!(app.state, State::Result { ref jobs } if jobs.len() == 3));
+        assert_eq!(app.job_statuses.iter().filter(|s| s.error.is_some()).count(), 1);
+        assert_eq!(app.session_summary[0], "2 of 3 drives finished successfully");
+        assert_eq!(app.session_summary[1], "Failed: /dev/test-fail");
+        //Starting over forgets the marks
+        press(&mut app, "e");
+        assert!(app.marked_drives.is_empty());
+    }
+
+    #[test]
+    fn discard_is_toggled_per_method() {
+        let mut entire = app();
+        press(&mut entire, DESTROY);
+        press(&mut entire, "de de d d  ");
+        let discards: Vec<bool> = entire.deletion_methods.items.iter().map(|m| m.discard).collect();
+        assert_eq!(discards, vec![true, false]);
+        press(&mut entire, "ue");
+        match &entire.state {
+            State::Confirm { jobs } => assert!(matches!(&jobs[0], JobKind::Wipe { method, .. } if method.discard)),
+            state => panic!("{:?}", state),
+        }
+
+        //Free space wipes never discard, the files still live on the drive
+        let mut free_space = app();
+        press(&mut free_space, DESTROY);
+        press(&mut free_space, "de dde d ");
+        assert!(free_space.deletion_methods.items.iter().all(|m| !m.discard));
+    }
+
+    #[test]
+    fn moving_back_to_cancel_does_not_start() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "de de de");
+        press(&mut app, "rle");
+        assert_eq!(launched(), 0);
+        assert!(matches!(app.state, State::SelectMethod { .. }));
+    }
+
+    #[test]
+    fn reentering_confirm_resets_to_cancel() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "de de de");
+        press(&mut app, "r");
+        press(&mut app, "c"); //back to methods with delete highlighted
+        press(&mut app, "e");
+        assert!(matches!(app.state, State::Confirm { .. }));
+        assert_eq!(app.confirmation.index, 0);
+        press(&mut app, "e");
+        assert_eq!(launched(), 0);
+    }
+
+    #[test]
+    fn hammering_continue_never_starts_a_wipe() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "de de de");
+        press(&mut app, &"e".repeat(20));
+        press(&mut app, &"dede".repeat(10));
+        assert_eq!(launched(), 0);
+    }
+
+    #[test]
+    fn left_and_right_only_act_on_confirm() {
+        let mut app = app();
+        press(&mut app, "rrr");
+        press(&mut app, DESTROY);
+        press(&mut app, "rrde rrde rrde");
+        assert!(matches!(app.state, State::Confirm { .. }));
+        assert_eq!(app.confirmation.index, 0);
+    }
+
+    #[test]
+    fn slack_skips_method_selection_both_ways() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "de");
+        press(&mut app, "ddde"); //third scope is slack
+        assert!(matches!(
+            app.state,
+            State::Confirm { ref jobs } if matches!(jobs.as_slice(), [JobKind::Wipe { scope: WipeScope::Slack, .. }])
+        ));
+        press(&mut app, "c");
+        assert!(matches!(app.state, State::SelectScope { .. }));
+    }
+
+    #[test]
+    fn back_walks_all_the_way_to_mode_selection() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "de de de");
+        press(&mut app, "c");
+        assert!(matches!(app.state, State::SelectMethod { .. }));
+        press(&mut app, "c");
+        assert!(matches!(app.state, State::SelectScope { .. }));
+        press(&mut app, "c");
+        assert!(matches!(app.state, State::SelectDrive { mode: Mode::Destroy, .. }));
+        press(&mut app, "c");
+        assert!(matches!(app.state, State::SelectMode));
+        press(&mut app, "c");
+        assert!(matches!(app.state, State::SelectMode));
+    }
+
+    #[test]
+    fn running_job_blocks_back_and_quit() {
+        let mut app = app();
+        press(&mut app, DESTROY);
+        press(&mut app, "de de de r0001et");
+        press(&mut app, "ccqeudlr ");
+        assert!(matches!(app.state, State::Progress { .. }));
+        assert!(!app.should_quit);
+        press(&mut app, "t");
+        assert!(matches!(app.state, State::Progress { .. }));
+    }
+
+    #[test]
+    fn finished_job_shows_result_then_starts_over() {
+        let mut app = app();
+        FINISH.with(|f| f.set(true));
+        press(&mut app, DESTROY);
+        press(&mut app, "de de de r0001et");
+        press(&mut app, "t");
+        assert!(matches!(app.state, State::Result { .. }));
+        assert!(app.jobs.is_empty());
+        press(&mut app, "e");
+        assert!(matches!(app.state, State::SelectMode));
+        //The previous drive must be picked again explicitly
+        assert!(app.selected_drive().is_none());
+        press(&mut app, "q");
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn clone_options_toggle_verify() {
+        let mut app = app();
+        press(&mut app, CLONE);
+        press(&mut app, "de");
+        assert!(matches!(app.state, State::Options { job: JobKind::Clone { verify: true, .. } }));
+        press(&mut app, " ");
+        assert!(matches!(app.state, State::Options { job: JobKind::Clone { verify: false, .. } }));
+        press(&mut app, "e");
+        assert!(matches!(app.state, State::Confirm { ref jobs } if matches!(jobs.as_slice(), [JobKind::Clone { verify: false, .. }])));
+        press(&mut app, "c");
+        assert!(matches!(app.state, State::Options { job: JobKind::Clone { verify: false, .. } }));
+        press(&mut app, "c");
+        assert!(matches!(app.state, State::SelectDrive { mode: Mode::Clone, .. }));
+    }
+
+    #[test]
+    fn burn_picks_image_before_drive() {
+        let mut app = app();
+        press(&mut app, BURN);
+        assert!(matches!(app.state, State::SelectImage));
+        press(&mut app, "e");
+        assert!(matches!(app.state, State::SelectImage));
+        press(&mut app, "de de e");
+        match &app.state {
+            State::Confirm { jobs } => {
+                let (image, disk) = match jobs.as_slice() {
+                    [JobKind::Burn { image, disk, .. }] => (image, disk),
+                    jobs => panic!("unexpected jobs {:?}", jobs),
+                };
+                assert_eq!(image, &PathBuf::from("test.img"));
+                assert_eq!(disk.name, PathBuf::from("/dev/test-a"));
+            }
+            state => panic!("unexpected state {:?}", state),
+        }
+        press(&mut app, "cc");
+        assert!(matches!(app.state, State::SelectDrive { mode: Mode::Burn, image: Some(_) }));
+        press(&mut app, "c");
+        assert!(matches!(app.state, State::SelectImage));
+    }
+
+    #[test]
+    fn invalid_transitions_are_refused() {
+        let mut app = app();
+        let job = JobKind::Wipe {
+            disk: test_disk("/dev/test-a"),
+            method: WipeMethod::new("Zero fill", "", Vec::new()),
+            scope: WipeScope::EntireDrive,
+            sanitize: Vec::new(),
+            io: IoOptions::default(),
+        };
+        assert!(!app.transition(State::Progress { jobs: vec![job.clone()] }));
+        assert!(!app.transition(State::Result { jobs: vec![job.clone()] }));
+        assert!(!app.transition(State::Confirm { jobs: vec![job.clone()] }));
+        assert!(matches!(app.state, State::SelectMode));
+        app.start_jobs(vec![job]);
+        assert_eq!(launched(), 0);
+    }
+
+    #[test]
+    fn hex_viewer_opens_and_goes_back() {
+        let path = std::env::temp_dir().join(format!("minuteman-view-{}", std::process::id()));
+        std::fs::write(&path, vec![1; 4096]).unwrap();
+        let mut app = app();
+        app.drives.items[0].name = path.clone();
+        press(&mut app, CLONE);
+        press(&mut app, "dv");
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(&app.state, State::HexView { back } if matches!(**back, State::SelectDrive { mode: Mode::Clone, .. })));
+        //Digits go into the prompt while it's open
+        press(&mut app, "g");
+        assert!(app.is_typing());
+        press(&mut app, "32e");
+        assert!(!app.is_typing());
+        assert!(matches!(&app.viewer, Some(Ok(viewer)) if viewer.offset == 32));
+        press(&mut app, "c");
+        assert!(matches!(app.state, State::SelectDrive { mode: Mode::Clone, .. }));
+        assert!(app.viewer.is_none());
+        assert_eq!(app.drives.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn log_pane_takes_the_page_keys() {
+        let mut app = app();
+        app.on_shortcut('l');
+        assert!(app.show_log);
+        app.on_page_up();
+        app.on_page_up();
+        app.on_page_down();
+        assert_eq!(app.log_scroll, LOG_PAGE);
+        //Hiding and showing it again starts at the latest records
+        app.on_shortcut('l');
+        app.on_shortcut('l');
+        assert_eq!(app.log_scroll, 0);
+    }
+}
@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod app;
-pub use app::App;
\ No newline at end of file
+pub use app::{App, Mode, State};
\ No newline at end of file
@@ -0,0 +1,242 @@
+//Drive throughput benchmark. Reads are safe on any drive, the write benchmark overwrites whatever is
+//at the offsets it picks so it's only run on drives the user confirmed. Everything goes through the same
+//direct I/O as jobs do, otherwise the page cache would be measured instead of the drive.
+
+use anyhow::{anyhow, Result};
+use rand::Rng;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::clone::device_size;
+use crate::disk::direct::{ring_drive, AlignedBuffer, IoOptions};
+use crate::disk::DriveAccessor;
+use crate::wipe::engine::{run_source, PatternSource};
+use crate::wipe::{Pass, Pattern, WipeMethod};
+
+pub const BLOCK_SIZES: [usize; 4] = [4 << 10, 64 << 10, 1 << 20, 4 << 20];
+
+//Every measurement stops after whichever limit comes first
+const MEASURE_BYTES: u64 = 256 << 20;
+const MEASURE_TIME: Duration = Duration::from_millis(750);
+//Comparing the wipe backends overwrites this much of the start of the target
+const COMPARE_BYTES: u64 = 512 << 20;
+
+/// Throughput at one block size in bytes per second, writes only with the destructive benchmark.
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchResult {
+    pub block_size: usize,
+    pub sequential_read: f64,
+    pub random_read: f64,
+    pub sequential_write: Option<f64>,
+    pub random_write: Option<f64>,
+}
+
+/// How fast a wipe pass writes through one I/O backend, in bytes per second.
+#[derive(Clone, Debug, Serialize)]
+pub struct BackendResult {
+    pub backend: &'static str,
+    pub queue_depth: u32,
+    pub write: f64,
+}
+
+///Bytes per second moving `block` sized blocks through `op` at sequential or random block aligned offsets
+fn measure<F>(size: u64, block: usize, random: bool, mut op: F) -> Result<f64>
+where
+    F: FnMut(u64, &mut [u8]) -> Result<usize>,
+{
+    let blocks = size / block as u64;
+    if blocks == 0 {
+        return Err(anyhow!("drive is smaller than a {} byte block", block));
+    }
+    let mut buffer = AlignedBuffer::new(block);
+    let mut rng = rand::thread_rng();
+    let started = Instant::now();
+    let mut moved = 0;
+    let mut index = 0;
+    while moved < MEASURE_BYTES && started.elapsed() < MEASURE_TIME {
+        let offset = if random { rng.gen_range(0..blocks) } else { index % blocks } * block as u64;
+        moved += op(offset, &mut buffer)? as u64;
+        index += 1;
+    }
+    Ok(moved as f64 / started.elapsed().as_secs_f64())
+}
+
+fn read_at(file: &File, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+    Ok(file.read_at(buffer, offset)?)
+}
+
+//Writes are synced as they go so the drive's cache doesn't make them look faster than they are
+fn write_at(file: &File, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+    file.write_all_at(buffer, offset)?;
+    file.sync_data()?;
+    Ok(buffer.len())
+}
+
+///Sequential read speed of `path` in bytes per second at the configured block size
+pub fn sequential_read(path: &Path, io: &IoOptions) -> Result<f64> {
+    let file = io.open(path, false)?;
+    let size = device_size(path)?;
+    measure(size, io.block_size_for(path).min(size as usize), false, |offset, buffer| read_at(&file, offset, buffer))
+}
+
+///Measures `path` at every block size in `BLOCK_SIZES` the drive is large enough for, overwriting parts
+/// of it when `write` is set. `on_progress` gets a description of every measurement as it starts.
+pub fn run_benchmark<F>(path: &Path, io: &IoOptions, write: bool, mut on_progress: F) -> Result<Vec<BenchResult>>
+where
+    F: FnMut(&str, f64),
+{
+    let size = device_size(path)?;
+    let file = io.open(path, write)?;
+    let sizes: Vec<usize> = BLOCK_SIZES.iter().copied().filter(|b| *b as u64 <= size).collect();
+    let mut results = Vec::new();
+    for (i, block) in sizes.iter().copied().enumerate() {
+        let progress = i as f64 / sizes.len() as f64;
+        on_progress(&format!("Reading {} KiB blocks", block >> 10), progress);
+        let mut result = BenchResult {
+            block_size: block,
+            sequential_read: measure(size, block, false, |offset, buffer| read_at(&file, offset, buffer))?,
+            random_read: measure(size, block, true, |offset, buffer| read_at(&file, offset, buffer))?,
+            sequential_write: None,
+            random_write: None,
+        };
+        if write {
+            on_progress(&format!("Writing {} KiB blocks", block >> 10), progress);
+            result.sequential_write = Some(measure(size, block, false, |offset, buffer| write_at(&file, offset, buffer))?);
+            result.random_write = Some(measure(size, block, true, |offset, buffer| write_at(&file, offset, buffer))?);
+        }
+        results.push(result);
+    }
+    on_progress("Done", 1.0);
+    Ok(results)
+}
+
+fn time_pass(drive: &mut dyn DriveAccessor, size: u64, block: usize) -> Result<f64> {
+    let source = PatternSource::new(&[Pass::new(Pattern::Byte(0))]);
+    let started = Instant::now();
+    run_source(drive, size, &source, block, |_| {})?;
+    Ok(size as f64 / started.elapsed().as_secs_f64())
+}
+
+///Zeroes the start of `path` with plain writes, then through io_uring at the configured queue depth when
+/// it's built in and the kernel supports it. Works on image files as well as drives.
+pub fn compare_backends<F>(path: &Path, io: &IoOptions, mut on_progress: F) -> Result<Vec<BackendResult>>
+where
+    F: FnMut(&str, f64),
+{
+    let size = device_size(path)?.min(COMPARE_BYTES);
+    let block = io.block_size_for(path);
+    if size < block as u64 {
+        return Err(anyhow!("{} is smaller than a {} byte block", path.display(), block));
+    }
+    let size = size - size % block as u64;
+    let mut results = Vec::new();
+    on_progress("Writing with plain writes", 0.0);
+    let mut file = io.open(path, true)?;
+    results.push(BackendResult { backend: "synchronous", queue_depth: 0, write: time_pass(&mut file, size, block)? });
+    drop(file);
+    if io.queue_depth > 0 {
+        on_progress("Writing through io_uring", 0.5);
+        if let Some(mut drive) = ring_drive(&io.open(path, true)?, io.queue_depth) {
+            results.push(BackendResult { backend: "io_uring", queue_depth: io.queue_depth, write: time_pass(&mut *drive, size, block)? });
+        }
+    }
+    on_progress("Done", 1.0);
+    Ok(results)
+}
+
+///How long overwriting `size` bytes with `method` takes at `throughput` bytes per second, verify passes
+/// read everything back once more. Firmware erases run on the drive and can't be estimated.
+pub fn estimate_wipe(method: &WipeMethod, size: u64, throughput: f64) -> Option<Duration> {
+    if method.is_firmware() || throughput <= 0.0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(method.rounds() as f64 * size as f64 / throughput))
+}
+
+/// Read speeds of drives measured in the background, shared with the thread doing the measuring.
+#[derive(Clone, Default)]
+pub struct Throughputs {
+    measured: Arc<Mutex<HashMap<PathBuf, Option<f64>>>>, //None while measuring or when it failed
+}
+
+impl Throughputs {
+    ///Starts measuring `path` unless it was already measured
+    pub fn measure(&self, path: &Path, io: IoOptions) {
+        let mut measured = self.measured.lock().unwrap();
+        if measured.contains_key(path) {
+            return;
+        }
+        measured.insert(path.to_path_buf(), None);
+        let shared = self.measured.clone();
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            match sequential_read(&path, &io) {
+                Ok(throughput) => {
+                    log::info!("{} reads at {:.0} MB/s", path.display(), throughput / 1e6);
+                    shared.lock().unwrap().insert(path, Some(throughput));
+                }
+                Err(e) => log::warn!("Could not measure how fast {} reads: {}", path.display(), e),
+            }
+        });
+    }
+
+    ///Bytes per second, once measured
+    pub fn get(&self, path: &Path) -> Option<f64> {
+        self.measured.lock().unwrap().get(path).copied().flatten()
+    }
+
+    #[cfg(test)]
+    pub fn set(&self, path: &Path, throughput: f64) {
+        self.measured.lock().unwrap().insert(path.to_path_buf(), Some(throughput));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wipe::{Pass, Pattern};
+
+    #[test]
+    fn estimates_count_verify_passes() {
+        let method = WipeMethod::new(
+            "Two passes",
+            "",
+            vec![Pass { pattern: Pattern::Byte(0), verify: false }, Pass { pattern: Pattern::Random, verify: true }],
+        );
+        assert_eq!(estimate_wipe(&method, 1000, 100.0), Some(Duration::from_secs(30)));
+        assert_eq!(estimate_wipe(&method, 1000, 0.0), None);
+    }
+
+    #[test]
+    fn benchmarks_files() {
+        let path = std::env::temp_dir().join(format!("minuteman-bench-{}", std::process::id()));
+        std::fs::write(&path, vec![7; 256 << 10]).unwrap();
+        let results = run_benchmark(&path, &IoOptions::default(), true, |_, _| {}).unwrap();
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        //Only the block sizes that fit
+        assert_eq!(results.iter().map(|r| r.block_size).collect::<Vec<_>>(), vec![4 << 10, 64 << 10]);
+        assert!(results.iter().all(|r| r.sequential_read > 0.0 && r.random_write.is_some()));
+        assert_eq!(contents.len(), 256 << 10);
+    }
+
+    #[test]
+    fn compares_backends_on_files() {
+        let path = std::env::temp_dir().join(format!("minuteman-backends-{}", std::process::id()));
+        std::fs::write(&path, vec![7; 1 << 20]).unwrap();
+        let results = compare_backends(&path, &IoOptions::default(), |_, _| {}).unwrap();
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(results[0].backend, "synchronous");
+        //io_uring only shows up in uring builds on kernels that have it
+        assert!(results.len() <= 2);
+        assert!(results.iter().all(|r| r.write > 0.0));
+        assert!(contents.iter().all(|b| *b == 0));
+    }
+}
@@ -0,0 +1,760 @@
+//Headless subcommands so minuteman can be driven from shell scripts and provisioning pipelines. They
+//run the same jobs as the ui, progress goes to stderr and results to stdout. With --json stdout is a
+//stream of newline delimited JSON events instead, progress events followed by a single result event.
+
+use anyhow::{anyhow, Result};
+use argh::FromArgs;
+use serde::Serialize;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bench::{compare_backends, run_benchmark, BackendResult, BenchResult};
+use crate::clone::{device_size, verify_copy};
+use crate::disk::direct::IoOptions;
+use crate::disk::{find_external_disks, Disk};
+use crate::job::{Job, JobKind, JobReport, JobStatus, WipeScope};
+use crate::report::{
+    default_operator, generate_key, load_public_key, load_signing_key, ErasureReport, SignedReport,
+};
+use crate::sanitize::supported_commands;
+use crate::shred::{filesystem_warning, shred_path};
+use crate::wipe::engine::WipeProgress;
+use crate::wipe::WipeMethod;
+
+//How often a running job is polled for progress
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+    List(ListCommand),
+    Wipe(WipeCommand),
+    Clone(CloneCommand),
+    Burn(BurnCommand),
+    Verify(VerifyCommand),
+    Bench(BenchCommand),
+    Shred(ShredCommand),
+    Keygen(KeygenCommand),
+    CheckReport(CheckReportCommand),
+}
+
+/// list the external drives minuteman can work on
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "list")]
+pub struct ListCommand {
+    /// print newline delimited JSON instead of text
+    #[argh(switch)]
+    json: bool,
+}
+
+/// wipe a drive, its free space or leftover metadata
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "wipe")]
+pub struct WipeCommand {
+    /// drive to wipe, e.g. /dev/sdb
+    #[argh(option)]
+    device: PathBuf,
+    /// name of the wipe method, see the method list in the ui or the methods file
+    #[argh(option, default = "String::from(\"Zero fill\")")]
+    method: String,
+    /// what to wipe: entire (default), free-space or slack
+    #[argh(option, default = "WipeScope::EntireDrive", from_str_fn(parse_scope))]
+    scope: WipeScope,
+    /// directory to write a certificate of erasure to (json, text and html)
+    #[argh(option)]
+    report: Option<PathBuf>,
+    /// key file to sign the certificate with (Ed25519), see `minuteman keygen`
+    #[argh(option)]
+    sign_key: Option<PathBuf>,
+    /// operator named on the certificate, defaults to the logged in user
+    #[argh(option)]
+    operator: Option<String>,
+    /// print newline delimited JSON instead of text
+    #[argh(switch)]
+    json: bool,
+    /// don't ask for confirmation
+    #[argh(switch)]
+    yes: bool,
+}
+
+/// clone a drive into an image file
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "clone")]
+pub struct CloneCommand {
+    /// drive to clone, e.g. /dev/sdb
+    #[argh(option)]
+    device: PathBuf,
+    /// image file to write
+    #[argh(option)]
+    image: PathBuf,
+    /// skip reading the image back after cloning
+    #[argh(switch)]
+    no_verify: bool,
+    /// print newline delimited JSON instead of text
+    #[argh(switch)]
+    json: bool,
+    /// overwrite the image file if it exists without asking
+    #[argh(switch)]
+    yes: bool,
+}
+
+/// burn an image file onto a drive
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "burn")]
+pub struct BurnCommand {
+    /// image file to burn
+    #[argh(option)]
+    image: PathBuf,
+    /// drive to overwrite, e.g. /dev/sdb
+    #[argh(option)]
+    device: PathBuf,
+    /// skip reading the drive back after burning
+    #[argh(switch)]
+    no_verify: bool,
+    /// print newline delimited JSON instead of text
+    #[argh(switch)]
+    json: bool,
+    /// don't ask for confirmation
+    #[argh(switch)]
+    yes: bool,
+}
+
+/// compare an image file with the start of a drive
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "verify")]
+pub struct VerifyCommand {
+    /// image file to compare
+    #[argh(option)]
+    image: PathBuf,
+    /// drive to compare against, e.g. /dev/sdb
+    #[argh(option)]
+    device: PathBuf,
+    /// print newline delimited JSON instead of text
+    #[argh(switch)]
+    json: bool,
+}
+
+/// measure how fast a drive reads, and writes with --write or --compare-backends
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "bench")]
+pub struct BenchCommand {
+    /// drive to measure, e.g. /dev/sdb
+    #[argh(option)]
+    device: PathBuf,
+    /// also measure writes, this overwrites data all over the drive
+    #[argh(switch)]
+    write: bool,
+    /// time a zero fill with plain writes against io_uring, this overwrites the start of the target,
+    /// which may also be an image file
+    #[argh(switch)]
+    compare_backends: bool,
+    /// print newline delimited JSON instead of text
+    #[argh(switch)]
+    json: bool,
+    /// don't ask for confirmation before the write benchmark
+    #[argh(switch)]
+    yes: bool,
+}
+
+/// overwrite, rename and delete files or directories
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "shred")]
+pub struct ShredCommand {
+    /// files or directories to shred
+    #[argh(positional)]
+    paths: Vec<PathBuf>,
+    /// name of the wipe method
+    #[argh(option, default = "String::from(\"Zero fill\")")]
+    method: String,
+    /// shred directories and everything below them
+    #[argh(switch, short = 'r')]
+    recursive: bool,
+    /// print newline delimited JSON instead of text
+    #[argh(switch)]
+    json: bool,
+    /// don't ask for confirmation
+    #[argh(switch)]
+    yes: bool,
+}
+
+/// create an Ed25519 key pair for signing certificates of erasure
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "keygen")]
+pub struct KeygenCommand {
+    /// file to write the secret key to, the public key is written next to it with a .pub extension
+    #[argh(option)]
+    out: PathBuf,
+}
+
+/// check the signature of a certificate of erasure
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "check-report")]
+pub struct CheckReportCommand {
+    /// json certificate to check
+    #[argh(positional)]
+    report: PathBuf,
+    /// public key file the certificate has to be signed with
+    #[argh(option)]
+    public_key: Option<PathBuf>,
+}
+
+fn parse_scope(value: &str) -> Result<WipeScope, String> {
+    WipeScope::from_key(value).ok_or_else(|| String::from("expected entire, free-space or slack"))
+}
+
+///Runs a subcommand to completion, errors and failed verifies are returned as errors. Dry runs go
+/// through the same steps without writing to any device or file and don't ask for confirmation.
+pub fn run(command: Command, methods: Vec<WipeMethod>, dry_run: bool, io: IoOptions) -> Result<()> {
+    match command {
+        Command::List(args) => list(args.json),
+        Command::Wipe(args) => {
+            let disk = find_disk(&args.device)?;
+            let method = find_method(&methods, &args.method)?;
+            if args.scope == WipeScope::EntireDrive {
+                confirm(args.yes || dry_run, &format!("Erase everything on {} with {}?", disk.name.display(), method.name))?;
+            } else {
+                confirm(args.yes || dry_run, &format!("Wipe the {} of {}?", args.scope.name().to_lowercase(), disk.name.display()))?;
+            }
+            //Load the key up front, a typo shouldn't only show up after hours of wiping
+            let key = args.sign_key.as_deref().map(load_signing_key).transpose()?;
+            let sanitize = supported_commands(&disk);
+            let kind = JobKind::Wipe { disk, method, scope: args.scope, sanitize, io };
+            let status = run_job(&kind, args.json, dry_run)?;
+            if let Some(directory) = args.report {
+                let operator = args.operator.unwrap_or_else(default_operator);
+                if let Some(report) = ErasureReport::new(&kind, &status, &operator) {
+                    let files = SignedReport::new(report, key.as_ref())?.write(&directory)?;
+                    if args.json {
+                        emit("report", ReportFiles { files: &files, signed: key.is_some() })?;
+                    } else {
+                        for file in files.iter() {
+                            eprintln!("Certificate written to {}", file.display());
+                        }
+                    }
+                }
+            }
+            finish(&kind, &status, args.json)
+        }
+        Command::Clone(args) => {
+            let disk = find_disk(&args.device)?;
+            if args.image.exists() {
+                confirm(args.yes || dry_run, &format!("Overwrite {}?", args.image.display()))?;
+            }
+            let kind = JobKind::Clone { disk, destination: args.image, verify: !args.no_verify, io };
+            let status = run_job(&kind, args.json, dry_run)?;
+            finish(&kind, &status, args.json)
+        }
+        Command::Burn(args) => {
+            let disk = find_disk(&args.device)?;
+            if !args.image.is_file() {
+                return Err(anyhow!("{} is not an image file", args.image.display()));
+            }
+            confirm(
+                args.yes || dry_run,
+                &format!("Overwrite everything on {} with {}?", disk.name.display(), args.image.display()),
+            )?;
+            let kind = JobKind::Burn { image: args.image, disk, verify: !args.no_verify, io };
+            let status = run_job(&kind, args.json, dry_run)?;
+            finish(&kind, &status, args.json)
+        }
+        Command::Verify(args) => verify(&args.image, &args.device, args.json, &io),
+        Command::Bench(args) => bench(args, dry_run, &io),
+        Command::Shred(args) => shred(args, &methods, dry_run),
+        Command::Keygen(args) => {
+            let public = generate_key(&args.out)?;
+            println!("Secret key written to {}, public key to {}", args.out.display(), public.display());
+            Ok(())
+        }
+        Command::CheckReport(args) => {
+            let trusted = args.public_key.as_deref().map(load_public_key).transpose()?;
+            SignedReport::load(&args.report)?.check(trusted.as_ref())?;
+            if trusted.is_some() {
+                println!("Signature is valid and made with the given key");
+            } else {
+                println!("Signature is valid, compare the public key with the one you trust");
+            }
+            Ok(())
+        }
+    }
+}
+
+fn list(json: bool) -> Result<()> {
+    let disks = find_external_disks()?;
+    //One event per drive, like every other --json output
+    if json {
+        for disk in disks.iter() {
+            emit("disk", disk)?;
+        }
+        return Ok(());
+    }
+    for disk in disks {
+        println!(
+            "{}\t{:?}\t{}\t{}\t{} bytes",
+            disk.name.display(),
+            disk.disk_type,
+            disk.model,
+            disk.serial_number,
+            device_size(&disk.name).unwrap_or(disk.total_space)
+        );
+        for partition in disk.partitions.iter() {
+            println!(
+                "  {}\t{}\t{}\t{} of {} bytes free",
+                partition.name,
+                partition.file_system,
+                partition.mount_point.display(),
+                partition.free,
+                partition.total
+            );
+        }
+    }
+    Ok(())
+}
+
+//Only drives the ui would offer can be worked on, anything else is most likely a typo
+fn find_disk(device: &Path) -> Result<Disk> {
+    let device = device.canonicalize().unwrap_or_else(|_| device.to_path_buf());
+    find_external_disks()?
+        .into_iter()
+        .find(|d| d.name == device)
+        .ok_or_else(|| anyhow!("{} is not an external drive, see `minuteman list`", device.display()))
+}
+
+//Method names are long, a unique case insensitive prefix like "zero fill" or "gutmann" is enough
+fn find_method(methods: &[WipeMethod], name: &str) -> Result<WipeMethod> {
+    let name = name.to_lowercase();
+    if let Some(method) = methods.iter().find(|m| m.name.to_lowercase() == name) {
+        return Ok(method.clone());
+    }
+    let matches: Vec<&WipeMethod> = methods.iter().filter(|m| m.name.to_lowercase().starts_with(&name)).collect();
+    match matches.as_slice() {
+        [method] => Ok((*method).clone()),
+        [] => {
+            let names: Vec<&str> = methods.iter().map(|m| m.name.as_str()).collect();
+            Err(anyhow!("unknown wipe method \"{}\", expected one of:\n  {}", name, names.join("\n  ")))
+        }
+        _ => {
+            let names: Vec<&str> = matches.iter().map(|m| m.name.as_str()).collect();
+            Err(anyhow!("\"{}\" matches several wipe methods:\n  {}", name, names.join("\n  ")))
+        }
+    }
+}
+
+//Destructive commands need --yes or a typed "yes", anything else aborts
+fn confirm(yes: bool, question: &str) -> Result<()> {
+    if yes {
+        return Ok(());
+    }
+    eprint!("{} This cannot be undone, type yes to continue: ", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if answer.trim() == "yes" {
+        Ok(())
+    } else {
+        Err(anyhow!("aborted"))
+    }
+}
+
+//Single line JSON event on stdout
+#[derive(Serialize)]
+struct Event<T: Serialize> {
+    event: &'static str,
+    #[serde(flatten)]
+    data: T,
+}
+
+fn emit<T: Serialize>(event: &'static str, data: T) -> Result<()> {
+    println!("{}", serde_json::to_string(&Event { event, data })?);
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ProgressEvent<'a> {
+    stage: &'a str,
+    progress: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pass: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_passes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pattern: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verifying: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes_done: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes_total: Option<u64>,
+}
+
+//Reports every stage change and whole percent, as text on stderr or as progress events
+struct Reporter {
+    json: bool,
+    last: (String, i64),
+}
+
+impl Reporter {
+    fn new(json: bool) -> Reporter {
+        Reporter { json, last: (String::new(), -1) }
+    }
+
+    fn progress(&mut self, stage: &str, progress: f64, wipe: Option<&WipeProgress>) -> Result<()> {
+        let percent = (progress * 100.0) as i64;
+        if stage.is_empty() || (stage == self.last.0 && percent == self.last.1) {
+            return Ok(());
+        }
+        self.last = (stage.to_string(), percent);
+        if !self.json {
+            eprintln!("{}: {}%", stage, percent);
+            return Ok(());
+        }
+        emit(
+            "progress",
+            ProgressEvent {
+                stage,
+                progress,
+                pass: wipe.map(|w| w.pass),
+                total_passes: wipe.map(|w| w.total_passes),
+                pattern: wipe.map(|w| w.pattern.to_string()),
+                verifying: wipe.map(|w| w.verifying),
+                bytes_done: wipe.map(|w| w.bytes_done),
+                bytes_total: wipe.map(|w| w.bytes_total),
+            },
+        )
+    }
+}
+
+//Runs a job on its thread and reports its progress until it is done
+fn run_job(kind: &JobKind, json: bool, dry_run: bool) -> Result<JobStatus> {
+    let job = if dry_run { Job::simulate(kind.clone()) } else { Job::spawn(kind.clone()) };
+    let mut reporter = Reporter::new(json);
+    let status = loop {
+        let status = job.status();
+        reporter.progress(&status.stage, status.progress, status.wipe.as_ref())?;
+        if status.finished {
+            break status;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    Ok(status)
+}
+
+#[derive(Serialize)]
+struct ReportFiles<'a> {
+    files: &'a [PathBuf],
+    signed: bool,
+}
+
+//Prints the outcome of a job, failed jobs and verifies are turned into errors
+fn finish(kind: &JobKind, status: &JobStatus, json: bool) -> Result<()> {
+    let report = JobReport::new(kind, status);
+    if json {
+        emit("result", &report)?;
+    } else {
+        for line in report.summary.iter() {
+            println!("{}", line);
+        }
+    }
+    if let Some(error) = report.error {
+        return Err(anyhow!(error));
+    }
+    if report.verify_failures > 0 {
+        return Err(anyhow!("{} blocks did not verify", report.verify_failures));
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct VerifyReport<'a> {
+    kind: &'static str,
+    image: &'a Path,
+    device: &'a Path,
+    bytes: u64,
+    duration_secs: f64,
+    mismatches: &'a [u64], //Offsets of the chunks which differ
+    success: bool,
+}
+
+fn verify(image: &Path, device: &Path, json: bool, io: &IoOptions) -> Result<()> {
+    let started = Instant::now();
+    let size = device_size(image)?;
+    let mut reporter = Reporter::new(json);
+    let mut reported = Ok(());
+    let failures = verify_copy(image, device, size, io, |done| {
+        if reported.is_ok() {
+            reported = reporter.progress("Verifying", done as f64 / size.max(1) as f64, None);
+        }
+    })?;
+    reported?;
+    if json {
+        emit(
+            "result",
+            VerifyReport {
+                kind: "verify",
+                image,
+                device,
+                bytes: size,
+                duration_secs: started.elapsed().as_secs_f64(),
+                mismatches: &failures,
+                success: failures.is_empty(),
+            },
+        )?;
+    } else {
+        for offset in failures.iter() {
+            println!("Mismatch at offset {}", offset);
+        }
+        if failures.is_empty() {
+            println!("{} matches the first {} bytes of {}", image.display(), size, device.display());
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("{} blocks differ", failures.len()))
+    }
+}
+
+#[derive(Serialize)]
+struct BenchReport<'a> {
+    kind: &'static str,
+    device: &'a Path,
+    results: &'a [BenchResult],
+}
+
+fn bench(args: BenchCommand, dry_run: bool, io: &IoOptions) -> Result<()> {
+    //There's nothing to simulate about a write benchmark, it's all about really writing
+    if args.write && dry_run {
+        return Err(anyhow!("--write can't be used with --dry-run, the write benchmark overwrites the drive"));
+    }
+    if args.compare_backends {
+        return compare(args, dry_run, io);
+    }
+    let disk = find_disk(&args.device)?;
+    if args.write {
+        confirm(args.yes, &format!("The write benchmark overwrites data all over {}.", disk.name.display()))?;
+    }
+    let mut reporter = Reporter::new(args.json);
+    let mut reported = Ok(());
+    let results = run_benchmark(&disk.name, io, args.write, |stage, progress| {
+        if reported.is_ok() {
+            reported = reporter.progress(stage, progress, None);
+        }
+    })?;
+    reported?;
+    if args.json {
+        return emit("result", BenchReport { kind: "bench", device: &disk.name, results: &results });
+    }
+    let rate = |bytes: Option<f64>| bytes.map(|b| format!("{:.1}", b / 1e6)).unwrap_or_else(|| String::from("-"));
+    println!("block\tseq read\trand read\tseq write\trand write (MB/s)");
+    for result in results.iter() {
+        println!(
+            "{} KiB\t{}\t{}\t{}\t{}",
+            result.block_size >> 10,
+            rate(Some(result.sequential_read)),
+            rate(Some(result.random_read)),
+            rate(result.sequential_write),
+            rate(result.random_write)
+        );
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct CompareReport<'a> {
+    kind: &'static str,
+    device: &'a Path,
+    results: &'a [BackendResult],
+}
+
+//Image files make a target that is safe to overwrite, anything else has to be an external drive
+fn compare(args: BenchCommand, dry_run: bool, io: &IoOptions) -> Result<()> {
+    if dry_run {
+        return Err(anyhow!("--compare-backends can't be used with --dry-run, it overwrites the target"));
+    }
+    let target = if args.device.is_file() { args.device.clone() } else { find_disk(&args.device)?.name };
+    confirm(args.yes, &format!("Comparing the backends overwrites the start of {}.", target.display()))?;
+    let mut reporter = Reporter::new(args.json);
+    let mut reported = Ok(());
+    let results = compare_backends(&target, io, |stage, progress| {
+        if reported.is_ok() {
+            reported = reporter.progress(stage, progress, None);
+        }
+    })?;
+    reported?;
+    if args.json {
+        return emit("result", CompareReport { kind: "compare-backends", device: &target, results: &results });
+    }
+    for result in results.iter() {
+        println!("{}	queue depth {}	{:.1} MB/s", result.backend, result.queue_depth, result.write / 1e6);
+    }
+    if results.len() < 2 {
+        println!("io_uring unavailable: built without the uring feature, turned off with --queue-depth 0 or unsupported by the kernel");
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ShredReport<'a> {
+    kind: &'static str,
+    method: &'a str,
+    paths: Vec<ShredPathReport<'a>>,
+    duration_secs: f64,
+    success: bool,
+    simulated: bool,
+}
+
+#[derive(Serialize)]
+struct ShredPathReport<'a> {
+    path: &'a Path,
+    files: usize,
+    directories: usize,
+    bytes_written: u64,
+    failures: Vec<ShredFailure>,
+}
+
+#[derive(Serialize)]
+struct ShredFailure {
+    path: PathBuf,
+    error: String,
+}
+
+fn shred(args: ShredCommand, methods: &[WipeMethod], dry_run: bool) -> Result<()> {
+    if args.paths.is_empty() {
+        return Err(anyhow!("nothing to shred"));
+    }
+    let method = find_method(methods, &args.method)?;
+    let names: Vec<String> = args.paths.iter().map(|p| p.display().to_string()).collect();
+    confirm(args.yes || dry_run, &format!("Shred {}?", names.join(", ")))?;
+    let started = Instant::now();
+    let mut reporter = Reporter::new(args.json);
+    let mut reports = Vec::new();
+    let mut failed = 0;
+    for path in args.paths.iter() {
+        if let Some(warning) = filesystem_warning(path) {
+            eprintln!("Warning: {}", warning);
+        }
+        let mut reported = Ok(());
+        let summary = shred_path(path, &method, args.recursive, dry_run, |file, progress| {
+            if reported.is_ok() {
+                let stage = format!("Shredding {}", file.display());
+                let ratio = progress.bytes_done as f64 / progress.bytes_total.max(1) as f64;
+                reported = reporter.progress(&stage, ratio, Some(progress));
+            }
+        })?;
+        reported?;
+        if !args.json {
+            println!(
+                "{}: {} files, {} directories, {} bytes {}",
+                path.display(),
+                summary.files,
+                summary.directories,
+                summary.bytes_written,
+                if dry_run { "would be overwritten" } else { "overwritten" }
+            );
+            for (file, error) in summary.failures.iter() {
+                println!("Failed {}: {}", file.display(), error);
+            }
+        }
+        failed += summary.failures.len();
+        reports.push(ShredPathReport {
+            path,
+            files: summary.files,
+            directories: summary.directories,
+            bytes_written: summary.bytes_written,
+            failures: summary
+                .failures
+                .into_iter()
+                .map(|(path, error)| ShredFailure { path, error })
+                .collect(),
+        });
+    }
+    if args.json {
+        emit(
+            "result",
+            ShredReport {
+                kind: "shred",
+                method: &method.name,
+                paths: reports,
+                duration_secs: started.elapsed().as_secs_f64(),
+                success: failed == 0,
+                simulated: dry_run,
+            },
+        )?;
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} entries could not be shredded", failed));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::test_disk;
+    use crate::wipe::builtin_methods;
+
+    fn parse(args: &[&str]) -> std::result::Result<Command, argh::EarlyExit> {
+        Command::from_args(&["minuteman", args[0]], &args[1..])
+    }
+
+    #[test]
+    fn arguments_parse() {
+        match parse(&["wipe", "--device", "/dev/sdb", "--method", "gutmann", "--scope", "free-space", "--yes", "--json"]) {
+            Ok(Command::Wipe(args)) => {
+                assert_eq!(args.device, PathBuf::from("/dev/sdb"));
+                assert_eq!(args.method, "gutmann");
+                assert_eq!(args.scope, WipeScope::FreeSpace);
+                assert!(args.yes && args.json);
+                assert_eq!(args.report, None);
+            }
+            other => panic!("{:?}", other),
+        }
+        //Defaults keep scripts short
+        match parse(&["wipe", "--device", "/dev/sdb"]) {
+            Ok(Command::Wipe(args)) => {
+                assert_eq!(args.method, "Zero fill");
+                assert_eq!(args.scope, WipeScope::EntireDrive);
+                assert!(!args.yes);
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse(&["shred", "-r", "a", "b"]) {
+            Ok(Command::Shred(args)) => {
+                assert!(args.recursive);
+                assert_eq!(args.paths, vec![PathBuf::from("a"), PathBuf::from("b")]);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(parse(&["list", "--json"]), Ok(Command::List(ListCommand { json: true }))));
+        assert!(matches!(parse(&["burn", "--image", "a.img", "--device", "/dev/sdb", "--no-verify"]), Ok(Command::Burn(BurnCommand { no_verify: true, .. }))));
+
+        assert!(parse(&["wipe", "--device", "/dev/sdb", "--scope", "everything"]).is_err());
+        assert!(parse(&["wipe"]).is_err()); //--device is required
+        assert!(parse(&["clone", "--device", "/dev/sdb"]).is_err()); //and so is --image
+        assert!(parse(&["verify", "--image", "a.img", "--device", "/dev/sdb", "--yes"]).is_err());
+    }
+
+    #[test]
+    fn failed_jobs_become_errors() {
+        let method = find_method(&builtin_methods(), "zero fill").unwrap();
+        let kind = JobKind::Wipe { disk: test_disk("/dev/minuteman-test"), method, scope: WipeScope::EntireDrive, sanitize: Vec::new(), io: IoOptions::default() };
+        let done = JobStatus { finished: true, summary: vec![String::from("Wrote 0 bytes in 1 passes")], ..JobStatus::default() };
+        finish(&kind, &done, false).unwrap();
+        finish(&kind, &done, true).unwrap();
+
+        let failed = JobStatus { error: Some(String::from("Input/output error")), ..done.clone() };
+        assert_eq!(finish(&kind, &failed, false).unwrap_err().to_string(), "Input/output error");
+        let mismatched = JobStatus { verified: true, verify_failures: vec![0, 4096], ..done };
+        assert_eq!(finish(&kind, &mismatched, true).unwrap_err().to_string(), "2 blocks did not verify");
+    }
+
+    #[test]
+    fn dry_runs_never_benchmark_writes() {
+        for flag in ["--write", "--compare-backends"] {
+            let args = BenchCommand::from_args(&["bench"], &["--device", "/dev/minuteman-test", flag, "--yes"]).unwrap();
+            let error = run(Command::Bench(args), Vec::new(), true, IoOptions::default()).unwrap_err();
+            assert!(error.to_string().contains("--dry-run"), "{}", error);
+        }
+    }
+}
@@ -1,112 +1,135 @@
-use std::io::BufReader;
-use std::fs::OpenOptions;
-use std::io::{Read, Write};
-use std::mem::*;
-use std::io::prelude::*;
-use std::path::PathBuf;
-use std::os::unix::io::AsRawFd;
+use anyhow::{anyhow, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom};
+use std::path::Path;
 
+use crate::disk::direct::{pipeline, read_full, write_full, AlignedBuffer, IoOptions};
 
-
-pub struct WritePath {
-    pub source: PathBuf,
-    pub destination: PathBuf,
+///Size of a file or block device, block devices report a length of 0 in their metadata so seek to the end instead
+pub fn device_size(path: &Path) -> Result<u64> {
+    let mut file = File::open(path)?;
+    Ok(file.seek(SeekFrom::End(0))?)
 }
 
-impl WritePath {
-    fn new(source: PathBuf, destination: PathBuf) -> WritePath {
-        WritePath { source, destination }
-    }
+///Copies the first `size` bytes of `source` to `write` in blocks of `block_size`, reading the next block
+/// while the current one is written. Calls `on_progress` with the number of bytes copied so far and
+/// returns the number of bytes copied.
+fn copy_data<W, F>(source: &Path, size: u64, io: &IoOptions, block_size: usize, mut write: W, mut on_progress: F) -> Result<u64>
+where
+    W: FnMut(&[u8]) -> Result<()>,
+    F: FnMut(u64),
+{
+    let mut src = io.open(source, false)?;
+    pipeline(
+        size,
+        block_size,
+        |_, buffer| read_full(&mut src, buffer),
+        |offset, data| {
+            write(data)?;
+            on_progress(offset + data.len() as u64);
+            Ok(())
+        },
+    )
 }
 
-
-
-
-pub fn burn_disk(device: PathBuf) {
-
+///Reads the first `size` bytes of `source` and throws them away, dry runs use it to go through the
+/// same reads a copy would without writing anything
+pub fn read_through<F>(source: &Path, size: u64, io: &IoOptions, on_progress: F) -> Result<u64>
+where
+    F: FnMut(u64),
+{
+    copy_data(source, size, io, io.block_size_for(source), |_| Ok(()), on_progress)
 }
 
+///Reads back the first `size` bytes of both paths and compares them, returns the offsets of the blocks
+/// which differ
+pub fn verify_copy<F>(source: &Path, destination: &Path, size: u64, io: &IoOptions, mut on_progress: F) -> Result<Vec<u64>>
+where
+    F: FnMut(u64),
+{
+    let block_size = io.block_size_for(source).max(io.block_size_for(destination));
+    let mut src = io.open(source, false)?;
+    let mut dst = io.open(destination, false)?;
+    let mut actual = AlignedBuffer::new(block_size);
+    let mut mismatches = Vec::new();
+    pipeline(
+        size,
+        block_size,
+        |_, buffer| read_full(&mut src, buffer),
+        |offset, expected| {
+            let actual = &mut actual[..expected.len()];
+            if read_full(&mut dst, actual)? < expected.len() {
+                return Err(anyhow!("{} ends before {} bytes", destination.display(), size));
+            }
+            if actual != expected {
+                mismatches.push(offset);
+            }
+            on_progress(offset + expected.len() as u64);
+            Ok(())
+        },
+    )?;
+    Ok(mismatches)
+}
 
-///Function which takes a source and destination as arg and then attempts to copy the source
-/// to an iso file at the destination
-pub fn create_disk_backup(device: &PathBuf, size: &u64) -> std::io::Result<String> {
-    if device.exists() {
-
-
-        let mut src = std::fs::File::open(device)?;
-        let src_meta = src.metadata().unwrap();
-        let src_size = src_meta.len();
-        // let src_size = 138000000;
-        println!("SIZE {}", src_size);
-        let mut destination_file = std::fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open("backup.iso")?;
-        
-        //How much to chip away at cloning process
-        // let mut remaining = src_size;
-        // let mut bytes_written = 0;
-        
-        //TODO redo the disk function to get accurate disk sizes
-        
-        //Future use of retrying from blocks of data
-        src.seek(std::io::SeekFrom::Start(0));
-
-
-        destination_file.seek(std::io::SeekFrom::Start(0));
-        
-        //Chunk size is ~ 1mb
-        let mut buf: Vec<u8> = vec![0; 1048576];
-
-        let mut src = src.take(*size);
-        println!("{}", src_size);
-        loop {
-            println!("WRITING!");
-            let len = match src.read(&mut buf) {
-                Ok(0) =>  {
-                    println!("NOTHING TO WRITE");
-                    break;
-                },
-                Ok(len) => len,
-                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
-                Err(e) => {
-                    println!("ERROR {}", e);
-                    break;
-                }
-            };
-
-            destination_file.write_all(&buf[..len]).unwrap();
-
-        }
-
-        Ok(String::from("COOL DONE"))
-
-        // while remaining > 0 {
-        //     let read_size = if chunk_size > remaining {
-        //         remaining
-        //     } else {
-        //         chunk_size 
-        //     };
-
-        //     buf.resize(read_size as usize, 0);
-
-        //     src.read_exact(&mut buf).unwrap();
-        //     destination_file.write_all(&buf).unwrap();
-
-        //     remaining -= read_size;
-        //     bytes_written += read_size;
-        // }
-        // destination_file.sync_data().unwrap();
-        // Ok(bytes_written.to_string())
-    } else {
-        Ok(String::from("NOT FOUND"))
+///Size of an image file, failing if it doesn't fit on the device
+pub fn image_fits(image: &Path, device: &Path) -> Result<u64> {
+    let size = device_size(image)?;
+    let capacity = device_size(device)?;
+    if size > capacity {
+        return Err(anyhow!(
+            "{} is {} bytes but {} only holds {}",
+            image.display(),
+            size,
+            device.display(),
+            capacity
+        ));
     }
+    Ok(size)
 }
 
-///Clone disk takes in a source and destination as arguments and then creates a clone
+///Writes an image file onto a device. The image has to fit on the device.
+pub fn burn_image<F>(image: &Path, device: &Path, io: &IoOptions, on_progress: F) -> Result<u64>
+where
+    F: FnMut(u64),
+{
+    let size = image_fits(image, device)?;
+    let mut destination = io.open(device, true)?;
+    let written = copy_data(image, size, io, io.block_size_for(device), |data| write_full(&mut destination, data), on_progress)?;
+    destination.sync_all()?;
+    Ok(written)
+}
 
+///Function which takes a device and destination as arg and then attempts to copy the whole device
+/// to an image file at the destination
+pub fn create_disk_backup<F>(device: &Path, destination: &Path, io: &IoOptions, on_progress: F) -> Result<u64>
+where
+    F: FnMut(u64),
+{
+    if !device.exists() {
+        return Err(anyhow!("{} not found", device.display()));
+    }
+    let size = device_size(device)?;
+    if !memory_is_available(destination, size) {
+        return Err(anyhow!("not enough space for a {} byte image at {}", size, destination.display()));
+    }
+    let mut destination_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(destination)?;
+    let copied = copy_data(device, size, io, io.block_size_for(device), |data| write_full(&mut destination_file, data), on_progress)?;
+    destination_file.sync_all()?;
+    Ok(copied)
+}
 
 //Check if there is enough memory in destination
-fn memory_is_available() -> bool {
-    true
+fn memory_is_available(destination: &Path, size: u64) -> bool {
+    let directory = match destination.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => std::path::PathBuf::from("."),
+    };
+    match crate::disk::calculate_partition_size(&directory) {
+        Ok((_, available)) => available >= size,
+        Err(_) => true,
+    }
 }
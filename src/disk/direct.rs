@@ -0,0 +1,292 @@
+//Direct I/O for clone, burn and wipe. Block devices are opened with O_DIRECT so copying or wiping a
+//whole drive doesn't push everything else out of the page cache, which needs buffers aligned to the
+//device's logical block size. Reading or generating the next block overlaps with writing the current one.
+
+use anyhow::{anyhow, Result};
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::fs::{File, OpenOptions};
+use std::ops::{Deref, DerefMut};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr::NonNull;
+use std::sync::mpsc::sync_channel;
+
+use crate::disk::{read_logical_block_size, DriveAccessor};
+
+//Buffers start on a page boundary, which covers every logical block size in use
+pub const ALIGNMENT: usize = 4096;
+pub const DEFAULT_BLOCK_SIZE: usize = 1 << 20;
+pub const DEFAULT_QUEUE_DEPTH: u32 = 32;
+
+/// How jobs move data around.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IoOptions {
+    pub block_size: usize, //Bytes per read and write
+    pub direct: bool,      //Bypass the page cache for block devices
+    pub queue_depth: u32,  //Writes in flight with io_uring, 0 keeps to plain reads and writes
+}
+
+impl Default for IoOptions {
+    fn default() -> IoOptions {
+        IoOptions { block_size: DEFAULT_BLOCK_SIZE, direct: true, queue_depth: DEFAULT_QUEUE_DEPTH }
+    }
+}
+
+impl IoOptions {
+    ///Block size rounded up to a whole number of logical blocks of `path`
+    pub fn block_size_for(&self, path: &Path) -> usize {
+        let align = ALIGNMENT.max(read_logical_block_size(path));
+        self.block_size.max(align).next_multiple_of(align)
+    }
+
+    ///Opens `path` for reading, or reading and writing. Block devices bypass the page cache when
+    /// `direct` is set, anything that refuses O_DIRECT is opened normally instead. Block devices
+    /// opened for writing are opened exclusively, which fails while a partition is mounted or in use.
+    pub fn open(&self, path: &Path, write: bool) -> Result<File> {
+        let block_device = is_block_device(path);
+        let direct = self.direct && block_device;
+        //O_EXCL on a block device makes the kernel refuse it while it's mounted or held by md or dm
+        let exclusive = if write && block_device { libc::O_EXCL } else { 0 };
+        let flags = if direct { libc::O_DIRECT | exclusive } else { exclusive };
+        let result = match OpenOptions::new().read(true).write(write).custom_flags(flags).open(path) {
+            Err(e) if direct && e.raw_os_error() == Some(libc::EINVAL) => {
+                OpenOptions::new().read(true).write(write).custom_flags(exclusive).open(path)
+            }
+            result => result,
+        };
+        match result {
+            Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+                Err(anyhow!("{} is in use, unmount its partitions first", path.display()))
+            }
+            result => Ok(result?),
+        }
+    }
+
+    ///Opens a drive for wiping, through io_uring when it's built in and the kernel supports it
+    pub fn open_drive(&self, path: &Path) -> Result<Box<dyn DriveAccessor>> {
+        let file = self.open(path, true)?;
+        if self.queue_depth > 0 {
+            if let Some(drive) = ring_drive(&file, self.queue_depth) {
+                return Ok(drive);
+            }
+        }
+        Ok(Box::new(file))
+    }
+}
+
+///io_uring backend on `file`, None when it isn't built in or the kernel doesn't support it
+#[cfg(feature = "uring")]
+pub fn ring_drive(file: &File, queue_depth: u32) -> Option<Box<dyn DriveAccessor>> {
+    let drive = crate::disk::uring::UringDrive::new(file.try_clone().ok()?, queue_depth).ok()?;
+    Some(Box::new(drive))
+}
+
+#[cfg(not(feature = "uring"))]
+pub fn ring_drive(_: &File, _: u32) -> Option<Box<dyn DriveAccessor>> {
+    None
+}
+
+fn is_block_device(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|m| m.file_type().is_block_device())
+}
+
+///Parses a block size such as 4096, 64K or 4M, it has to be a multiple of 4 KiB
+pub fn parse_block_size(value: &str) -> Result<usize, String> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => (&value[..i], &value[i..]),
+        None => (value, ""),
+    };
+    let multiplier = match unit.to_ascii_uppercase().as_str() {
+        "" => 1,
+        "K" | "KIB" => 1 << 10,
+        "M" | "MIB" => 1 << 20,
+        _ => return Err(format!("unknown unit {} in block size", unit)),
+    };
+    let size = number
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid block size {}", value))?;
+    if size == 0 || !size.is_multiple_of(ALIGNMENT) || size > 256 << 20 {
+        return Err(String::from("block size has to be a multiple of 4K and at most 256M"));
+    }
+    Ok(size)
+}
+
+/// Zeroed heap buffer starting on an `ALIGNMENT` boundary, as O_DIRECT requires.
+pub struct AlignedBuffer {
+    data: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+//The buffer owns its memory like a Vec does
+unsafe impl Send for AlignedBuffer {}
+
+impl AlignedBuffer {
+    pub fn new(len: usize) -> AlignedBuffer {
+        let layout = Layout::from_size_align(len.max(1), ALIGNMENT).expect("buffer size overflows");
+        let data = NonNull::new(unsafe { alloc_zeroed(layout) }).unwrap_or_else(|| std::alloc::handle_alloc_error(layout));
+        AlignedBuffer { data, len, layout }
+    }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.data.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.data.as_ptr(), self.layout) }
+    }
+}
+
+///Moves the first `size` bytes through two buffers of `block_size`: `produce` fills the block at an
+/// offset on its own thread and returns how much it filled, 0 meaning there is nothing more, while
+/// `consume` handles the previous block. Returns the number of bytes consumed.
+pub fn pipeline<P, C>(size: u64, block_size: usize, mut produce: P, mut consume: C) -> Result<u64>
+where
+    P: FnMut(u64, &mut [u8]) -> Result<usize> + Send,
+    C: FnMut(u64, &[u8]) -> Result<()>,
+{
+    let (full_tx, full_rx) = sync_channel::<Result<(u64, usize, AlignedBuffer)>>(2);
+    let (empty_tx, empty_rx) = sync_channel::<AlignedBuffer>(2);
+    for _ in 0..2 {
+        empty_tx.send(AlignedBuffer::new(block_size)).map_err(|_| anyhow!("buffer queue closed"))?;
+    }
+    //The channels move into the scope so an early return hangs up on the producer instead of leaving it
+    //blocked while the scope waits for it
+    std::thread::scope(move |scope| {
+        scope.spawn(move || {
+            let mut offset = 0;
+            //Stops once everything is produced or the consumer hung up
+            while offset < size {
+                let mut buffer = match empty_rx.recv() {
+                    Ok(buffer) => buffer,
+                    Err(_) => return,
+                };
+                let len = (size - offset).min(block_size as u64) as usize;
+                let block = match produce(offset, &mut buffer[..len]) {
+                    Ok(0) => return,
+                    Ok(filled) => Ok((offset, filled, buffer)),
+                    Err(e) => Err(e),
+                };
+                let failed = block.is_err();
+                if full_tx.send(block).is_err() || failed {
+                    return;
+                }
+                offset += len as u64;
+            }
+        });
+        let mut consumed = 0;
+        for block in full_rx.iter() {
+            let (offset, len, buffer) = block?;
+            consume(offset, &buffer[..len])?;
+            consumed += len as u64;
+            //The producer may already be done and gone
+            let _ = empty_tx.send(buffer);
+        }
+        Ok(consumed)
+    })
+}
+
+///Drops O_DIRECT from `file` when `len` isn't a whole number of blocks. Only the last block of a copy
+/// can be like that, so everything before it still bypasses the cache.
+fn allow_unaligned(file: &File, len: usize) -> Result<()> {
+    if len.is_multiple_of(ALIGNMENT) {
+        return Ok(());
+    }
+    let fd = file.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags >= 0 && flags & libc::O_DIRECT != 0 && unsafe { libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_DIRECT) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+///Reads until `buffer` is full or the end of `file`
+pub fn read_full(file: &mut File, buffer: &mut [u8]) -> Result<usize> {
+    use std::io::Read;
+    allow_unaligned(file, buffer.len())?;
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match Read::read(file, &mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(anyhow!(e)),
+        }
+    }
+    Ok(filled)
+}
+
+///Writes all of `data` to `file`
+pub fn write_full(file: &mut File, data: &[u8]) -> Result<()> {
+    use std::io::Write;
+    allow_unaligned(file, data.len())?;
+    Ok(file.write_all(data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_aligned() {
+        for len in [0, 1, 4096, 1 << 20] {
+            let buffer = AlignedBuffer::new(len);
+            assert_eq!(buffer.as_ptr() as usize % ALIGNMENT, 0);
+            assert_eq!(buffer.len(), len);
+        }
+    }
+
+    #[test]
+    fn pipeline_keeps_blocks_in_order() {
+        let mut seen = Vec::new();
+        let consumed = pipeline(
+            10_000,
+            4096,
+            |offset, buffer| {
+                buffer.iter_mut().for_each(|b| *b = (offset / 4096) as u8);
+                Ok(buffer.len())
+            },
+            |offset, data| {
+                seen.push((offset, data.len(), data[0]));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(consumed, 10_000);
+        assert_eq!(seen, vec![(0, 4096, 0), (4096, 4096, 1), (8192, 1808, 2)]);
+    }
+
+    #[test]
+    fn pipeline_stops_on_errors() {
+        let produced = pipeline(1 << 20, 4096, |offset, _| if offset < 8192 { Ok(4096) } else { Err(anyhow!("read failed")) }, |_, _| Ok(()));
+        assert!(produced.is_err());
+        let consumed = pipeline(1 << 20, 4096, |_, buffer| Ok(buffer.len()), |_, _| Err(anyhow!("write failed")));
+        assert!(consumed.is_err());
+    }
+
+    #[test]
+    fn block_sizes_parse() {
+        assert_eq!(parse_block_size("4096"), Ok(4096));
+        assert_eq!(parse_block_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_block_size("4M"), Ok(4 << 20));
+        assert!(parse_block_size("1000").is_err());
+        assert!(parse_block_size("4G").is_err());
+        assert!(parse_block_size("").is_err());
+    }
+}
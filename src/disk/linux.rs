@@ -3,12 +3,13 @@ use std::ffi::OsStr;
 use libc::statvfs;
 use std::fs::File;
 use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
 
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 use std::path::PathBuf;
 
-use crate::disk::{Disk, DiskType, Partition};
+use crate::disk::{Disk, DiskType, Partition, Slave};
 
 ///Retrieves all partitions by matching if `name` starts in the /proc/mounts file
 /// then returns them in a vector of `Partition`
@@ -26,8 +27,8 @@ pub fn read_partitions(name: &str) -> std::io::Result<Vec<Partition>> {
                 name: parts[0].to_string(),
                 mount_point: mount,
                 file_system: parts[2].to_string(),
-                total: total,
-                free: free,
+                total,
+                free,
                 read_only: false,
             })
         }
@@ -37,7 +38,7 @@ pub fn read_partitions(name: &str) -> std::io::Result<Vec<Partition>> {
 
 //Function takes in all block device partitions and adds up their allocated space
 //Unsure if this is correct method 
-pub fn calculate_disk_usage(partitions: &Vec<Partition>) -> std::io::Result<(u64, u64, u64)> {
+pub fn calculate_disk_usage(partitions: &[Partition]) -> std::io::Result<(u64, u64, u64)> {
     let mut total = 0;
     let mut free_space = 0;
     let mut used = 0;
@@ -91,7 +92,7 @@ fn is_usb_device(path: &Path) -> bool {
         if let Some(name) = path.file_name() {
             if let Some(name) = name.to_str() {
                 if name.starts_with("usb") {
-                    true;
+                    return true;
                 }
             }
         }
@@ -99,6 +100,48 @@ fn is_usb_device(path: &Path) -> bool {
     false
 }
 
+//Stacked and loop devices carry no usb attributes of their own, they only count as external when every
+//device they end up on is. Otherwise an LVM or LUKS root volume would be offered as a target
+fn sits_on_external(slaves: &[Slave], is_external: &dyn Fn(&Path) -> bool) -> bool {
+    !slaves.is_empty()
+        && slaves.iter().all(|slave| {
+            if slave.slaves.is_empty() {
+                is_external(&slave.name)
+            } else {
+                sits_on_external(&slave.slaves, is_external)
+            }
+        })
+}
+
+//Whether a device without slaves is a usb disk or one of its partitions, loop devices go by their backing file
+fn is_external_device(name: &Path) -> bool {
+    let sys_path = match name.file_name() {
+        Some(name) => Path::new("/sys/class/block").join(name),
+        None => return false,
+    };
+    match read_backing_file(&sys_path) {
+        Some(file) => is_on_external(&file),
+        None => sys_path.canonicalize().is_ok_and(|p| is_usb_device(&p)),
+    }
+}
+
+//Looks up the block device holding a file, which may be stacked itself
+fn is_on_external(file: &Path) -> bool {
+    let dev = match std::fs::metadata(file) {
+        Ok(metadata) => metadata.dev(),
+        Err(_) => return false,
+    };
+    let sys_path = Path::new("/sys/dev/block").join(format!("{}:{}", libc::major(dev), libc::minor(dev)));
+    let name = match sys_path.canonicalize().ok().and_then(|p| p.file_name().map(|n| n.to_owned())) {
+        Some(name) => Path::new("/dev").join(name),
+        None => return false,
+    };
+    match read_slaves(&sys_path) {
+        Ok(slaves) if !slaves.is_empty() => sits_on_external(&slaves, &is_external_device),
+        _ => is_external_device(&name),
+    }
+}
+
 fn disk_attributes(path: &Path) -> Option<PathBuf> {
     for path in path.ancestors() {
         if path.join("manufacturer").exists()
@@ -120,26 +163,236 @@ pub fn resolve_disk_type(block_path: PathBuf) -> std::io::Result<DiskType> {
         let contents = std::fs::read_to_string(path)?;
         Ok(contents.trim().into())
     };
+    //Partitions expose a "partition" file holding their number
+    if block_path.join("partition").exists() {
+        return Ok(DiskType::Partition);
+    }
+    //Loop devices get a "loop" directory once attached, check the name too for detached ones
+    let is_loop = block_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with("loop"));
+    if is_loop || block_path.join("loop").exists() {
+        return Ok(DiskType::Loop);
+    }
+    //Software RAID (md) arrays
+    if block_path.join("md").exists() {
+        return Ok(DiskType::RAID);
+    }
+    //Device mapper targets are told apart by the prefix of their uuid
+    if block_path.join("dm").exists() {
+        let uuid = read("dm/uuid").unwrap_or_default();
+        if uuid.starts_with("LVM-") {
+            return Ok(DiskType::LVM);
+        } else if uuid.starts_with("CRYPT-") {
+            return Ok(DiskType::Crypt);
+        }
+        return Ok(DiskType::Mapper);
+    }
     //If the disk is removable return that type
     let is_removable = read("removable")?;
-    if is_removable == String::from("1") {
+    if is_removable == "1" {
         Ok(DiskType::Removable) //CD, Flash, Floppy, etc.
     } else {
-        //Todo check for partition type
         //Check if its rotational, if true very likely that its a HDD
         let disk_queue = block_path.join("queue");
 
         if disk_queue.exists() {
             let is_rotational = read("queue/rotational")?;
-            if is_rotational == String::from("1") {
-                return Ok(DiskType::HDD);
+            if is_rotational == "1" {
+                Ok(DiskType::HDD)
             } else {
-                return Ok(DiskType::SSD);
+                Ok(DiskType::SSD)
             }
         } else {
             //Unknown disk types
-            return Ok(DiskType::Unknown);
+            Ok(DiskType::Unknown)
+        }
+    }
+}
+
+///Walks the "slaves" directory of a block device and returns every device it is stacked on,
+/// recursing so that e.g. LUKS on LVM on RAID shows up as a full chain
+pub fn read_slaves(block_path: &Path) -> std::io::Result<Vec<Slave>> {
+    let mut slaves: Vec<Slave> = Vec::new();
+    let slaves_path = block_path.join("slaves");
+    if !slaves_path.exists() {
+        return Ok(slaves);
+    }
+    for entry in std::fs::read_dir(slaves_path)? {
+        let entry = entry?;
+        //Entries are symlinks into the device tree, /sys/class/block has a flat view of them
+        let sys_path = Path::new("/sys/class/block").join(entry.file_name());
+        slaves.push(Slave {
+            name: Path::new("/dev").join(entry.file_name()),
+            disk_type: resolve_disk_type(sys_path.clone()).unwrap_or(DiskType::Unknown),
+            slaves: read_slaves(&sys_path)?,
+        });
+    }
+    slaves.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(slaves)
+}
+
+///Lists the partition device nodes of a disk, or the disk itself when it has no partition table
+pub fn partition_devices(disk_name: &Path) -> Vec<PathBuf> {
+    let name = match disk_name.file_name() {
+        Some(name) => name.to_owned(),
+        None => return Vec::new(),
+    };
+    let mut partitions: Vec<PathBuf> = std::fs::read_dir(Path::new("/sys/class/block").join(&name))
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().join("partition").exists())
+                .map(|e| Path::new("/dev").join(e.file_name()))
+                .collect()
+        })
+        .unwrap_or_default();
+    if partitions.is_empty() {
+        partitions.push(disk_name.to_path_buf());
+    }
+    partitions.sort();
+    partitions
+}
+
+//Queue directory of a block device in sysfs, partitions share the queue of their disk
+fn queue_path(device: &Path) -> Option<PathBuf> {
+    let name = device.canonicalize().ok()?.file_name()?.to_owned();
+    let block = Path::new("/sys/class/block").join(name).canonicalize().ok()?;
+    Some(if block.join("partition").exists() { block.join("../queue") } else { block.join("queue") })
+}
+
+fn read_queue_value(device: &Path, name: &str) -> Option<usize> {
+    std::fs::read_to_string(queue_path(device)?.join(name)).ok()?.trim().parse().ok()
+}
+
+///Smallest unit the device can be addressed in, partitions share the queue of their disk. Falls back
+/// to 512 for anything that isn't a block device.
+pub fn read_logical_block_size(device: &Path) -> usize {
+    read_queue_value(device, "logical_block_size").unwrap_or(512)
+}
+
+///Smallest unit the device writes internally, often 4096 on drives addressed in 512 byte sectors
+pub fn read_physical_block_size(device: &Path) -> usize {
+    read_queue_value(device, "physical_block_size").unwrap_or_else(|| read_logical_block_size(device))
+}
+
+//sysfs counts sizes and offsets in 512 byte sectors whatever the block size
+fn read_sectors(device: &Path, name: &str) -> Option<u64> {
+    let device = device.canonicalize().ok()?.file_name()?.to_owned();
+    let contents = std::fs::read_to_string(Path::new("/sys/class/block").join(device).join(name)).ok()?;
+    Some(contents.trim().parse::<u64>().ok()? * 512)
+}
+
+///Size of a block device or partition in bytes
+pub fn read_capacity(device: &Path) -> u64 {
+    read_sectors(device, "size").unwrap_or(0)
+}
+
+///Offset of a partition from the start of its disk in bytes, 0 for whole disks
+pub fn read_start(partition: &Path) -> u64 {
+    read_sectors(partition, "start").unwrap_or(0)
+}
+
+///Property udev recorded for a block device while probing it, e.g. ID_FS_TYPE or ID_PART_TABLE_TYPE
+pub fn read_udev_property(device: &Path, key: &str) -> Option<String> {
+    let name = device.canonicalize().ok()?.file_name()?.to_owned();
+    let numbers = std::fs::read_to_string(Path::new("/sys/class/block").join(name).join("dev")).ok()?;
+    let data = std::fs::read_to_string(format!("/run/udev/data/b{}", numbers.trim())).ok()?;
+    let prefix = format!("E:{}=", key);
+    data.lines().find_map(|l| l.strip_prefix(prefix.as_str())).map(String::from)
+}
+
+///Partition table of a disk as udev found it, "dos" tables go by their usual name MBR
+pub fn read_partition_table(disk_name: &Path) -> Option<String> {
+    match read_udev_property(disk_name, "ID_PART_TABLE_TYPE")?.as_str() {
+        "dos" => Some(String::from("MBR")),
+        table => Some(table.to_uppercase()),
+    }
+}
+
+///Filesystem label of a partition, looked up through the udev maintained /dev/disk/by-label links
+pub fn read_label(partition: &Path) -> Option<String> {
+    let partition = partition.canonicalize().ok()?;
+    std::fs::read_dir("/dev/disk/by-label")
+        .ok()?
+        .filter_map(|e| e.ok())
+        .find(|e| e.path().canonicalize().ok().as_deref() == Some(partition.as_path()))
+        .map(|e| unescape_label(&e.file_name().to_string_lossy()))
+}
+
+//udev escapes spaces, slashes and the like in link names as \xNN
+fn unescape_label(name: &str) -> String {
+    let mut bytes = Vec::new();
+    let raw = name.as_bytes();
+    let mut i = 0;
+    while i < raw.len() {
+        if raw[i] == b'\\' && raw.get(i + 1) == Some(&b'x') {
+            if let Some(byte) = name.get(i + 2..i + 4).and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                bytes.push(byte);
+                i += 4;
+                continue;
+            }
         }
+        bytes.push(raw[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+///Returns the file a loop device is attached to, if any
+fn read_backing_file(block_path: &Path) -> Option<PathBuf> {
+    let contents = std::fs::read_to_string(block_path.join("loop/backing_file")).ok()?;
+    Some(PathBuf::from(contents.trim()))
+}
+
+///Reads how many bytes the device accepts in a single discard request, 0 means no discard support
+pub fn read_discard_max_bytes(block_path: &Path) -> u64 {
+    std::fs::read_to_string(block_path.join("queue/discard_max_bytes"))
+        .ok()
+        .and_then(|c| c.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+///Names the bus a disk hangs off by walking its sysfs device path, "virtual" for stacked and loop devices
+pub fn read_bus(disk_name: &Path) -> String {
+    let name = match disk_name.file_name() {
+        Some(name) => name.to_owned(),
+        None => return String::from("unknown"),
+    };
+    let device = match Path::new("/sys/block").join(&name).join("device").canonicalize() {
+        Ok(device) => device,
+        Err(_) => return String::from("virtual"),
+    };
+    let path = device.to_string_lossy();
+    let bus = if path.contains("/usb") {
+        "usb"
+    } else if path.contains("/nvme") {
+        "nvme"
+    } else if path.contains("/mmc") {
+        "mmc"
+    } else if path.contains("/ata") {
+        "sata"
+    } else if path.contains("/virtio") {
+        "virtio"
+    } else if path.contains("/host") {
+        "scsi"
+    } else {
+        "unknown"
+    };
+    String::from(bus)
+}
+
+///Builds a human readable name for virtual devices which have no usb attributes to read from
+fn virtual_disk_model(block_path: &Path, disk_type: DiskType) -> String {
+    let read = |name| std::fs::read_to_string(block_path.join(name)).map(|c| c.trim().to_string());
+    match disk_type {
+        DiskType::RAID => format!("Software RAID ({})", read("md/level").unwrap_or_default()),
+        DiskType::LVM => format!("LVM volume {}", read("dm/name").unwrap_or_default()),
+        DiskType::Crypt => format!("Encrypted volume {}", read("dm/name").unwrap_or_default()),
+        DiskType::Mapper => format!("Device mapper {}", read("dm/name").unwrap_or_default()),
+        DiskType::Loop => String::from("Loop device"),
+        _ => String::new(),
     }
 }
 
@@ -150,6 +403,57 @@ pub fn find_external_disks() -> std::io::Result<Vec<Disk>> {
     for entry in std::fs::read_dir("/sys/block")? {
         let entry = entry?;
         let path = entry.path();
+        let name = Path::new("/dev").join(entry.file_name());
+        let disk_type = resolve_disk_type(path.clone()).unwrap_or(DiskType::Unknown);
+
+        //Stacked and loop devices have no physical "device" to read attributes from
+        if disk_type.is_stacked() || disk_type == DiskType::Loop {
+            let backing_file = read_backing_file(&path);
+            //Detached loop devices are just empty slots
+            if disk_type == DiskType::Loop && backing_file.is_none() {
+                continue;
+            }
+            let slaves = read_slaves(&path)?;
+            let external = match &backing_file {
+                Some(file) => is_on_external(file),
+                None => sits_on_external(&slaves, &is_external_device),
+            };
+            if !external {
+                log::debug!("Skipping {}, it isn't backed by a USB drive", name.display());
+                continue;
+            }
+            let read = |name| -> String {
+                std::fs::read_to_string(path.join(name)).map(|c| c.trim().to_string()).unwrap_or_default()
+            };
+            let mut partitions = read_partitions(name.to_str().unwrap())?;
+            //Mapper targets are usually mounted through their /dev/mapper alias
+            if path.join("dm").exists() {
+                let alias = Path::new("/dev/mapper").join(read("dm/name"));
+                partitions.extend(read_partitions(alias.to_str().unwrap())?);
+            }
+            let (total_space, free, used) = calculate_disk_usage(&partitions)?;
+            disks.push(Disk {
+                model: virtual_disk_model(&path, disk_type),
+                serial_number: if disk_type == DiskType::RAID { read("md/uuid") } else { read("dm/uuid") },
+                disk_type,
+                version: String::new(),
+                partitions,
+                total_space,
+                free_space: free,
+                used_space: used,
+                slaves,
+                backing_file,
+                discard_max_bytes: read_discard_max_bytes(&path),
+                capacity: read_capacity(&name),
+                bus: read_bus(&name),
+                logical_block_size: read_logical_block_size(&name),
+                physical_block_size: read_physical_block_size(&name),
+                partition_table: read_partition_table(&name),
+                name,
+            });
+            continue;
+        }
+
         let device_path = path.join("device");
         if !device_path.exists() {
             continue;
@@ -158,20 +462,17 @@ pub fn find_external_disks() -> std::io::Result<Vec<Disk>> {
 
         let device_path = device_path.canonicalize()?;
         // Skip non-USB devices
-        if is_usb_device(&device_path) {
+        if !is_usb_device(&device_path) {
             continue;
         }
         //Once we get our path and are certain its an external drive then get attributes like name, serial, etc.
         
 
 
-        let name = Path::new("/dev").join(entry.file_name());
-
-        let _type = resolve_disk_type(path);
-        let partitions = read_partitions(&name.to_str().unwrap())?;
+        let partitions = read_partitions(name.to_str().unwrap())?;
         let (total_space, free, used) = calculate_disk_usage(&partitions)?;
         if let Some(info_path) = disk_attributes(&device_path) {
-            println!("{:?}", device_path);
+            log::debug!("Found USB drive {} at {}", name.display(), device_path.display());
             //Read is a closure that displays specific disk attribute by reading a file value to string if it exists
             let read = |name| -> std::io::Result<String> {
                 let path = info_path.join(name);
@@ -182,17 +483,52 @@ pub fn find_external_disks() -> std::io::Result<Vec<Disk>> {
 
 
             disks.push(Disk {
-                name: name,
+                capacity: read_capacity(&name),
+                bus: read_bus(&name),
+                logical_block_size: read_logical_block_size(&name),
+                physical_block_size: read_physical_block_size(&name),
+                partition_table: read_partition_table(&name),
+                name,
                 model: read("product")?,
                 serial_number: read("serial")?,
-                disk_type: _type.unwrap_or(DiskType::Unknown),
+                disk_type,
                 version: read("version")?,
-                partitions: partitions,
-                total_space: total_space,
+                partitions,
+                total_space,
                 free_space: free,
                 used_space: used,
+                slaves: read_slaves(&path)?,
+                backing_file: None,
+                discard_max_bytes: read_discard_max_bytes(&path),
             });
         }
     }
     Ok(disks)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slave(name: &str, slaves: Vec<Slave>) -> Slave {
+        let disk_type = if slaves.is_empty() { DiskType::Partition } else { DiskType::LVM };
+        Slave { name: PathBuf::from(name), disk_type, slaves }
+    }
+
+    #[test]
+    fn stacked_devices_need_every_slave_external() {
+        let usb = |name: &Path| {
+            let name = name.to_string_lossy();
+            name.starts_with("/dev/sdb") || name.starts_with("/dev/sdc")
+        };
+        //LVM on a usb stick, LUKS on top of that, a mirror across two sticks
+        assert!(sits_on_external(&[slave("/dev/sdb1", vec![])], &usb));
+        assert!(sits_on_external(&[slave("/dev/dm-0", vec![slave("/dev/sdb1", vec![])])], &usb));
+        assert!(sits_on_external(&[slave("/dev/sdb", vec![]), slave("/dev/sdc", vec![])], &usb));
+        //The system drive's root volume, or an array only half on a stick
+        assert!(!sits_on_external(&[slave("/dev/sda3", vec![])], &usb));
+        assert!(!sits_on_external(&[slave("/dev/dm-0", vec![slave("/dev/nvme0n1p2", vec![])])], &usb));
+        assert!(!sits_on_external(&[slave("/dev/sda", vec![]), slave("/dev/sdb", vec![])], &usb));
+        assert!(!sits_on_external(&[], &usb));
+    }
+}
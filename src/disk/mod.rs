@@ -1,13 +1,21 @@
 //Todo here export the module depending on operating system
 
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use anyhow::Result;
+use serde::Serialize;
 
+pub mod direct;
 mod linux;
+pub mod simulated;
+#[cfg(feature = "uring")]
+pub mod uring;
 #[cfg(unix)]
 use linux as os;
 
-pub use os::find_external_disks;
+pub use os::{calculate_partition_size, find_external_disks, partition_devices, read_capacity, read_discard_max_bytes,
+    read_label, read_logical_block_size, read_start, read_udev_property};
 
 
 
@@ -19,7 +27,37 @@ pub trait DriveAccessor {
     fn flush(&mut self) -> Result<()>;
 }
 
-#[derive(Clone, Debug)]
+//Plain files and block devices opened through std, flushing syncs the data to the media
+impl DriveAccessor for File {
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.stream_position()?)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        Ok(Seek::seek(self, SeekFrom::Start(position))?)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        loop {
+            match Read::read(self, buffer) {
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                result => return Ok(result?),
+            }
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        Ok(self.write_all(data)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Write::flush(self)?;
+        self.sync_data()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct Partition {
     pub name: String,
     pub mount_point: PathBuf,
@@ -30,9 +68,21 @@ pub struct Partition {
 }
 
 
+/// A partition device node of a disk with its label, filesystem and where it is mounted, mounted or not.
+#[derive(Clone, Debug, Serialize)]
+pub struct PartitionInfo {
+    pub device: PathBuf,
+    pub label: Option<String>,
+    pub file_system: Option<String>,
+    pub mount_point: Option<PathBuf>,
+    pub start: u64, //Offset from the start of the disk
+    pub size: u64,
+    pub used: Option<u64>, //Only known while mounted
+}
+
 /// `Disk` represents a single Disk/drive which contains metadata about it
 /// such as `name`, `total_space` or `drive_type`.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Disk {
     pub name: PathBuf,
     pub model: String,
@@ -43,22 +93,110 @@ pub struct Disk {
     pub total_space: u64,
     pub free_space: u64,
     pub used_space: u64,
+    pub slaves: Vec<Slave>, //Devices this one is stacked on top of (md members, dm targets, etc.)
+    pub backing_file: Option<PathBuf>, //Only set for loop devices
+    pub discard_max_bytes: u64, //0 when the device can't discard (TRIM) blocks
+    pub capacity: u64, //Size of the whole device, unlike total_space which only counts mounted partitions
+    pub bus: String,
+    pub logical_block_size: usize,
+    pub physical_block_size: usize,
+    pub partition_table: Option<String>, //GPT, MBR, etc. None when there is none or udev didn't say
+}
+
+impl Disk {
+    /// Whether the device reports support for discarding (TRIM/UNMAP) blocks
+    pub fn supports_discard(&self) -> bool {
+        self.discard_max_bytes > 0
+    }
+
+    /// Every partition of the disk, the disk itself when it has no partition table
+    pub fn describe_partitions(&self) -> Vec<PartitionInfo> {
+        partition_devices(&self.name)
+            .into_iter()
+            .map(|device| {
+                let mounted = self.partitions.iter().find(|p| Path::new(&p.name) == device);
+                PartitionInfo {
+                    label: read_label(&device),
+                    //udev probes unmounted partitions too
+                    file_system: mounted
+                        .map(|p| p.file_system.clone())
+                        .or_else(|| read_udev_property(&device, "ID_FS_TYPE")),
+                    mount_point: mounted.map(|p| p.mount_point.clone()),
+                    start: read_start(&device),
+                    size: read_capacity(&device),
+                    used: mounted.map(|p| p.total - p.free),
+                    device,
+                }
+            })
+            .collect()
+    }
+
+    /// Flash based storage where overwriting does not reliably reach every physical cell
+    pub fn is_flash(&self) -> bool {
+        matches!(self.disk_type, DiskType::SSD | DiskType::Removable)
+    }
+}
+
+///Plain USB hard drive with nothing mounted, for tests that need a `Disk` but never touch it
+#[cfg(test)]
+pub fn test_disk(name: &str) -> Disk {
+    Disk {
+        name: PathBuf::from(name),
+        model: String::from("Test"),
+        serial_number: String::from("0001"),
+        disk_type: DiskType::HDD,
+        partitions: Vec::new(),
+        version: String::new(),
+        total_space: 0,
+        free_space: 0,
+        used_space: 0,
+        slaves: Vec::new(),
+        backing_file: None,
+        discard_max_bytes: 0,
+        capacity: 0,
+        bus: String::from("usb"),
+        logical_block_size: 512,
+        physical_block_size: 512,
+        partition_table: None,
+    }
+}
+
+
+/// `Slave` is a block device sitting underneath a stacked `Disk` such as a RAID member
+/// or the physical volume of an LVM/dm-crypt target. Slaves can be stacked themselves.
+#[derive(Clone, Debug, Serialize)]
+pub struct Slave {
+    pub name: PathBuf,
+    pub disk_type: DiskType,
+    pub slaves: Vec<Slave>,
 }
 
 
 /// Enum which contains supported disk types by application.
 ///
 ///
-#[derive(Clone, Debug, Copy)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, Copy, PartialEq, Serialize)]
 pub enum DiskType {
     HDD,
     SSD,
     Partition,
     Removable,
     RAID,
+    LVM,
+    Crypt, //dm-crypt / LUKS
+    Mapper, //Any other device-mapper target
+    Loop,
     Unknown,
 }
 
+impl DiskType {
+    /// Whether the device is a virtual one stacked on top of other block devices
+    pub fn is_stacked(&self) -> bool {
+        matches!(self, DiskType::RAID | DiskType::LVM | DiskType::Crypt | DiskType::Mapper)
+    }
+}
+
 impl std::fmt::Display for DiskType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
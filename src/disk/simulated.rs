@@ -0,0 +1,202 @@
+//Stand-ins for a drive used by dry runs, nothing written to them ever reaches a device.
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+
+use crate::disk::DriveAccessor;
+use crate::wipe::engine::PatternSource;
+
+/// Drive of `size` bytes that keeps nothing but how many passes went over every chunk. Reads replay what
+/// the last pass wrote from the same `PatternSource`, so verifying a simulated wipe behaves like the real
+/// thing without holding the data in memory. Only works with the block aligned writes the engine does,
+/// `chunk` has to match the engine's block size.
+pub struct SimulatedDrive {
+    size: u64,
+    chunk: usize,
+    position: u64,
+    source: PatternSource,
+    writes: Vec<u32>, //Passes written per chunk
+    scratch: Vec<u8>,
+}
+
+impl SimulatedDrive {
+    pub fn new(size: u64, chunk: usize, source: PatternSource) -> SimulatedDrive {
+        let chunks = size.div_ceil(chunk as u64) as usize;
+        SimulatedDrive {
+            size,
+            chunk,
+            position: 0,
+            source,
+            writes: vec![0; chunks],
+            scratch: vec![0; chunk],
+        }
+    }
+}
+
+impl DriveAccessor for SimulatedDrive {
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.position)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        self.position = position;
+        Ok(position)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        let mut filled = 0;
+        while filled < buffer.len() && self.position < self.size {
+            let index = (self.position / self.chunk as u64) as usize;
+            let start = index as u64 * self.chunk as u64;
+            let chunk_len = (self.size - start).min(self.chunk as u64) as usize;
+            let within = (self.position - start) as usize;
+            let len = (chunk_len - within).min(buffer.len() - filled);
+            match self.writes[index] {
+                0 => buffer[filled..filled + len].iter_mut().for_each(|b| *b = 0),
+                passes => {
+                    self.source.fill(passes as usize - 1, start, &mut self.scratch[..chunk_len]);
+                    buffer[filled..filled + len].copy_from_slice(&self.scratch[within..within + len]);
+                }
+            }
+            filled += len;
+            self.position += len as u64;
+        }
+        Ok(filled)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        if !self.position.is_multiple_of(self.chunk as u64) {
+            return Err(anyhow!("simulated writes have to start on a chunk boundary"));
+        }
+        if self.position + data.len() as u64 > self.size {
+            return Err(anyhow!("write past the end of the simulated drive"));
+        }
+        let first = (self.position / self.chunk as u64) as usize;
+        let last = (self.position + data.len() as u64).div_ceil(self.chunk as u64) as usize;
+        self.writes[first..last].iter_mut().for_each(|w| *w += 1);
+        self.position += data.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Wraps a drive opened read only and keeps every write in memory, reads see the writes on top of the
+/// drive's contents. Meant for small scattered writes like deep cleaning does.
+pub struct Overlay<T: DriveAccessor> {
+    inner: T,
+    position: u64,
+    writes: BTreeMap<u64, Vec<u8>>, //Offset to data, kept disjoint
+    written: u64,
+}
+
+impl<T: DriveAccessor> Overlay<T> {
+    pub fn new(inner: T) -> Overlay<T> {
+        Overlay { inner, position: 0, writes: BTreeMap::new(), written: 0 }
+    }
+
+    /// Bytes that would have been written to the drive
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+}
+
+impl<T: DriveAccessor> DriveAccessor for Overlay<T> {
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.position)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        self.position = position;
+        Ok(position)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        self.inner.seek(self.position)?;
+        let len = self.inner.read(buffer)?;
+        let end = self.position + len as u64;
+        let before = self.writes.range(..self.position).next_back();
+        for (offset, data) in before.into_iter().chain(self.writes.range(self.position..end)) {
+            let data_end = offset + data.len() as u64;
+            if data_end <= self.position {
+                continue;
+            }
+            let from = self.position.max(*offset);
+            let to = end.min(data_end);
+            buffer[(from - self.position) as usize..(to - self.position) as usize]
+                .copy_from_slice(&data[(from - offset) as usize..(to - offset) as usize]);
+        }
+        self.position = end;
+        Ok(len)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        let start = self.position;
+        let end = start + data.len() as u64;
+        //Cut what this write covers out of earlier writes so the entries never overlap
+        let overlapping: Vec<u64> = self
+            .writes
+            .range(..end)
+            .rev()
+            .take_while(|(offset, old)| *offset + old.len() as u64 > start)
+            .map(|(offset, _)| *offset)
+            .collect();
+        for offset in overlapping {
+            let old = self.writes.remove(&offset).unwrap_or_default();
+            let old_end = offset + old.len() as u64;
+            if offset < start {
+                self.writes.insert(offset, old[..(start - offset) as usize].to_vec());
+            }
+            if old_end > end {
+                self.writes.insert(end, old[(end - offset) as usize..].to_vec());
+            }
+        }
+        self.writes.insert(start, data.to_vec());
+        self.position += data.len() as u64;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wipe::engine::{run_source, CHUNK_SIZE};
+    use crate::wipe::{Pass, Pattern};
+
+    #[test]
+    fn simulated_wipe_verifies() {
+        let passes = vec![
+            Pass { pattern: Pattern::Random, verify: true },
+            Pass { pattern: Pattern::Complement, verify: true },
+        ];
+        let size = CHUNK_SIZE as u64 * 2 + 100;
+        let source = PatternSource::new(&passes);
+        let mut drive = SimulatedDrive::new(size, CHUNK_SIZE, source.clone());
+        let outcome = run_source(&mut drive, size, &source, CHUNK_SIZE, |_| {}).unwrap();
+        assert_eq!(outcome.bytes_written, size * 2);
+        assert!(outcome.verified);
+        assert!(outcome.verify_failures.is_empty());
+    }
+
+    #[test]
+    fn overlay_keeps_the_latest_write() {
+        let inner = SimulatedDrive::new(64, 64, PatternSource::new(&[]));
+        let mut overlay = Overlay::new(inner);
+        overlay.seek(4).unwrap();
+        overlay.write(&[1; 8]).unwrap();
+        overlay.seek(8).unwrap();
+        overlay.write(&[2; 8]).unwrap();
+        overlay.seek(0).unwrap();
+        let mut buffer = [0xFF; 20];
+        assert_eq!(overlay.read(&mut buffer).unwrap(), 20);
+        assert_eq!(buffer, [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 0, 0, 0]);
+        assert_eq!(overlay.bytes_written(), 16);
+    }
+}
@@ -0,0 +1,208 @@
+//io_uring backend for wiping drives. Writes are copied into buffers of their own and queued, so up to
+//`depth` of them are in flight while the engine generates the next block, and reads are split across
+//the queue. Only built with the uring feature.
+
+use anyhow::{anyhow, Result};
+use io_uring::{opcode, types, IoUring};
+use std::collections::HashMap;
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+
+use crate::disk::direct::{AlignedBuffer, ALIGNMENT};
+use crate::disk::DriveAccessor;
+
+//Reads aren't split into pieces smaller than this
+const MIN_READ: usize = 64 << 10;
+
+pub struct UringDrive {
+    file: File,
+    ring: IoUring,
+    depth: usize,
+    position: u64,
+    pending: HashMap<u64, (AlignedBuffer, usize)>, //Writes in flight by id, with their length
+    free: Vec<AlignedBuffer>,
+    next_id: u64,
+}
+
+impl UringDrive {
+    ///Fails on kernels without io_uring or where it's disabled
+    pub fn new(file: File, depth: u32) -> Result<UringDrive> {
+        let depth = depth.max(1);
+        let ring = IoUring::new(depth.next_power_of_two())?;
+        Ok(UringDrive {
+            file,
+            ring,
+            depth: depth as usize,
+            position: 0,
+            pending: HashMap::new(),
+            free: Vec::new(),
+            next_id: 0,
+        })
+    }
+
+    ///Waits for at least `count` writes to complete, the first failed one is returned as an error once
+    /// every completion that came in has been handled
+    fn reap(&mut self, count: usize) -> Result<()> {
+        self.ring.submit_and_wait(count)?;
+        let completions: Vec<(u64, i32)> = self.ring.completion().map(|c| (c.user_data(), c.result())).collect();
+        let mut result = Ok(());
+        for (id, written) in completions {
+            let (buffer, len) = match self.pending.remove(&id) {
+                Some(write) => write,
+                None => continue,
+            };
+            self.free.push(buffer);
+            if result.is_ok() {
+                if written < 0 {
+                    result = Err(std::io::Error::from_raw_os_error(-written).into());
+                } else if (written as usize) < len {
+                    result = Err(anyhow!("short write of {} bytes out of {}", written, len));
+                }
+            }
+        }
+        result
+    }
+
+    fn drain(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        while !self.pending.is_empty() {
+            let before = self.pending.len();
+            let reaped = self.reap(before);
+            if self.pending.len() == before && reaped.is_err() {
+                //The ring itself failed, the writes may never complete so their buffers can't be freed
+                self.pending.drain().for_each(|(_, (buffer, _))| std::mem::forget(buffer));
+                return reaped;
+            }
+            if result.is_ok() {
+                result = reaped;
+            }
+        }
+        result
+    }
+}
+
+impl DriveAccessor for UringDrive {
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.position)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        self.position = position;
+        Ok(position)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        //Reads have to see every write queued before them
+        self.drain()?;
+        let piece = buffer.len().div_ceil(self.depth).max(MIN_READ).next_multiple_of(ALIGNMENT);
+        let pieces: Vec<(usize, usize)> = (0..buffer.len())
+            .step_by(piece)
+            .map(|start| (start, piece.min(buffer.len() - start)))
+            .collect();
+        let fd = types::Fd(self.file.as_raw_fd());
+        //There are never more pieces than the queue holds
+        let mut submission = self.ring.submission();
+        for (index, (start, len)) in pieces.iter().enumerate() {
+            let entry = opcode::Read::new(fd, buffer[*start..].as_mut_ptr(), *len as u32)
+                .offset(self.position + *start as u64)
+                .build()
+                .user_data(index as u64);
+            unsafe { submission.push(&entry) }.map_err(|_| anyhow!("io_uring submission queue is full"))?;
+        }
+        drop(submission);
+        //Every read has to complete before `buffer` goes back to the caller
+        let mut results = vec![0; pieces.len()];
+        let mut done = 0;
+        while done < pieces.len() {
+            self.ring.submit_and_wait(1)?;
+            for completion in self.ring.completion() {
+                results[completion.user_data() as usize] = completion.result();
+                done += 1;
+            }
+        }
+        let mut filled = 0;
+        for (result, (_, len)) in results.iter().zip(pieces.iter()) {
+            if *result < 0 {
+                return Err(std::io::Error::from_raw_os_error(-result).into());
+            }
+            filled += *result as usize;
+            //Anything after a short read is past the end
+            if (*result as usize) < *len {
+                break;
+            }
+        }
+        self.position += filled as u64;
+        Ok(filled)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        while self.pending.len() >= self.depth {
+            self.reap(1)?;
+        }
+        let mut buffer = match self.free.pop() {
+            Some(buffer) if buffer.len() >= data.len() => buffer,
+            _ => AlignedBuffer::new(data.len()),
+        };
+        buffer[..data.len()].copy_from_slice(data);
+        let id = self.next_id;
+        self.next_id += 1;
+        let entry = opcode::Write::new(types::Fd(self.file.as_raw_fd()), buffer.as_ptr(), data.len() as u32)
+            .offset(self.position)
+            .build()
+            .user_data(id);
+        if unsafe { self.ring.submission().push(&entry) }.is_err() {
+            self.free.push(buffer);
+            return Err(anyhow!("io_uring submission queue is full"));
+        }
+        //Queued from here on, the buffer has to outlive the write even if submitting fails
+        self.pending.insert(id, (buffer, data.len()));
+        self.ring.submit()?;
+        self.position += data.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.drain()?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+impl Drop for UringDrive {
+    fn drop(&mut self) {
+        //The kernel may still be reading from the buffers
+        let _ = self.drain();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wipe::engine::run_passes;
+    use crate::wipe::{Pass, Pattern};
+    use std::fs::OpenOptions;
+    use std::path::PathBuf;
+
+    fn temp_file(name: &str, size: u64) -> (PathBuf, File) {
+        let path = std::env::temp_dir().join(format!("minuteman-uring-{}-{}", name, std::process::id()));
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+        file.set_len(size).unwrap();
+        (path, file)
+    }
+
+    #[test]
+    fn writes_read_back() {
+        let size = 3 << 20;
+        let (path, file) = temp_file("verify", size);
+        //Kernels without io_uring have nothing to test
+        let mut drive = match UringDrive::new(file, 4) {
+            Ok(drive) => drive,
+            Err(_) => return,
+        };
+        let passes = vec![Pass { pattern: Pattern::Random, verify: true }];
+        let outcome = run_passes(&mut drive, size, &passes, |_| {}).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(outcome.bytes_written, size);
+        assert!(outcome.verify_failures.is_empty());
+    }
+}
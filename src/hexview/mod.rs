@@ -0,0 +1,406 @@
+//Read-only hex viewer for drives and images, to eyeball what is on a drive before wiping it or spot
+//check a wiped region afterwards. Only the page on screen is read, searches go through the drive a
+//chunk per tick so the UI keeps responding.
+
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::clone::device_size;
+use crate::disk::{partition_devices, read_logical_block_size, read_start, DriveAccessor};
+
+pub const BYTES_PER_LINE: u64 = 16;
+
+//How much of the drive a search goes through per tick, small enough to read within a tick from a USB 2 stick
+const SEARCH_STEP: usize = 2 << 20;
+
+/// What is being typed at the bottom of the viewer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Prompt {
+    Offset,
+    Sector,
+    Search,
+}
+
+impl Prompt {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Prompt::Offset => "Go to offset (decimal or 0x hex)",
+            Prompt::Sector => "Go to sector",
+            Prompt::Search => "Search for hex bytes like 55 aa, or \"text\"",
+        }
+    }
+}
+
+pub struct HexViewer {
+    pub path: PathBuf,
+    source: Box<dyn DriveAccessor>,
+    pub size: u64,
+    pub sector_size: u64,
+    pub offset: u64, //First byte on screen, always at the start of a line
+    rows: u64,
+    pub data: Vec<u8>, //What is on screen, shorter than a page at the end of the drive
+    partitions: Vec<(PathBuf, u64)>, //Partition devices by their start, in order
+    pub prompt: Option<Prompt>,
+    pub input: String,
+    pub message: Option<String>, //Outcome of the last jump or search, or what went wrong
+    pattern: Vec<u8>,             //Last searched for
+    pub found: Option<u64>,       //Offset of the last match, highlighted on screen
+    pub searching: Option<u64>,   //Where a running search goes on from
+}
+
+impl HexViewer {
+    ///Opens a drive or image read only, drives get their partitions listed to jump between
+    pub fn open(path: &Path) -> Result<HexViewer> {
+        let file = File::open(path)?;
+        let size = device_size(path)?;
+        let mut partitions: Vec<(PathBuf, u64)> = partition_devices(path)
+            .into_iter()
+            .filter(|device| device != path)
+            .map(|device| {
+                let start = read_start(&device);
+                (device, start)
+            })
+            .collect();
+        partitions.sort_by_key(|(_, start)| *start);
+        Ok(HexViewer::new(Box::new(file), path, size, read_logical_block_size(path) as u64, partitions))
+    }
+
+    pub fn new(
+        source: Box<dyn DriveAccessor>,
+        path: &Path,
+        size: u64,
+        sector_size: u64,
+        partitions: Vec<(PathBuf, u64)>,
+    ) -> HexViewer {
+        HexViewer {
+            path: path.to_path_buf(),
+            source,
+            size,
+            sector_size: sector_size.max(1),
+            offset: 0,
+            rows: 0,
+            data: Vec::new(),
+            partitions,
+            prompt: None,
+            input: String::new(),
+            message: None,
+            pattern: Vec::new(),
+            found: None,
+            searching: None,
+        }
+    }
+
+    ///Lines that fit on screen, the page is read again when it changes
+    pub fn set_rows(&mut self, rows: u64) {
+        if rows != self.rows {
+            self.rows = rows;
+            self.load();
+        }
+    }
+
+    fn page_size(&self) -> u64 {
+        self.rows.max(1) * BYTES_PER_LINE
+    }
+
+    fn load(&mut self) {
+        let len = self.page_size().min(self.size.saturating_sub(self.offset)) as usize;
+        let mut data = vec![0; len];
+        match read_at(&mut *self.source, self.offset, &mut data) {
+            Ok(filled) => {
+                data.truncate(filled);
+                self.data = data;
+            }
+            Err(e) => {
+                self.data.clear();
+                self.message = Some(format!("Could not read at {}: {}", self.offset, e));
+            }
+        }
+    }
+
+    ///Shows `offset` on the top line, or the last page when it's further than that
+    pub fn go_to(&mut self, offset: u64) {
+        let last_page = self.size.saturating_sub(self.page_size()).next_multiple_of(BYTES_PER_LINE);
+        self.offset = (offset - offset % BYTES_PER_LINE).min(last_page);
+        self.load();
+    }
+
+    pub fn scroll(&mut self, lines: i64) {
+        let offset = self.offset as i64 + lines * BYTES_PER_LINE as i64;
+        self.go_to(offset.max(0) as u64);
+    }
+
+    pub fn page(&mut self, pages: i64) {
+        self.scroll(pages * self.rows.max(1) as i64);
+    }
+
+    ///Jumps to the start of the partition after the top line, going round to the first one
+    pub fn next_partition(&mut self) {
+        let next = self
+            .partitions
+            .iter()
+            .find(|(_, start)| *start > self.offset)
+            .or_else(|| self.partitions.first())
+            .cloned();
+        match next {
+            Some((device, start)) => {
+                self.go_to(start);
+                self.message = Some(format!("{} starts at {} (sector {})", device.display(), start, start / self.sector_size));
+            }
+            None => self.message = Some(String::from("No partitions")),
+        }
+    }
+
+    pub fn start_prompt(&mut self, prompt: Prompt) {
+        self.prompt = Some(prompt);
+        self.input.clear();
+    }
+
+    ///Typing into the prompt, or a running search which can be cancelled the same way
+    pub fn is_busy(&self) -> bool {
+        self.prompt.is_some() || self.searching.is_some()
+    }
+
+    pub fn cancel(&mut self) {
+        if self.searching.take().is_some() {
+            self.message = Some(String::from("Search cancelled"));
+        }
+        self.prompt = None;
+    }
+
+    ///Acts on what was typed into the prompt
+    pub fn submit(&mut self) {
+        let prompt = match self.prompt.take() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        let result = match prompt {
+            Prompt::Offset => parse_number(&self.input).map(|offset| self.go_to(offset)),
+            Prompt::Sector => parse_number(&self.input)
+                .and_then(|sector| sector.checked_mul(self.sector_size).ok_or_else(|| anyhow!("sector is out of range")))
+                .map(|offset| self.go_to(offset)),
+            Prompt::Search => parse_pattern(&self.input).map(|pattern| {
+                self.pattern = pattern;
+                self.found = None;
+                self.searching = Some(self.offset);
+            }),
+        };
+        self.message = result.err().map(|e| e.to_string());
+    }
+
+    ///Looks for the last pattern again, after the last match or the top line
+    pub fn search_next(&mut self) {
+        if self.pattern.is_empty() {
+            self.message = Some(String::from("Nothing searched for yet, press / to search"));
+            return;
+        }
+        self.searching = Some(self.found.map(|f| f + 1).unwrap_or(self.offset));
+    }
+
+    ///Goes on with a running search, called every tick
+    pub fn on_tick(&mut self) {
+        let from = match self.searching {
+            Some(from) => from,
+            None => return,
+        };
+        match find(&mut *self.source, self.size, &self.pattern, from, SEARCH_STEP) {
+            Ok(Found::At(offset)) => {
+                self.searching = None;
+                self.found = Some(offset);
+                self.go_to(offset);
+                self.message = Some(format!("Found at {} (sector {})", offset, offset / self.sector_size));
+            }
+            Ok(Found::NotYet(next)) => self.searching = Some(next),
+            Ok(Found::Nowhere) => {
+                self.searching = None;
+                self.message = Some(String::from("Not found before the end"));
+            }
+            Err(e) => {
+                self.searching = None;
+                self.message = Some(format!("Search failed: {}", e));
+            }
+        }
+    }
+
+    ///How far a running search got, as a ratio of the drive
+    pub fn search_progress(&self) -> Option<f64> {
+        self.searching.map(|at| if self.size == 0 { 1.0 } else { at as f64 / self.size as f64 })
+    }
+
+    ///Length of what was searched for, to highlight matches
+    pub fn pattern_len(&self) -> usize {
+        self.pattern.len()
+    }
+}
+
+enum Found {
+    At(u64),
+    NotYet(u64), //Nothing up to here, go on from there
+    Nowhere,
+}
+
+//Reads until `buffer` is full or the end of `source`
+fn read_at(source: &mut dyn DriveAccessor, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+    source.seek(offset)?;
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match source.read(&mut buffer[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+//Searches `step` bytes from `from`, reading a little further so matches across the boundary count
+fn find(source: &mut dyn DriveAccessor, size: u64, pattern: &[u8], from: u64, step: usize) -> Result<Found> {
+    if from + pattern.len() as u64 > size {
+        return Ok(Found::Nowhere);
+    }
+    let len = (step + pattern.len() - 1).min((size - from) as usize);
+    let mut buffer = vec![0; len];
+    let filled = read_at(source, from, &mut buffer)?;
+    buffer.truncate(filled);
+    if let Some(position) = buffer.windows(pattern.len()).position(|w| w == pattern) {
+        return Ok(Found::At(from + position as u64));
+    }
+    let next = from + step as u64;
+    if filled < len || next + pattern.len() as u64 > size {
+        Ok(Found::Nowhere)
+    } else {
+        Ok(Found::NotYet(next))
+    }
+}
+
+///Parses a decimal number or a 0x prefixed hex one
+pub fn parse_number(text: &str) -> Result<u64> {
+    let text = text.trim().replace('_', "");
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| anyhow!("{} is not a number", text))
+}
+
+///Parses hex bytes with or without spaces between them, or text in double quotes
+pub fn parse_pattern(text: &str) -> Result<Vec<u8>> {
+    let text = text.trim();
+    if let Some(quoted) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        if quoted.is_empty() {
+            return Err(anyhow!("nothing to search for"));
+        }
+        return Ok(quoted.as_bytes().to_vec());
+    }
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    //Slicing below goes by bytes, anything typed that isn't ASCII can't be a hex digit anyway
+    if !digits.is_ascii() {
+        return Err(anyhow!("{} is not hex, put text in double quotes", digits));
+    }
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err(anyhow!("hex bytes take two digits each"));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| anyhow!("{} is not a hex byte", &digits[i..i + 2])))
+        .collect()
+}
+
+///One line of the dump: offset, the bytes in hex and as ASCII with dots for anything unprintable
+pub fn format_line(offset: u64, bytes: &[u8]) -> String {
+    let mut hex = String::new();
+    for i in 0..BYTES_PER_LINE as usize {
+        match bytes.get(i) {
+            Some(byte) => hex.push_str(&format!("{:02x} ", byte)),
+            None => hex.push_str("   "),
+        }
+        if i == 7 {
+            hex.push(' ');
+        }
+    }
+    let ascii: String = bytes.iter().map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' }).collect();
+    format!("{:012x}  {}|{}|", offset, hex, ascii)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Opened and already deleted, the file goes away with the handle
+    fn source(name: &str, data: &[u8]) -> File {
+        let path = std::env::temp_dir().join(format!("minuteman-hexview-{}-{}", name, std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        let file = File::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        file
+    }
+
+    fn viewer(name: &str, data: &[u8]) -> HexViewer {
+        let partitions = vec![(PathBuf::from("/dev/test1"), 512), (PathBuf::from("/dev/test2"), 2048)];
+        HexViewer::new(Box::new(source(name, data)), Path::new("/dev/test"), data.len() as u64, 512, partitions)
+    }
+
+    #[test]
+    fn pages_and_jumps() {
+        let data: Vec<u8> = (0..4096).map(|i| (i / 16) as u8).collect();
+        let mut viewer = viewer("pages", &data);
+        viewer.set_rows(4);
+        assert_eq!(viewer.data, data[..64]);
+        viewer.page(1);
+        assert_eq!(viewer.offset, 64);
+        viewer.scroll(-10);
+        assert_eq!(viewer.offset, 0);
+        viewer.next_partition();
+        assert_eq!(viewer.offset, 512);
+        viewer.next_partition();
+        viewer.next_partition();
+        assert_eq!(viewer.offset, 512);
+        viewer.start_prompt(Prompt::Sector);
+        viewer.input.push('3');
+        viewer.submit();
+        assert_eq!((viewer.offset, viewer.data[0]), (1536, 96));
+        //Past the end shows the last page
+        viewer.go_to(1 << 40);
+        assert_eq!((viewer.offset, viewer.data.len()), (4032, 64));
+    }
+
+    #[test]
+    fn searches_across_steps() {
+        let mut data = vec![0; 10_000];
+        data[4094..4098].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        let pattern = [0xde, 0xad, 0xbe, 0xef];
+        let mut file = source("find", &data);
+        //Straddles the boundary between the first two steps
+        assert!(matches!(find(&mut file, 10_000, &pattern, 0, 4096).unwrap(), Found::At(4094)));
+        assert!(matches!(find(&mut file, 10_000, &pattern, 4095, 4096).unwrap(), Found::NotYet(8191)));
+        assert!(matches!(find(&mut file, 10_000, &pattern, 8191, 4096).unwrap(), Found::Nowhere));
+
+        let mut viewer = viewer("search", &data);
+        viewer.set_rows(4);
+        viewer.start_prompt(Prompt::Search);
+        viewer.input.push_str("de ad BE EF");
+        viewer.submit();
+        while viewer.searching.is_some() {
+            viewer.on_tick();
+        }
+        assert_eq!((viewer.found, viewer.offset), (Some(4094), 4080));
+        viewer.search_next();
+        while viewer.searching.is_some() {
+            viewer.on_tick();
+        }
+        assert_eq!(viewer.found, Some(4094));
+        assert_eq!(viewer.message.as_deref(), Some("Not found before the end"));
+    }
+
+    #[test]
+    fn parses_input() {
+        assert_eq!(parse_number("0x1f").unwrap(), 31);
+        assert_eq!(parse_number("1_000").unwrap(), 1000);
+        assert!(parse_number("abc").is_err());
+        assert_eq!(parse_pattern("55aa").unwrap(), vec![0x55, 0xaa]);
+        assert_eq!(parse_pattern("\"EFI PART\"").unwrap(), b"EFI PART".to_vec());
+        assert!(parse_pattern("5").is_err());
+        assert!(parse_pattern("aé1").is_err());
+        assert_eq!(parse_pattern("\"é\"").unwrap(), "é".as_bytes().to_vec());
+        assert_eq!(format_line(16, b"Hi\0"), format!("{:012x}  48 69 00 {}|Hi.|", 16, " ".repeat(13 * 3 + 1)));
+    }
+}
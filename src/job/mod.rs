@@ -0,0 +1,672 @@
+//Long running jobs (wipe, clone, burn) run on their own thread and publish their progress through a
+//shared `JobStatus` the ui polls on every tick
+
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+
+use crate::clone::{burn_image, create_disk_backup, device_size, verify_copy};
+use crate::disk::direct::IoOptions;
+use crate::disk::{partition_devices, Disk};
+use crate::sanitize::{open_sanitize_device, run_sanitize, SanitizeCommand};
+use crate::slack::deep_clean_partition;
+use crate::wipe::engine::{run_source, PatternSource, WipeProgress};
+use crate::wipe::free_space::wipe_free_space;
+use crate::wipe::{Pass, WipeMethod};
+
+mod simulate;
+
+//Throughput is sampled this often for the progress screen, keeping the last few minutes
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const HISTORY_LEN: usize = 300;
+
+/// Which part of a drive a Destroy job overwrites.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WipeScope {
+    EntireDrive,
+    FreeSpace, //Free space of every mounted partition, files are kept
+    Slack, //File tails and deleted metadata of unmounted partitions
+}
+
+impl WipeScope {
+    pub const ALL: [WipeScope; 3] = [WipeScope::EntireDrive, WipeScope::FreeSpace, WipeScope::Slack];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WipeScope::EntireDrive => "Entire drive",
+            WipeScope::FreeSpace => "Free space of mounted partitions",
+            WipeScope::Slack => "File slack and deleted entries (unmounted FAT32, exFAT, ext4)",
+        }
+    }
+
+    /// Short name used on the command line and in reports
+    pub fn key(&self) -> &'static str {
+        match self {
+            WipeScope::EntireDrive => "entire",
+            WipeScope::FreeSpace => "free-space",
+            WipeScope::Slack => "slack",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<WipeScope> {
+        WipeScope::ALL.iter().copied().find(|s| s.key() == key)
+    }
+
+    /// Whether the scope overwrites with a wipe method, deep cleaning only zeroes
+    pub fn uses_method(&self) -> bool {
+        *self != WipeScope::Slack
+    }
+}
+
+/// Work handed to a job thread.
+#[derive(Clone, Debug)]
+pub enum JobKind {
+    Wipe {
+        disk: Disk,
+        method: WipeMethod,
+        scope: WipeScope,
+        sanitize: Vec<SanitizeCommand>, //Firmware commands the drive supports
+        io: IoOptions,
+    },
+    Clone {
+        disk: Disk,
+        destination: PathBuf,
+        verify: bool,
+        io: IoOptions,
+    },
+    Burn {
+        image: PathBuf,
+        disk: Disk,
+        verify: bool,
+        io: IoOptions,
+    },
+}
+
+impl JobKind {
+    /// Drive the job reads from or writes to
+    pub fn disk(&self) -> &Disk {
+        match self {
+            JobKind::Wipe { disk, .. } | JobKind::Burn { disk, .. } | JobKind::Clone { disk, .. } => disk,
+        }
+    }
+
+    /// Drive the job writes to, if it overwrites one
+    pub fn target(&self) -> Option<&Disk> {
+        match self {
+            JobKind::Wipe { disk, .. } | JobKind::Burn { disk, .. } => Some(disk),
+            JobKind::Clone { .. } => None,
+        }
+    }
+}
+
+/// Progress of a job as last reported by its thread.
+#[derive(Clone, Debug, Default)]
+pub struct JobStatus {
+    pub stage: String,
+    pub progress: f64, //Ratio of the current stage
+    pub wipe: Option<WipeProgress>,
+    pub finished: bool,
+    pub error: Option<String>,
+    pub verify_failures: Vec<u64>, //Offsets of the chunks that did not read back, relative to what was verified
+    pub verified: bool, //Whether anything was read back
+    pub bytes_written: u64,
+    pub block_size: u64, //Bytes per write, verify failures are reported per block
+    pub started: Option<SystemTime>,
+    pub elapsed: Duration, //Set once finished
+    pub summary: Vec<String>, //Shown on the result screen
+    pub simulated: bool, //Dry run, nothing was written
+    pub estimated: Option<Duration>, //How long a dry run would have taken for real
+    pub bytes_done: u64, //Written and read back so far, counting every pass
+    pub bytes_total: u64, //What `bytes_done` ends up at, 0 when unknown
+    pub stage_total: u64, //Bytes of the current stage or pass
+    pub history: Vec<f64>, //Throughput in bytes per second, one sample per `SAMPLE_INTERVAL`
+    pub last_sample: Option<(Instant, u64)>,
+}
+
+impl JobStatus {
+    fn record(&mut self, done: u64) {
+        self.bytes_done = done;
+        let now = Instant::now();
+        match self.last_sample {
+            Some((at, before)) if now - at >= SAMPLE_INTERVAL => {
+                self.history.push(done.saturating_sub(before) as f64 / (now - at).as_secs_f64());
+                if self.history.len() > HISTORY_LEN {
+                    self.history.remove(0);
+                }
+                self.last_sample = Some((now, done));
+            }
+            Some(_) => {}
+            None => self.last_sample = Some((now, done)),
+        }
+    }
+
+    /// How long the job has been running
+    pub fn running_time(&self) -> Duration {
+        if self.finished {
+            return self.elapsed;
+        }
+        self.started.and_then(|s| s.elapsed().ok()).unwrap_or_default()
+    }
+
+    /// Throughput over the last few samples in bytes per second
+    pub fn current_rate(&self) -> Option<f64> {
+        let recent = &self.history[self.history.len().saturating_sub(5)..];
+        if recent.is_empty() {
+            None
+        } else {
+            Some(recent.iter().sum::<f64>() / recent.len() as f64)
+        }
+    }
+
+    /// Throughput since the job started in bytes per second
+    pub fn average_rate(&self) -> Option<f64> {
+        let elapsed = self.running_time().as_secs_f64();
+        if elapsed <= 0.0 || self.bytes_done == 0 {
+            None
+        } else {
+            Some(self.bytes_done as f64 / elapsed)
+        }
+    }
+
+    /// Time left for the whole job. Jobs that don't count bytes, like firmware erases, go by the ratio
+    /// of their stage instead.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.bytes_total > 0 {
+            let rate = self.average_rate()?;
+            return Some(Duration::from_secs_f64(self.bytes_total.saturating_sub(self.bytes_done) as f64 / rate));
+        }
+        if self.progress <= 0.0 {
+            return None;
+        }
+        let elapsed = self.running_time().as_secs_f64();
+        Some(Duration::from_secs_f64(elapsed * (1.0 - self.progress) / self.progress))
+    }
+
+    /// Time left for the current stage or pass, going by the recent throughput
+    pub fn stage_eta(&self) -> Option<Duration> {
+        if self.stage_total == 0 {
+            return None;
+        }
+        let rate = self.current_rate().or_else(|| self.average_rate())?;
+        let left = self.stage_total as f64 * (1.0 - self.progress.clamp(0.0, 1.0));
+        Some(Duration::from_secs_f64(left / rate.max(1.0)))
+    }
+}
+
+/// What a finished job did, written out by `--json` and kept for erasure reports.
+#[derive(Clone, Debug, Serialize)]
+pub struct JobReport {
+    pub kind: &'static str,
+    pub device: PathBuf,
+    pub image: Option<PathBuf>,
+    pub method: Option<String>,
+    pub scope: Option<&'static str>,
+    pub passes: Option<usize>,
+    pub bytes_written: u64,
+    pub duration_secs: f64,
+    pub verified: bool,
+    pub verify_failures: usize,
+    pub success: bool,
+    pub error: Option<String>,
+    pub summary: Vec<String>,
+    pub simulated: bool,
+    pub estimated_secs: Option<f64>,
+}
+
+impl JobReport {
+    pub fn new(kind: &JobKind, status: &JobStatus) -> JobReport {
+        let mut report = JobReport {
+            kind: "",
+            device: PathBuf::new(),
+            image: None,
+            method: None,
+            scope: None,
+            passes: None,
+            bytes_written: status.bytes_written,
+            duration_secs: status.elapsed.as_secs_f64(),
+            verified: status.verified,
+            verify_failures: status.verify_failures.len(),
+            success: status.error.is_none() && status.verify_failures.is_empty(),
+            error: status.error.clone(),
+            summary: status.summary.clone(),
+            simulated: status.simulated,
+            estimated_secs: status.estimated.map(|d| d.as_secs_f64()),
+        };
+        match kind {
+            JobKind::Wipe { disk, method, scope, .. } => {
+                report.kind = "wipe";
+                report.device = disk.name.clone();
+                report.scope = Some(scope.key());
+                if scope.uses_method() {
+                    report.method = Some(method.name.clone());
+                    if !method.is_firmware() {
+                        report.passes = Some(method.passes.len());
+                    }
+                }
+            }
+            JobKind::Clone { disk, destination, .. } => {
+                report.kind = "clone";
+                report.device = disk.name.clone();
+                report.image = Some(destination.clone());
+            }
+            JobKind::Burn { image, disk, .. } => {
+                report.kind = "burn";
+                report.device = disk.name.clone();
+                report.image = Some(image.clone());
+            }
+        }
+        report
+    }
+}
+
+pub struct Job {
+    status: Arc<Mutex<JobStatus>>,
+}
+
+impl Job {
+    pub fn spawn(kind: JobKind) -> Job {
+        Job::start(kind, false)
+    }
+
+    /// Runs `kind` as a dry run, devices are only ever opened for reading
+    pub fn simulate(kind: JobKind) -> Job {
+        Job::start(kind, true)
+    }
+
+    fn start(kind: JobKind, dry_run: bool) -> Job {
+        let status = Arc::new(Mutex::new(JobStatus { simulated: dry_run, ..JobStatus::default() }));
+        let shared = status.clone();
+        thread::spawn(move || {
+            let started = Instant::now();
+            let name = kind.disk().name.display().to_string();
+            log::info!("Started {}{}", describe(&kind), if dry_run { " as a dry run" } else { "" });
+            lock(&shared).started = Some(SystemTime::now());
+            let result = catch_panic(|| if dry_run { simulate::run(&kind, &shared) } else { run(&kind, &shared) });
+            let mut status = lock(&shared);
+            match result {
+                Ok(()) if status.verify_failures.is_empty() => log::info!("Finished with {} in {:?}", name, started.elapsed()),
+                Ok(()) => log::warn!("{} blocks of {} did not read back", status.verify_failures.len(), name),
+                Err(e) => {
+                    log::error!("Job on {} failed: {}", name, e);
+                    status.error = Some(e.to_string());
+                }
+            }
+            status.elapsed = started.elapsed();
+            status.finished = true;
+        });
+        Job { status }
+    }
+
+    pub fn status(&self) -> JobStatus {
+        lock(&self.status).clone()
+    }
+
+    /// Job that never runs and just reports `status`, used to drive the app in tests
+    #[cfg(test)]
+    pub fn with_status(status: JobStatus) -> Job {
+        Job { status: Arc::new(Mutex::new(status)) }
+    }
+}
+
+//What a job is about to do, for the log
+fn describe(kind: &JobKind) -> String {
+    match kind {
+        JobKind::Wipe { disk, method, scope, .. } => {
+            format!("wiping {} ({}, {})", disk.name.display(), scope.name(), method.name)
+        }
+        JobKind::Clone { disk, destination, .. } => format!("cloning {} to {}", disk.name.display(), destination.display()),
+        JobKind::Burn { image, disk, .. } => format!("burning {} to {}", image.display(), disk.name.display()),
+    }
+}
+
+//A job that panicked halfway through an update leaves the status poisoned, what's in it is still worth showing
+fn lock(status: &Mutex<JobStatus>) -> MutexGuard<'_, JobStatus> {
+    status.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+//Turns a panic on the job thread into an error, otherwise the job would never be marked finished
+fn catch_panic<F: FnOnce() -> Result<()>>(f: F) -> Result<()> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|m| m.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("unknown error"));
+        Err(anyhow!("the job crashed: {}", message))
+    })
+}
+
+fn update<F: FnOnce(&mut JobStatus)>(status: &Mutex<JobStatus>, f: F) {
+    f(&mut lock(status));
+}
+
+fn ratio(done: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (done as f64 / total as f64).min(1.0)
+    }
+}
+
+///Progress of a pass, `base` is what was written and done before this wipe started. Free space wipes
+/// run one per partition.
+fn wipe_progress(status: &Mutex<JobStatus>, progress: &WipeProgress, passes: &[Pass], base: (u64, u64)) {
+    let earlier = &passes[..progress.pass - 1];
+    let size = progress.bytes_total;
+    let (written, read) = if progress.verifying {
+        (size, progress.bytes_done)
+    } else {
+        (progress.bytes_done, 0)
+    };
+    let written = earlier.len() as u64 * size + written;
+    let read = earlier.iter().filter(|p| p.verify).count() as u64 * size + read;
+    update(status, |s| {
+        s.bytes_written = base.0 + written;
+        s.stage_total = size;
+        s.record(base.1 + written + read);
+        s.stage = format!(
+            "{} pass {} of {}",
+            if progress.verifying { "Verifying" } else { "Writing" },
+            progress.pass,
+            progress.total_passes
+        );
+        s.progress = ratio(progress.bytes_done, progress.bytes_total);
+        s.wipe = Some(progress.clone());
+    });
+}
+
+///What was done and written so far, for wipes that go on from there
+fn totals(status: &Mutex<JobStatus>) -> (u64, u64) {
+    let s = lock(status);
+    (s.bytes_written, s.bytes_done)
+}
+
+///Progress of copying `stage_total` bytes or reading them back after they were written
+fn copy_progress(status: &Mutex<JobStatus>, done: u64, stage_total: u64, verifying: bool) {
+    update(status, |s| {
+        s.progress = ratio(done, stage_total);
+        s.stage_total = stage_total;
+        if verifying {
+            let written = s.bytes_written;
+            s.record(written + done);
+        } else {
+            s.bytes_written = done;
+            s.record(done);
+        }
+    });
+}
+
+fn run(kind: &JobKind, status: &Mutex<JobStatus>) -> Result<()> {
+    match kind {
+        JobKind::Wipe { disk, method, scope, sanitize, io } => run_wipe(disk, method, *scope, sanitize, io, status),
+        JobKind::Clone { disk, destination, verify, io } => {
+            let size = device_size(&disk.name)?;
+            let block = io.block_size_for(&disk.name);
+            update(status, |s| {
+                s.stage = format!("Cloning {}", disk.name.display());
+                s.block_size = block as u64;
+                s.bytes_total = if *verify { size * 2 } else { size };
+            });
+            let copied = create_disk_backup(&disk.name, destination, io, |done| copy_progress(status, done, size, false))?;
+            update(status, |s| s.bytes_written = copied);
+            if *verify {
+                update(status, |s| s.stage = String::from("Verifying image"));
+                let failures = verify_copy(&disk.name, destination, copied, io, |done| copy_progress(status, done, copied, true))?;
+                update(status, |s| {
+                    s.verified = true;
+                    s.verify_failures = failures;
+                });
+            }
+            update(status, |s| s.summary.push(format!("Cloned {} bytes to {}", copied, destination.display())));
+            Ok(())
+        }
+        JobKind::Burn { image, disk, verify, io } => {
+            ensure_unmounted(disk)?;
+            let size = device_size(image)?;
+            let block = io.block_size_for(&disk.name);
+            update(status, |s| {
+                s.stage = format!("Burning {}", image.display());
+                s.block_size = block as u64;
+                s.bytes_total = if *verify { size * 2 } else { size };
+            });
+            let written = burn_image(image, &disk.name, io, |done| copy_progress(status, done, size, false))?;
+            update(status, |s| s.bytes_written = written);
+            if *verify {
+                update(status, |s| s.stage = String::from("Verifying drive"));
+                let failures = verify_copy(image, &disk.name, written, io, |done| copy_progress(status, done, written, true))?;
+                update(status, |s| {
+                    s.verified = true;
+                    s.verify_failures = failures;
+                });
+            }
+            update(status, |s| s.summary.push(format!("Wrote {} bytes to {}", written, disk.name.display())));
+            Ok(())
+        }
+    }
+}
+
+//Swap, FAT16 or encrypted partitions and damaged filesystems can't be deep cleaned but shouldn't stop the
+//other partitions from being cleaned, only errors reaching the drive itself do
+fn can_skip(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<std::io::Error>().is_none()
+}
+
+fn deep_clean_partitions(status: &Mutex<JobStatus>, disk: &Disk, dry_run: bool) -> Result<()> {
+    for partition in partition_devices(&disk.name) {
+        update(status, |s| s.stage = format!("Cleaning {}", partition.display()));
+        let summary = match deep_clean_partition(&partition, dry_run, |done, total| update(status, |s| s.progress = ratio(done, total))) {
+            Ok(summary) => summary,
+            Err(e) if can_skip(&e) => {
+                update(status, |s| s.summary.push(format!("{}: skipped, {}", partition.display(), e)));
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let verb = if dry_run { "would clear" } else { "cleared" };
+        update(status, |s| {
+            s.summary.push(format!(
+                "{}: {} {} slack bytes, {} directory entries, {} inodes",
+                partition.display(),
+                verb,
+                summary.slack_bytes,
+                summary.entries_cleared,
+                summary.inodes_cleared
+            ))
+        });
+    }
+    Ok(())
+}
+
+//Overwriting a drive under a mounted filesystem corrupts it and leaves the kernel writing stale data
+//back, opening the drive exclusively catches what was mounted after the drive list was read
+fn ensure_unmounted(disk: &Disk) -> Result<()> {
+    if disk.partitions.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<&str> = disk.partitions.iter().map(|p| p.name.as_str()).collect();
+    Err(anyhow!("{} has mounted partitions ({}), unmount them first", disk.name.display(), names.join(", ")))
+}
+
+//Discard after the overwrite passes of a method that asks for it. Drives without discard support keep
+//the overwrite and only get a note, the passes already did what the method promises
+fn discard_drive(status: &Mutex<JobStatus>, disk: &Disk, dry_run: bool) -> Result<()> {
+    if !disk.supports_discard() {
+        update(status, |s| s.summary.push(format!("{} doesn't support discard, skipped it", disk.name.display())));
+        return Ok(());
+    }
+    let command = SanitizeCommand::Discard;
+    update(status, |s| s.stage = format!("Running {}", command.name()));
+    if dry_run {
+        update(status, |s| s.summary.push(format!("Would run {}", command.name())));
+        return Ok(());
+    }
+    let mut device = open_sanitize_device(&disk.name)?;
+    run_sanitize(&mut device, command, Duration::from_secs(1), |p| update(status, |s| s.progress = p))?;
+    update(status, |s| s.summary.push(format!("{} completed", command.name())));
+    Ok(())
+}
+
+fn run_wipe(
+    disk: &Disk,
+    method: &WipeMethod,
+    scope: WipeScope,
+    sanitize: &[SanitizeCommand],
+    io: &IoOptions,
+    status: &Mutex<JobStatus>,
+) -> Result<()> {
+    match scope {
+        WipeScope::EntireDrive if method.is_firmware() => {
+            ensure_unmounted(disk)?;
+            let command = method
+                .sanitize_command(sanitize)
+                .ok_or_else(|| anyhow!("{} supports none of the commands of {}", disk.name.display(), method.name))?;
+            update(status, |s| s.stage = format!("Running {}", command.name()));
+            let mut device = open_sanitize_device(&disk.name)?;
+            run_sanitize(&mut device, command, Duration::from_secs(1), |p| update(status, |s| s.progress = p))?;
+            update(status, |s| s.summary.push(format!("{} completed", command.name())));
+        }
+        WipeScope::EntireDrive => {
+            ensure_unmounted(disk)?;
+            let mut device = io.open_drive(&disk.name)?;
+            let size = device_size(&disk.name)?;
+            let block = io.block_size_for(&disk.name);
+            update(status, |s| {
+                s.block_size = block as u64;
+                s.bytes_total = size * method.rounds();
+            });
+            let source = PatternSource::new(&method.passes);
+            let outcome = run_source(&mut *device, size, &source, block, |p| wipe_progress(status, p, &method.passes, (0, 0)))?;
+            update(status, |s| {
+                s.bytes_written = outcome.bytes_written;
+                s.verified = outcome.verified;
+                s.verify_failures = outcome.verify_failures;
+                s.summary.push(format!("Wrote {} bytes in {} passes", outcome.bytes_written, method.passes.len()));
+            });
+            if method.discard {
+                drop(device);
+                discard_drive(status, disk, false)?;
+            }
+        }
+        WipeScope::FreeSpace => {
+            if disk.partitions.is_empty() {
+                return Err(anyhow!("{} has no mounted partitions", disk.name.display()));
+            }
+            let free: u64 = disk.partitions.iter().map(|p| p.free).sum();
+            update(status, |s| s.bytes_total = free * method.rounds());
+            for partition in disk.partitions.iter() {
+                let base = totals(status);
+                let outcome = wipe_free_space(partition, method, |p| wipe_progress(status, p, &method.passes, base))?;
+                update(status, |s| {
+                    s.bytes_written = base.0 + outcome.bytes_written;
+                    s.verified |= outcome.verified;
+                    s.verify_failures.extend(outcome.verify_failures);
+                    s.summary.push(format!("{}: wrote {} bytes of free space", partition.name, outcome.bytes_written));
+                });
+            }
+        }
+        WipeScope::Slack => deep_clean_partitions(status, disk, false)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::{test_disk, Partition};
+    use crate::wipe::Pattern;
+
+    #[test]
+    fn wipe_progress_counts_every_round() {
+        let status = Mutex::new(JobStatus::default());
+        let passes = vec![
+            Pass { pattern: Pattern::Byte(0), verify: true },
+            Pass { pattern: Pattern::Random, verify: false },
+        ];
+        let progress = WipeProgress {
+            pass: 2,
+            total_passes: 2,
+            pattern: Pattern::Random,
+            verifying: false,
+            bytes_done: 10,
+            bytes_total: 100,
+        };
+        wipe_progress(&status, &progress, &passes, (1000, 2000));
+        let status = status.into_inner().unwrap();
+        assert_eq!(status.bytes_written, 1000 + 110);
+        assert_eq!(status.bytes_done, 2000 + 210);
+        assert_eq!(status.stage_total, 100);
+    }
+
+    #[test]
+    fn eta_goes_by_bytes_or_ratio() {
+        let mut status = JobStatus {
+            started: Some(SystemTime::now() - Duration::from_secs(10)),
+            bytes_done: 100,
+            bytes_total: 300,
+            ..JobStatus::default()
+        };
+        let eta = status.eta().unwrap().as_secs_f64();
+        assert!((19.0..=20.5).contains(&eta), "{}", eta);
+        //Firmware erases only report how far along they are
+        status.bytes_total = 0;
+        status.progress = 0.5;
+        let eta = status.eta().unwrap().as_secs_f64();
+        assert!((9.5..=10.5).contains(&eta), "{}", eta);
+    }
+
+    #[test]
+    fn mounted_drives_are_never_overwritten() {
+        let mut disk = test_disk("/dev/minuteman-test");
+        disk.partitions.push(Partition {
+            name: String::from("/dev/minuteman-test1"),
+            mount_point: PathBuf::from("/media/stick"),
+            file_system: String::from("vfat"),
+            free: 0,
+            total: 0,
+            read_only: false,
+        });
+        let io = IoOptions::default();
+        let wipe = JobKind::Wipe {
+            disk: disk.clone(),
+            method: WipeMethod::new("Zero", "", vec![Pass { pattern: Pattern::Byte(0), verify: false }]),
+            scope: WipeScope::EntireDrive,
+            sanitize: Vec::new(),
+            io,
+        };
+        let burn = JobKind::Burn { image: PathBuf::from("minuteman-test.img"), disk, verify: false, io };
+        for kind in [wipe, burn] {
+            for result in [run(&kind, &Mutex::new(JobStatus::default())), simulate::run(&kind, &Mutex::new(JobStatus::default()))] {
+                let error = result.unwrap_err().to_string();
+                assert_eq!(error, "/dev/minuteman-test has mounted partitions (/dev/minuteman-test1), unmount them first");
+            }
+        }
+    }
+
+    #[test]
+    fn only_drive_errors_stop_a_deep_clean() {
+        let io: anyhow::Error = std::io::Error::from(std::io::ErrorKind::PermissionDenied).into();
+        assert!(!can_skip(&io));
+        assert!(!can_skip(&io.context("opening /dev/sdb1")));
+        assert!(can_skip(&anyhow!("no FAT32, exFAT or ext4 filesystem found")));
+        assert!(can_skip(&anyhow!("/dev/sdb1 is mounted, unmount it before deep cleaning")));
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        let status = Mutex::new(JobStatus::default());
+        let result = catch_panic(|| {
+            update(&status, |_| panic!("index out of bounds"));
+            Ok(())
+        });
+        assert_eq!(result.unwrap_err().to_string(), "the job crashed: index out of bounds");
+        //The panic poisoned the status, it's still readable
+        assert!(status.is_poisoned());
+        lock(&status).finished = true;
+        assert!(lock(&status).finished);
+    }
+}
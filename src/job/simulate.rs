@@ -0,0 +1,145 @@
+//Dry runs go through the same stages as a real job, against simulated drives and read only handles,
+//so progress, summaries and reports look the same without anything reaching a device
+
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::{copy_progress, deep_clean_partitions, discard_drive, ensure_unmounted, totals, update, wipe_progress, JobKind, JobStatus, WipeScope};
+use crate::bench::sequential_read;
+use crate::clone::{device_size, image_fits, read_through};
+use crate::disk::direct::IoOptions;
+use crate::disk::simulated::SimulatedDrive;
+use crate::disk::{calculate_partition_size, Disk};
+use crate::sanitize::SanitizeCommand;
+use crate::wipe::engine::{run_source, PatternSource, CHUNK_SIZE};
+use crate::wipe::WipeMethod;
+
+///Records how long moving `bytes` over `path` would take on the real drive
+fn estimate(status: &Mutex<JobStatus>, path: &Path, io: &IoOptions, bytes: u64) {
+    if let Ok(throughput) = sequential_read(path, io) {
+        let estimated = Duration::from_secs_f64(bytes as f64 / throughput);
+        update(status, |s| {
+            s.estimated = Some(estimated);
+            s.summary.push(format!("Estimated {}s at {:.1} MB/s", estimated.as_secs(), throughput / 1e6));
+        });
+    }
+}
+
+pub(super) fn run(kind: &JobKind, status: &Mutex<JobStatus>) -> Result<()> {
+    match kind {
+        JobKind::Wipe { disk, method, scope, sanitize, io } => run_wipe(disk, method, *scope, sanitize, io, status),
+        JobKind::Clone { disk, destination, verify, io } => {
+            let size = device_size(&disk.name)?;
+            update(status, |s| {
+                s.stage = format!("Cloning {}", disk.name.display());
+                s.bytes_total = if *verify { size * 2 } else { size };
+            });
+            let copied = read_through(&disk.name, size, io, |done| copy_progress(status, done, size, false))?;
+            update(status, |s| s.bytes_written = copied);
+            if *verify {
+                //Nothing was copied, reading the source again is all a verify would have to do here
+                update(status, |s| s.stage = String::from("Verifying image"));
+                read_through(&disk.name, copied, io, |done| copy_progress(status, done, copied, true))?;
+                update(status, |s| s.verified = true);
+            }
+            update(status, |s| s.summary.push(format!("Would clone {} bytes to {}", copied, destination.display())));
+            estimate(status, &disk.name, io, if *verify { copied * 2 } else { copied });
+            Ok(())
+        }
+        JobKind::Burn { image, disk, verify, io } => {
+            ensure_unmounted(disk)?;
+            let size = image_fits(image, &disk.name)?;
+            update(status, |s| {
+                s.stage = format!("Burning {}", image.display());
+                s.bytes_total = if *verify { size * 2 } else { size };
+            });
+            let written = read_through(image, size, io, |done| copy_progress(status, done, size, false))?;
+            update(status, |s| s.bytes_written = written);
+            if *verify {
+                update(status, |s| s.stage = String::from("Verifying drive"));
+                read_through(image, written, io, |done| copy_progress(status, done, written, true))?;
+                update(status, |s| s.verified = true);
+            }
+            update(status, |s| s.summary.push(format!("Would write {} bytes to {}", written, disk.name.display())));
+            estimate(status, &disk.name, io, if *verify { written * 2 } else { written });
+            Ok(())
+        }
+    }
+}
+
+fn run_wipe(
+    disk: &Disk,
+    method: &WipeMethod,
+    scope: WipeScope,
+    sanitize: &[SanitizeCommand],
+    io: &IoOptions,
+    status: &Mutex<JobStatus>,
+) -> Result<()> {
+    match scope {
+        WipeScope::EntireDrive if method.is_firmware() => {
+            ensure_unmounted(disk)?;
+            //The drive does the work, all there is to check is that it would accept the command
+            let command = method
+                .sanitize_command(sanitize)
+                .ok_or_else(|| anyhow!("{} supports none of the commands of {}", disk.name.display(), method.name))?;
+            update(status, |s| {
+                s.stage = format!("Running {}", command.name());
+                s.progress = 1.0;
+                s.summary.push(format!("Would run {}", command.name()));
+            });
+        }
+        WipeScope::EntireDrive => {
+            ensure_unmounted(disk)?;
+            let size = device_size(&disk.name)?;
+            let source = PatternSource::new(&method.passes);
+            let block = io.block_size_for(&disk.name);
+            update(status, |s| {
+                s.block_size = block as u64;
+                s.bytes_total = size * method.rounds();
+            });
+            let mut device = SimulatedDrive::new(size, block, source.clone());
+            let outcome = run_source(&mut device, size, &source, block, |p| wipe_progress(status, p, &method.passes, (0, 0)))?;
+            update(status, |s| {
+                s.bytes_written = outcome.bytes_written;
+                s.verified = outcome.verified;
+                s.verify_failures = outcome.verify_failures;
+                s.summary.push(format!("Would write {} bytes in {} passes", outcome.bytes_written, method.passes.len()));
+            });
+            estimate(status, &disk.name, io, size * method.rounds());
+            if method.discard {
+                discard_drive(status, disk, true)?;
+            }
+        }
+        WipeScope::FreeSpace => {
+            if disk.partitions.is_empty() {
+                return Err(anyhow!("{} has no mounted partitions", disk.name.display()));
+            }
+            if method.is_firmware() {
+                return Err(anyhow!("{} erases whole drives and can't wipe free space", method.name));
+            }
+            let free: u64 = disk.partitions.iter().map(|p| p.free).sum();
+            update(status, |s| s.bytes_total = free * method.rounds());
+            for partition in disk.partitions.iter() {
+                if partition.read_only {
+                    return Err(anyhow!("{} is mounted read only", partition.name));
+                }
+                let (_, free) = calculate_partition_size(&partition.mount_point)?;
+                let source = PatternSource::new(&method.passes);
+                let mut space = SimulatedDrive::new(free, CHUNK_SIZE, source.clone());
+                let base = totals(status);
+                let outcome = run_source(&mut space, free, &source, CHUNK_SIZE, |p| wipe_progress(status, p, &method.passes, base))?;
+                update(status, |s| {
+                    s.bytes_written = base.0 + outcome.bytes_written;
+                    s.verified |= outcome.verified;
+                    s.verify_failures.extend(outcome.verify_failures);
+                    s.summary.push(format!("{}: would write {} bytes of free space", partition.name, outcome.bytes_written));
+                });
+            }
+            estimate(status, &disk.name, io, totals(status).0);
+        }
+        WipeScope::Slack => deep_clean_partitions(status, disk, true)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,213 @@
+//Logging for everything that isn't drawn by the UI. Printing would scribble over the TUI, so records go
+//to a rotating log file and a buffer the log pane shows instead, and to stderr only when running headless.
+
+use anyhow::Result;
+use chrono::Local;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+//The log file is rotated once it grows past this, keeping this many old ones next to it
+const MAX_FILE_SIZE: u64 = 1 << 20;
+const KEPT_FILES: usize = 3;
+//Lines the log pane can scroll back through
+const BUFFER_LINES: usize = 1000;
+
+/// One record as the log pane shows it.
+#[derive(Clone, Debug)]
+pub struct LogLine {
+    pub time: String,
+    pub level: Level,
+    pub message: String,
+}
+
+impl std::fmt::Display for LogLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {:<5} {}", self.time, self.level, self.message)
+    }
+}
+
+/// Latest records, shared between the logger and the log pane.
+#[derive(Clone, Default)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<LogLine>>>,
+    problems: Arc<Mutex<usize>>, //Warnings and errors logged so far
+}
+
+impl LogBuffer {
+    fn push(&self, line: LogLine) {
+        if line.level <= Level::Warn {
+            *self.problems.lock().unwrap() += 1;
+        }
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == BUFFER_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    ///Copy of the buffered records, oldest first
+    pub fn lines(&self) -> Vec<LogLine> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn problems(&self) -> usize {
+        *self.problems.lock().unwrap()
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> Result<LogFile> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile { path: path.to_path_buf(), file, size })
+    }
+
+    fn write(&mut self, line: &str) -> std::io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    //minuteman.log becomes minuteman.log.1, .1 becomes .2 and so on, the oldest one is dropped
+    fn rotate(&mut self) -> std::io::Result<()> {
+        for i in (1..KEPT_FILES).rev() {
+            //Older files don't exist until the log rotated that many times
+            let _ = std::fs::rename(rotated(&self.path, i), rotated(&self.path, i + 1));
+        }
+        std::fs::rename(&self.path, rotated(&self.path, 1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn rotated(path: &Path, number: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", number));
+    PathBuf::from(name)
+}
+
+struct Logger {
+    level: LevelFilter,
+    buffer: LogBuffer,
+    file: Option<Mutex<LogFile>>,
+    stderr: bool, //Headless runs, warnings and errors are echoed
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = LogLine {
+            time: Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            level: record.level(),
+            message: record.args().to_string(),
+        };
+        if let Some(file) = &self.file {
+            //There's nowhere left to report a log file that can't be written to
+            let _ = file.lock().unwrap().write(&line.to_string());
+        }
+        if self.stderr && line.level <= Level::Warn {
+            eprintln!("{}", line);
+        }
+        self.buffer.push(line);
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().file.flush();
+        }
+    }
+}
+
+///Where the log file goes unless another one is picked, ~/.local/state/minuteman/minuteman.log
+pub fn default_log_path() -> Option<PathBuf> {
+    let state_dir = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(state_dir.join("minuteman").join("minuteman.log"))
+}
+
+///Installs the logger for the whole program. Records at `level` or above go to `file` and, when
+/// `stderr` is set, warnings and errors to stderr too. The returned buffer keeps the latest ones.
+pub fn init(level: LevelFilter, file: Option<&Path>, stderr: bool) -> LogBuffer {
+    let buffer = LogBuffer::default();
+    let (file, error) = match file.map(LogFile::open) {
+        Some(Ok(file)) => (Some(Mutex::new(file)), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    let logger = Logger { level, buffer: buffer.clone(), file, stderr };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+    if let Some(e) = error {
+        log::warn!("Could not open the log file: {}", e);
+    }
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(logger: &Logger, level: Level, message: &str) {
+        logger.log(&Record::builder().level(level).args(format_args!("{}", message)).build());
+    }
+
+    #[test]
+    fn filters_and_buffers_records() {
+        let logger = Logger { level: LevelFilter::Info, buffer: LogBuffer::default(), file: None, stderr: false };
+        record(&logger, Level::Debug, "hidden");
+        record(&logger, Level::Info, "shown");
+        record(&logger, Level::Error, "failed");
+        let lines = logger.buffer.lines();
+        assert_eq!(lines.iter().map(|l| l.message.as_str()).collect::<Vec<_>>(), vec!["shown", "failed"]);
+        assert_eq!(logger.buffer.problems(), 1);
+        for i in 0..BUFFER_LINES {
+            record(&logger, Level::Info, &i.to_string());
+        }
+        assert_eq!(logger.buffer.lines().len(), BUFFER_LINES);
+        assert_eq!(logger.buffer.lines()[0].message, "0");
+    }
+
+    #[test]
+    fn log_files_rotate() {
+        let directory = std::env::temp_dir().join(format!("minuteman-log-{}", std::process::id()));
+        let path = directory.join("test.log");
+        let mut file = LogFile::open(&path).unwrap();
+        let line = "x".repeat(1000);
+        //Enough for the log to rotate more often than old files are kept
+        for _ in 0..(KEPT_FILES + 2) * MAX_FILE_SIZE as usize / 1000 {
+            file.write(&line).unwrap();
+        }
+        let kept: Vec<bool> = (1..=KEPT_FILES + 1).map(|i| rotated(&path, i).exists()).collect();
+        let size = std::fs::metadata(&path).unwrap().len();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(kept, vec![true, true, true, false]);
+        assert!(size <= MAX_FILE_SIZE);
+    }
+}
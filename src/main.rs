@@ -1,13 +1,23 @@
 #[allow(dead_code)]
 mod disk;
 mod app;
+mod bench;
+mod cli;
 mod ui;
 mod util;
 mod clone;
+mod hexview;
+mod job;
+mod logging;
+mod report;
+mod sanitize;
+mod shred;
+mod slack;
+mod wipe;
 
-use crate::clone::create_disk_backup;
 
 use crate::disk::{ find_external_disks };
+use crate::disk::direct::{parse_block_size, IoOptions, DEFAULT_BLOCK_SIZE, DEFAULT_QUEUE_DEPTH};
 use argh::FromArgs;
 use crate::app::{App};
 use crate::ui::Ui;
@@ -16,6 +26,11 @@ use crate::util::event::Config;
 use crate::util::{
     event::{Event, Events},
 };
+use crate::wipe::config::{default_methods_path, load_methods, merge_methods};
+use crate::logging::default_log_path;
+use crate::wipe::WipeMethod;
+use log::LevelFilter;
+use std::path::PathBuf;
 use std::{error::Error, io};
 use termion::{event::Key, input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
 use tui::{backend::TermionBackend, Terminal};
@@ -24,24 +39,48 @@ use tui::{backend::TermionBackend, Terminal};
 
 
 
-//Deletion algorithm options
-const WIPE_METHODS: [&str; 8] = [
-    "British HMG IS5 (1 rewrite and 1 verify)",
-    "Russian GOST P50739-95 (2 rewrites)",
-    "NAVSO P-5239-26 (RLL), (3 rewrites and 1 verify)",
-    "NAVSO P-5239-26 (ALT), (3 rewrites and 1 verify)",
-    "Department of Defense (DoD, USA 5220.22-M) (3 rewrites and 3 verify)",
-    "Department of Defense (DoD, USA 5220.22-M ECE) (7 rewrites)",
-    "Canadian RCMP TSSIT OPS-II (7 rewrites)",
-    "German VSITR (7 rewrites)",
-];
-
 ///Minuteman CLI
 #[derive(Debug, FromArgs)]
 struct Cli {
     /// whether unicode symbols are used to improve the overall look of the app
     #[argh(option, default = "true")]
     enhanced_graphics: bool,
+    /// path to a TOML file with custom wipe methods, defaults to ~/.config/minuteman/methods.toml
+    #[argh(option)]
+    methods: Option<PathBuf>,
+    /// simulate jobs without writing to any device or file
+    #[argh(switch)]
+    dry_run: bool,
+    /// bytes per read and write when cloning, burning and wiping, e.g. 4M, defaults to 1M
+    #[argh(option, default = "DEFAULT_BLOCK_SIZE", from_str_fn(parse_block_size))]
+    block_size: usize,
+    /// go through the page cache instead of opening drives with O_DIRECT
+    #[argh(switch)]
+    no_direct: bool,
+    /// writes kept in flight when wiping with io_uring (uring builds only), 0 to turn it off
+    #[argh(option, default = "DEFAULT_QUEUE_DEPTH")]
+    queue_depth: u32,
+    /// least severe messages logged: error, warn, info, debug or trace, defaults to info
+    #[argh(option, default = "LevelFilter::Info")]
+    log_level: LevelFilter,
+    /// file messages are logged to, defaults to ~/.local/state/minuteman/minuteman.log
+    #[argh(option)]
+    log_file: Option<PathBuf>,
+    #[argh(subcommand)]
+    command: Option<cli::Command>,
+}
+
+///Built in wipe methods followed by the user defined ones. A methods file passed explicitly has to
+/// exist, the default one is optional.
+fn load_wipe_methods(path: Option<PathBuf>) -> anyhow::Result<Vec<WipeMethod>> {
+    let custom = match path {
+        Some(path) => load_methods(&path)?,
+        None => match default_methods_path() {
+            Some(path) if path.exists() => load_methods(&path)?,
+            _ => Vec::new(),
+        },
+    };
+    merge_methods(wipe::builtin_methods(), custom)
 }
 
 
@@ -49,25 +88,40 @@ struct Cli {
 fn main() -> Result<(), Box<dyn Error>> {
     let cli: Cli = argh::from_env();
 
+    //Report broken method specs before taking over the terminal
+    let methods = match load_wipe_methods(cli.methods) {
+        Ok(methods) => methods,
+        Err(e) => {
+            eprintln!("Invalid wipe methods:\n{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    //Headless runs have the terminal to themselves, the TUI shows messages in its log pane instead
+    let log_file = cli.log_file.or_else(default_log_path);
+    let log = logging::init(cli.log_level, log_file.as_deref(), cli.command.is_some());
+
+    let io_options = IoOptions { block_size: cli.block_size, direct: !cli.no_direct, queue_depth: cli.queue_depth };
+
+    //Subcommands run headless and never take over the terminal
+    if let Some(command) = cli.command {
+        if let Err(e) = cli::run(command, methods, cli.dry_run, io_options) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     
     //Instaniate disk get method here returns a vector of drives available to use
-    let disks = find_external_disks();
-    // for disk in disks.iter() {
-    //     println!("DISK !{:?}", disk);
-    // }
-
-    let first_disk = &disks.unwrap()[0];
-    let size = &first_disk.free_space;
-    let name = &first_disk.name;
+    let disks = find_external_disks()?;
 
-    let msg = create_disk_backup(name, size);
-    
-    // let clone = create_disk_backup(std::path::PathBuf::from("/dev/sda1"));
-    // println!("{:?}", clone);
-    let events = Events::with_config(Config {
+    let mut events = Events::with_config(Config {
         // tick_rate: Duration::from_millis(cli.tick_rate),
         ..Config::default()
     });
+    //The app decides when quitting is allowed, the input thread must keep running while a job is
+    events.disable_exit_key();
 
     // Terminal initialization
     let stdout = io::stdout().into_raw_mode()?;
@@ -80,53 +134,87 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 
     // Create a new app
-    // let mut app = App::new(disks.unwrap(), WIPE_METHODS.to_vec(), "Minuteman");
+    let mut app = App::new(disks, methods, "Minuteman");
+    app.enhanced_graphics = cli.enhanced_graphics;
+    app.debug_mode = cli.dry_run;
+    app.io = io_options;
+    app.report_dir = std::env::current_dir().ok();
+    app.log = log;
     
-    // loop {
-    //     terminal.draw(|f| Ui::draw(f, &mut app))?;
-
-    //     // This is the main event handler where user input is handled and dispatched according to the app state 
-    //     match events.next()? {
-    //         Event::Input(input) => match input {
-    //             Key::Down => {
-    //                 app.on_down();
-    //             }
-    //             Key::Up => {
-    //                 app.on_up();
-    //             }
-    //             Key::Left => {
-    //                 app.on_left();
-    //             }
-    //             Key::Right => {
-    //                 app.on_right();
-    //             }
-    //             Key::Char('q') => {
-    //                 app.quit();
-    //             }
-    //             Key::Esc => {
-    //                 app.quit();
-    //             }
-    //             Key::Char('e') => {
-    //                 app.on_continue();
-    //             }
-    //             Key::Char('c') => {
-    //                 app.on_back();
-    //             }
-    //             _ => {}
-    //         },
-    //         Event::Tick => {
-    //             if app.status.index == 3 {
-    //                 //Simulate app progress here
-    //                 app.deletion_progress += 0.008;
-                    
-    //             }
-                
-    //         }   
-    //     }
-    //     if app.should_quit {
-    //         break;
-    //     }
-    // }
+    loop {
+        terminal.draw(|f| Ui::draw(f, &mut app))?;
+
+        // This is the main event handler where user input is handled and dispatched according to the app state 
+        match events.next()? {
+            //Keys spell out the confirmation code instead of acting as shortcuts
+            Event::Input(input) if app.is_typing() => match input {
+                Key::Char('\n') => {
+                    app.on_continue();
+                }
+                Key::Char(c) => {
+                    app.on_char(c);
+                }
+                Key::Backspace => {
+                    app.on_backspace();
+                }
+                Key::Left => {
+                    app.on_left();
+                }
+                Key::Right => {
+                    app.on_right();
+                }
+                Key::Esc => {
+                    app.on_back();
+                }
+                _ => {}
+            },
+            Event::Input(input) => match input {
+                Key::Down => {
+                    app.on_down();
+                }
+                Key::Up => {
+                    app.on_up();
+                }
+                Key::Left => {
+                    app.on_left();
+                }
+                Key::Right => {
+                    app.on_right();
+                }
+                Key::Char('q') => {
+                    app.quit();
+                }
+                Key::Esc => {
+                    app.quit();
+                }
+                Key::Char('e') => {
+                    app.on_continue();
+                }
+                Key::Char('c') => {
+                    app.on_back();
+                }
+                Key::Char(' ') => {
+                    app.on_toggle();
+                }
+                Key::PageUp => {
+                    app.on_page_up();
+                }
+                Key::PageDown => {
+                    app.on_page_down();
+                }
+                Key::Char(c) => {
+                    app.on_shortcut(c);
+                }
+                _ => {}
+            },
+            Event::Tick => {
+                app.on_tick();
+            }   
+        }
+        if app.should_quit {
+            break;
+        }
+    }
     Ok(())
 }
 
@@ -0,0 +1,462 @@
+//Certificates of erasure for asset disposal audits. A report describes the wiped drive, how it was
+//wiped and whether it read back, and can be signed with an Ed25519 key. The signature covers the JSON
+//serialization of the report so changing any field afterwards is detected.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::clone::device_size;
+use crate::job::{JobKind, JobReport, JobStatus, WipeScope};
+use crate::wipe::engine::CHUNK_SIZE;
+
+//Bad blocks are reported in classic 512 byte sectors whatever the drive's native sector size is
+const SECTOR_SIZE: u64 = 512;
+
+/// Run of sectors that did not read back as written.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SectorRange {
+    pub first: u64,
+    pub count: u64,
+}
+
+/// A pass as it was written, e.g. `0x00` or `random` followed by a read back.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PassRecord {
+    pub pattern: String,
+    pub verify: bool,
+}
+
+/// Everything an auditor needs to know about one wipe.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ErasureReport {
+    pub tool: String,
+    pub device: PathBuf,
+    pub model: String,
+    pub serial_number: String,
+    pub size: u64,
+    pub bus: String,
+    pub scope: String,
+    pub method: String,
+    pub passes: Vec<PassRecord>, //Empty for firmware erases and slack cleaning
+    pub started: String,         //RFC 3339, UTC
+    pub finished: String,
+    pub operator: String,
+    pub host: String,
+    pub verified: bool,
+    pub verification: String, //passed, failed or not verified
+    pub bad_sectors: Vec<SectorRange>,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub simulated: bool, //Dry run, the drive was never written to
+}
+
+/// Detached Ed25519 signature over the JSON of a report, keys and signature are hex encoded.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReportSignature {
+    pub algorithm: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// What gets written to the JSON report file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignedReport {
+    pub report: ErasureReport,
+    pub signature: Option<ReportSignature>,
+}
+
+impl ErasureReport {
+    ///Builds the report of a finished wipe job, `None` for clone and burn jobs
+    pub fn new(kind: &JobKind, status: &JobStatus, operator: &str) -> Option<ErasureReport> {
+        let (disk, method, scope) = match kind {
+            JobKind::Wipe { disk, method, scope, .. } => (disk, method, *scope),
+            _ => return None,
+        };
+        let started = status.started.unwrap_or_else(SystemTime::now);
+        let finished = started + status.elapsed;
+        let size = device_size(&disk.name).unwrap_or(disk.total_space);
+        //Free space offsets are relative to the fill files, only whole drive wipes map to sectors
+        let bad_sectors = if scope == WipeScope::EntireDrive {
+            let block = if status.block_size > 0 { status.block_size } else { CHUNK_SIZE as u64 };
+            status
+                .verify_failures
+                .iter()
+                .map(|offset| SectorRange {
+                    first: offset / SECTOR_SIZE,
+                    count: (size.saturating_sub(*offset)).min(block) / SECTOR_SIZE,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let verification = if status.error.is_some() || !status.verify_failures.is_empty() {
+            "failed"
+        } else if status.verified {
+            "passed"
+        } else {
+            "not verified"
+        };
+        Some(ErasureReport {
+            tool: format!("minuteman {}", env!("CARGO_PKG_VERSION")),
+            device: disk.name.clone(),
+            model: disk.model.clone(),
+            serial_number: disk.serial_number.clone(),
+            size,
+            bus: disk.bus.clone(),
+            scope: String::from(scope.name()),
+            method: if scope.uses_method() { method.name.clone() } else { String::from("Zero slack") },
+            passes: if scope.uses_method() {
+                method
+                    .passes
+                    .iter()
+                    .map(|p| PassRecord { pattern: p.pattern.to_string(), verify: p.verify })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            started: timestamp(started),
+            finished: timestamp(finished),
+            operator: String::from(operator),
+            host: hostname(),
+            verified: status.verified,
+            verification: String::from(verification),
+            bad_sectors,
+            error: status.error.clone(),
+            simulated: status.simulated,
+        })
+    }
+
+    ///Plain text version for printing or attaching to tickets
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("CERTIFICATE OF ERASURE\n\n");
+        if self.simulated {
+            text.push_str("DRY RUN, nothing was erased\n\n");
+        }
+        for (label, value) in self.fields() {
+            let _ = writeln!(text, "{:<16}{}", format!("{}:", label), value);
+        }
+        text.push_str("\nPasses:\n");
+        if self.passes.is_empty() {
+            text.push_str("  none\n");
+        }
+        for (i, pass) in self.passes.iter().enumerate() {
+            let _ = writeln!(text, "  {:>2}. {}{}", i + 1, pass.pattern, if pass.verify { " (verified)" } else { "" });
+        }
+        text.push_str("\nBad sectors:\n");
+        if self.bad_sectors.is_empty() {
+            text.push_str("  none\n");
+        }
+        for range in self.bad_sectors.iter() {
+            let _ = writeln!(text, "  {} - {}", range.first, range.first + range.count.max(1) - 1);
+        }
+        text
+    }
+
+    ///Standalone html page, signature details are appended when given
+    pub fn to_html(&self, signature: Option<&ReportSignature>) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Certificate of erasure</title>\n\
+             <style>body{font-family:sans-serif;margin:2em}table{border-collapse:collapse}\
+             td,th{border:1px solid #999;padding:4px 8px;text-align:left}</style>\n</head>\n<body>\n\
+             <h1>Certificate of erasure</h1>\n<table>\n",
+        );
+        if self.simulated {
+            html.push_str("<p><strong>Dry run, nothing was erased</strong></p>\n");
+        }
+        for (label, value) in self.fields() {
+            let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", label, escape(&value));
+        }
+        html.push_str("</table>\n<h2>Passes</h2>\n<ol>\n");
+        for pass in self.passes.iter() {
+            let _ = writeln!(html, "<li>{}{}</li>", escape(&pass.pattern), if pass.verify { " (verified)" } else { "" });
+        }
+        html.push_str("</ol>\n<h2>Bad sectors</h2>\n");
+        if self.bad_sectors.is_empty() {
+            html.push_str("<p>none</p>\n");
+        } else {
+            html.push_str("<ul>\n");
+            for range in self.bad_sectors.iter() {
+                let _ = writeln!(html, "<li>{} - {}</li>", range.first, range.first + range.count.max(1) - 1);
+            }
+            html.push_str("</ul>\n");
+        }
+        if let Some(signature) = signature {
+            let _ = write!(
+                html,
+                "<h2>Signature</h2>\n<p>{} public key <code>{}</code></p>\n<p><code>{}</code></p>\n",
+                escape(&signature.algorithm),
+                escape(&signature.public_key),
+                escape(&signature.signature)
+            );
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Device", self.device.display().to_string()),
+            ("Model", self.model.clone()),
+            ("Serial number", self.serial_number.clone()),
+            ("Size", format!("{} bytes", self.size)),
+            ("Bus", self.bus.clone()),
+            ("Scope", self.scope.clone()),
+            ("Method", self.method.clone()),
+            ("Started", self.started.clone()),
+            ("Finished", self.finished.clone()),
+            ("Operator", self.operator.clone()),
+            ("Host", self.host.clone()),
+            ("Verification", self.verification.clone()),
+            ("Error", self.error.clone().unwrap_or_else(|| String::from("none"))),
+            ("Tool", self.tool.clone()),
+        ]
+    }
+}
+
+impl SignedReport {
+    pub fn new(report: ErasureReport, key: Option<&SigningKey>) -> Result<SignedReport> {
+        let signature = match key {
+            Some(key) => {
+                let signature = key.sign(&serde_json::to_vec(&report)?);
+                Some(ReportSignature {
+                    algorithm: String::from("ed25519"),
+                    public_key: to_hex(key.verifying_key().as_bytes()),
+                    signature: to_hex(&signature.to_bytes()),
+                })
+            }
+            None => None,
+        };
+        Ok(SignedReport { report, signature })
+    }
+
+    ///Checks the signature against the embedded public key, and against `trusted` when given since
+    /// anyone can re-sign a modified report with a key of their own
+    pub fn check(&self, trusted: Option<&VerifyingKey>) -> Result<()> {
+        let signature = self.signature.as_ref().ok_or_else(|| anyhow!("report is not signed"))?;
+        if signature.algorithm != "ed25519" {
+            return Err(anyhow!("unsupported signature algorithm {}", signature.algorithm));
+        }
+        let key = VerifyingKey::from_bytes(&from_hex::<32>(&signature.public_key)?)?;
+        if let Some(trusted) = trusted {
+            if trusted != &key {
+                return Err(anyhow!("report was signed with a different key"));
+            }
+        }
+        let bytes = from_hex::<64>(&signature.signature)?;
+        key.verify(&serde_json::to_vec(&self.report)?, &Signature::from_bytes(&bytes))
+            .map_err(|_| anyhow!("signature does not match, the report was modified"))
+    }
+
+    ///Writes the report as json, text and html next to each other in `directory`
+    pub fn write(&self, directory: &Path) -> Result<Vec<PathBuf>> {
+        std::fs::create_dir_all(directory)?;
+        let device = self.report.device.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let stamp = self.report.finished.replace(':', "");
+        let base = directory.join(format!("erasure-{}-{}", device, stamp));
+        let mut text = self.report.to_text();
+        if let Some(signature) = &self.signature {
+            let _ = write!(text, "\nSigned ({}) with {}\n{}\n", signature.algorithm, signature.public_key, signature.signature);
+        }
+        let files = vec![
+            (base.with_extension("json"), serde_json::to_string_pretty(self)?),
+            (base.with_extension("txt"), text),
+            (base.with_extension("html"), self.report.to_html(self.signature.as_ref())),
+        ];
+        let mut written = Vec::new();
+        for (path, contents) in files {
+            std::fs::write(&path, contents)?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+
+    pub fn load(path: &Path) -> Result<SignedReport> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// Combined outcome of several drives wiped side by side.
+#[derive(Serialize)]
+struct SessionSummary<'a> {
+    finished: String,
+    operator: String,
+    host: String,
+    succeeded: usize,
+    failed: usize,
+    jobs: &'a [JobReport],
+}
+
+///Writes the summary of a session next to the certificates of its drives, returns the file written
+pub fn write_session(directory: &Path, jobs: &[JobReport]) -> Result<PathBuf> {
+    std::fs::create_dir_all(directory)?;
+    let finished = timestamp(SystemTime::now());
+    let succeeded = jobs.iter().filter(|j| j.success).count();
+    let summary = SessionSummary {
+        operator: default_operator(),
+        host: hostname(),
+        succeeded,
+        failed: jobs.len() - succeeded,
+        jobs,
+        finished: finished.clone(),
+    };
+    let path = directory.join(format!("session-{}.json", finished.replace(':', "")));
+    std::fs::write(&path, serde_json::to_string_pretty(&summary)?)?;
+    Ok(path)
+}
+
+///Reads a signing key file holding the hex encoded 32 byte secret
+pub fn load_signing_key(path: &Path) -> Result<SigningKey> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(SigningKey::from_bytes(&from_hex::<32>(contents.trim())?))
+}
+
+///Reads a public key file holding the hex encoded 32 byte key
+pub fn load_public_key(path: &Path) -> Result<VerifyingKey> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(VerifyingKey::from_bytes(&from_hex::<32>(contents.trim())?)?)
+}
+
+///Creates a new key pair, the secret goes to `path` (readable by the owner only) and the public key
+/// to `path` with a .pub extension added
+pub fn generate_key(path: &Path) -> Result<PathBuf> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut secret = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    let key = SigningKey::from_bytes(&secret);
+    let mut file = std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?;
+    writeln!(file, "{}", to_hex(&secret))?;
+    let mut public = path.as_os_str().to_owned();
+    public.push(".pub");
+    let public = PathBuf::from(public);
+    std::fs::write(&public, format!("{}\n", to_hex(key.verifying_key().as_bytes())))?;
+    Ok(public)
+}
+
+///Login of whoever ran the wipe, the user behind sudo when there is one
+pub fn default_operator() -> String {
+    ["SUDO_USER", "USER", "LOGNAME"]
+        .iter()
+        .find_map(|v| std::env::var(v).ok().filter(|u| !u.is_empty()))
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    if result != 0 {
+        return String::from("unknown");
+    }
+    let len = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..len]).to_string()
+}
+
+fn timestamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex<const N: usize>(text: &str) -> Result<[u8; N]> {
+    if text.len() != N * 2 || !text.is_ascii() {
+        return Err(anyhow!("expected {} hex encoded bytes", N));
+    }
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).map_err(|_| anyhow!("invalid hex"))?;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> ErasureReport {
+        ErasureReport {
+            tool: String::from("minuteman test"),
+            device: PathBuf::from("/dev/sdz"),
+            model: String::from("Test <Disk>"),
+            serial_number: String::from("0001"),
+            size: 1 << 20,
+            bus: String::from("usb"),
+            scope: String::from("Entire drive"),
+            method: String::from("Zero fill"),
+            passes: vec![PassRecord { pattern: String::from("0x00"), verify: true }],
+            started: String::from("2026-01-01T00:00:00Z"),
+            finished: String::from("2026-01-01T00:01:00Z"),
+            operator: String::from("tester"),
+            host: String::from("bench"),
+            verified: true,
+            verification: String::from("passed"),
+            bad_sectors: Vec::new(),
+            error: None,
+            simulated: false,
+        }
+    }
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    #[test]
+    fn signed_report_checks_out() {
+        let signed = SignedReport::new(report(), Some(&key(1))).unwrap();
+        signed.check(None).unwrap();
+        signed.check(Some(&key(1).verifying_key())).unwrap();
+        //Survives the round trip through the json file
+        let json = serde_json::to_string_pretty(&signed).unwrap();
+        let loaded: SignedReport = serde_json::from_str(&json).unwrap();
+        loaded.check(None).unwrap();
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let mut signed = SignedReport::new(report(), Some(&key(1))).unwrap();
+        signed.report.verification = String::from("failed");
+        assert!(signed.check(None).is_err());
+    }
+
+    #[test]
+    fn resigning_with_another_key_is_detected() {
+        let mut tampered = report();
+        tampered.serial_number = String::from("0002");
+        let signed = SignedReport::new(tampered, Some(&key(2))).unwrap();
+        signed.check(None).unwrap();
+        assert!(signed.check(Some(&key(1).verifying_key())).is_err());
+    }
+
+    #[test]
+    fn unsigned_report_does_not_check() {
+        let signed = SignedReport::new(report(), None).unwrap();
+        assert!(signed.check(None).is_err());
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        let html = report().to_html(None);
+        assert!(html.contains("Test &lt;Disk&gt;"));
+        assert!(!html.contains("<Disk>"));
+    }
+
+    #[test]
+    fn dry_runs_are_marked() {
+        assert!(!report().to_text().contains("DRY RUN"));
+        let simulated = ErasureReport { simulated: true, ..report() };
+        assert!(simulated.to_text().contains("DRY RUN"));
+        assert!(simulated.to_html(None).contains("Dry run"));
+    }
+}
@@ -0,0 +1,323 @@
+//ATA (through SCSI generic pass-through) and NVMe admin command ioctls
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::disk::read_discard_max_bytes;
+use std::time::Duration;
+
+use crate::sanitize::{AtaSecurity, NvmeCapabilities, SanitizeCommand, SanitizeDevice, SanitizeStatus};
+
+const SG_IO: libc::c_ulong = 0x2285;
+const SG_DXFER_NONE: i32 = -1;
+const SG_DXFER_TO_DEV: i32 = -2;
+const SG_DXFER_FROM_DEV: i32 = -3;
+//_IOWR('N', 0x41, struct nvme_admin_cmd)
+const NVME_IOCTL_ADMIN_CMD: libc::c_ulong = 0xC048_4E41;
+const BLKGETSIZE64: libc::c_ulong = 0x8008_1272;
+const BLKDISCARD: libc::c_ulong = 0x1277;
+const BLKSECDISCARD: libc::c_ulong = 0x127d;
+
+const ATA_IDENTIFY: u8 = 0xEC;
+const ATA_SECURITY_SET_PASSWORD: u8 = 0xF1;
+const ATA_SECURITY_ERASE_PREPARE: u8 = 0xF3;
+const ATA_SECURITY_ERASE_UNIT: u8 = 0xF4;
+const ATA_SECURITY_DISABLE_PASSWORD: u8 = 0xF6;
+
+const NVME_ADMIN_GET_LOG_PAGE: u8 = 0x02;
+const NVME_ADMIN_IDENTIFY: u8 = 0x06;
+const NVME_ADMIN_FORMAT_NVM: u8 = 0x80;
+const NVME_ADMIN_SANITIZE: u8 = 0x84;
+const NVME_LOG_SANITIZE_STATUS: u32 = 0x81;
+
+#[repr(C)]
+struct SgIoHdr {
+    interface_id: i32,
+    dxfer_direction: i32,
+    cmd_len: u8,
+    mx_sb_len: u8,
+    iovec_count: u16,
+    dxfer_len: u32,
+    dxferp: *mut libc::c_void,
+    cmdp: *const u8,
+    sbp: *mut u8,
+    timeout: u32,
+    flags: u32,
+    pack_id: i32,
+    usr_ptr: *mut libc::c_void,
+    status: u8,
+    masked_status: u8,
+    msg_status: u8,
+    sb_len_wr: u8,
+    host_status: u16,
+    driver_status: u16,
+    resid: i32,
+    duration: u32,
+    info: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct NvmeAdminCmd {
+    opcode: u8,
+    flags: u8,
+    rsvd1: u16,
+    nsid: u32,
+    cdw2: u32,
+    cdw3: u32,
+    metadata: u64,
+    addr: u64,
+    metadata_len: u32,
+    data_len: u32,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+    timeout_ms: u32,
+    result: u32,
+}
+
+/// Either end of the sanitize ioctls, picked from the device name.
+pub struct LinuxSanitizeDevice {
+    file: File,
+    sys_path: PathBuf,
+    nvme_namespace: u32,
+}
+
+///Opens a block device for issuing sanitize commands, NVMe namespaces are detected from their name
+pub fn open_sanitize_device(path: &Path) -> Result<LinuxSanitizeDevice> {
+    let file = std::fs::OpenOptions::new().read(true).write(true).open(path)?;
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    //nvme0n1 -> namespace 1
+    let nvme_namespace = if name.starts_with("nvme") {
+        name.rsplit('n').next().and_then(|n| n.parse().ok()).unwrap_or(1)
+    } else {
+        0
+    };
+    Ok(LinuxSanitizeDevice {
+        file,
+        sys_path: Path::new("/sys/class/block").join(name),
+        nvme_namespace,
+    })
+}
+
+impl LinuxSanitizeDevice {
+    //Issues an ATA PASS-THROUGH (16) command, `data_out` selects the transfer direction
+    fn ata_command(&mut self, command: u8, features: u8, buffer: Option<(&mut [u8], bool)>, timeout: Duration) -> Result<()> {
+        //Protocol: 3 non-data, 4 PIO data-in, 5 PIO data-out
+        let (protocol, flags, direction, len, ptr) = match buffer {
+            None => (3u8, 0x20u8, SG_DXFER_NONE, 0, std::ptr::null_mut()),
+            Some((data, true)) => (5, 0x06, SG_DXFER_TO_DEV, data.len(), data.as_mut_ptr()),
+            Some((data, false)) => (4, 0x0e, SG_DXFER_FROM_DEV, data.len(), data.as_mut_ptr()),
+        };
+        let sectors = (len / 512) as u8;
+        let cdb: [u8; 16] = [0x85, protocol << 1, flags, 0, features, 0, sectors, 0, 0, 0, 0, 0, 0, 0x40, command, 0];
+        let mut sense = [0u8; 32];
+        let mut hdr = SgIoHdr {
+            interface_id: 'S' as i32,
+            dxfer_direction: direction,
+            cmd_len: cdb.len() as u8,
+            mx_sb_len: sense.len() as u8,
+            iovec_count: 0,
+            dxfer_len: len as u32,
+            dxferp: ptr as *mut libc::c_void,
+            cmdp: cdb.as_ptr(),
+            sbp: sense.as_mut_ptr(),
+            timeout: timeout.as_millis().min(u128::from(u32::MAX)) as u32,
+            flags: 0,
+            pack_id: 0,
+            usr_ptr: std::ptr::null_mut(),
+            status: 0,
+            masked_status: 0,
+            msg_status: 0,
+            sb_len_wr: 0,
+            host_status: 0,
+            driver_status: 0,
+            resid: 0,
+            duration: 0,
+            info: 0,
+        };
+        let ret = unsafe { libc::ioctl(self.file.as_raw_fd(), SG_IO, &mut hdr) };
+        if ret != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        //Sense data descriptor 0x09 carries the ATA status register, bit 0 is ERR
+        let ata_error = sense[8] == 0x09 && sense[21] & 0x01 != 0;
+        if hdr.host_status != 0 || ata_error || (hdr.status != 0 && hdr.status != 0x02) {
+            return Err(anyhow!("ATA command {:#x} aborted by device", command));
+        }
+        Ok(())
+    }
+
+    //Builds the 512 byte payload used by the security commands
+    fn password_block(password: &[u8], control: u16) -> [u8; 512] {
+        let mut block = [0u8; 512];
+        block[0..2].copy_from_slice(&control.to_le_bytes());
+        let len = password.len().min(32);
+        block[2..2 + len].copy_from_slice(&password[..len]);
+        block
+    }
+
+    //Sanitize status log page, progress and state of the last sanitize followed by time estimates
+    fn sanitize_log(&mut self) -> Result<[u8; 512]> {
+        let mut data = [0u8; 512];
+        let dwords = (data.len() / 4 - 1) as u32;
+        let mut cmd = NvmeAdminCmd {
+            opcode: NVME_ADMIN_GET_LOG_PAGE,
+            nsid: 0xffff_ffff,
+            addr: data.as_mut_ptr() as u64,
+            data_len: data.len() as u32,
+            cdw10: (dwords << 16) | NVME_LOG_SANITIZE_STATUS,
+            ..NvmeAdminCmd::default()
+        };
+        self.nvme_admin(&mut cmd)?;
+        Ok(data)
+    }
+
+    fn nvme_admin(&mut self, cmd: &mut NvmeAdminCmd) -> Result<u32> {
+        let ret = unsafe { libc::ioctl(self.file.as_raw_fd(), NVME_IOCTL_ADMIN_CMD, cmd as *mut NvmeAdminCmd) };
+        match ret {
+            0 => Ok(cmd.result),
+            r if r < 0 => Err(std::io::Error::last_os_error().into()),
+            status => Err(anyhow!("NVMe admin command {:#x} failed with status {:#x}", cmd.opcode, status)),
+        }
+    }
+}
+
+impl SanitizeDevice for LinuxSanitizeDevice {
+    fn ata_identify(&mut self) -> Result<AtaSecurity> {
+        let mut data = [0u8; 512];
+        self.ata_command(ATA_IDENTIFY, 0, Some((&mut data, false)), Duration::from_secs(10))?;
+        let word = |n: usize| u16::from_le_bytes([data[n * 2], data[n * 2 + 1]]);
+        let security = word(128);
+        Ok(AtaSecurity {
+            supported: word(82) & 0x2 != 0 && security & 0x1 != 0,
+            enabled: security & 0x2 != 0,
+            locked: security & 0x4 != 0,
+            frozen: security & 0x8 != 0,
+            count_expired: security & 0x10 != 0,
+            enhanced_erase_supported: security & 0x20 != 0,
+            erase_time_minutes: (word(89) & 0xff) * 2,
+            enhanced_erase_time_minutes: (word(90) & 0xff) * 2,
+        })
+    }
+
+    fn ata_set_password(&mut self, password: &[u8]) -> Result<()> {
+        let mut block = Self::password_block(password, 0);
+        self.ata_command(ATA_SECURITY_SET_PASSWORD, 0, Some((&mut block, true)), Duration::from_secs(10))
+    }
+
+    fn ata_erase_prepare(&mut self) -> Result<()> {
+        self.ata_command(ATA_SECURITY_ERASE_PREPARE, 0, None, Duration::from_secs(10))
+    }
+
+    fn ata_erase_unit(&mut self, password: &[u8], enhanced: bool, timeout: Duration) -> Result<()> {
+        let mut block = Self::password_block(password, if enhanced { 0x2 } else { 0 });
+        self.ata_command(ATA_SECURITY_ERASE_UNIT, 0, Some((&mut block, true)), timeout)
+    }
+
+    fn ata_disable_password(&mut self, password: &[u8]) -> Result<()> {
+        let mut block = Self::password_block(password, 0);
+        self.ata_command(ATA_SECURITY_DISABLE_PASSWORD, 0, Some((&mut block, true)), Duration::from_secs(10))
+    }
+
+    fn nvme_identify(&mut self) -> Result<NvmeCapabilities> {
+        if self.nvme_namespace == 0 {
+            return Err(anyhow!("not an NVMe device"));
+        }
+        let mut data = vec![0u8; 4096];
+        let mut cmd = NvmeAdminCmd {
+            opcode: NVME_ADMIN_IDENTIFY,
+            addr: data.as_mut_ptr() as u64,
+            data_len: data.len() as u32,
+            cdw10: 1, //Identify controller
+            ..NvmeAdminCmd::default()
+        };
+        self.nvme_admin(&mut cmd)?;
+        let oacs = u16::from_le_bytes([data[256], data[257]]);
+        let sanicap = u32::from_le_bytes([data[328], data[329], data[330], data[331]]);
+        let fna = data[524];
+        Ok(NvmeCapabilities {
+            format_supported: oacs & 0x2 != 0,
+            crypto_format_supported: fna & 0x4 != 0,
+            sanitize_crypto: sanicap & 0x1 != 0,
+            sanitize_block: sanicap & 0x2 != 0,
+            sanitize_overwrite: sanicap & 0x4 != 0,
+        })
+    }
+
+    fn nvme_format(&mut self, crypto: bool) -> Result<()> {
+        //Secure erase settings: 1 user data erase, 2 cryptographic erase
+        let ses: u32 = if crypto { 2 } else { 1 };
+        let mut cmd = NvmeAdminCmd {
+            opcode: NVME_ADMIN_FORMAT_NVM,
+            nsid: self.nvme_namespace,
+            cdw10: ses << 9,
+            timeout_ms: u32::MAX,
+            ..NvmeAdminCmd::default()
+        };
+        self.nvme_admin(&mut cmd).map(|_| ())
+    }
+
+    fn nvme_sanitize(&mut self, command: SanitizeCommand) -> Result<()> {
+        //Sanitize action: 2 block erase, 3 overwrite (one pass of zeroes), 4 crypto erase
+        let action: u32 = match command {
+            SanitizeCommand::NvmeSanitizeBlock => 2,
+            SanitizeCommand::NvmeSanitizeOverwrite => 3 | (1 << 4),
+            SanitizeCommand::NvmeSanitizeCrypto => 4,
+            _ => return Err(anyhow!("{} is not an NVMe sanitize action", command.name())),
+        };
+        let mut cmd = NvmeAdminCmd {
+            opcode: NVME_ADMIN_SANITIZE,
+            cdw10: action,
+            ..NvmeAdminCmd::default()
+        };
+        self.nvme_admin(&mut cmd).map(|_| ())
+    }
+
+    fn nvme_sanitize_status(&mut self) -> Result<SanitizeStatus> {
+        let data = self.sanitize_log()?;
+        let progress = u16::from_le_bytes([data[0], data[1]]);
+        let status = u16::from_le_bytes([data[2], data[3]]) & 0x7;
+        Ok(match status {
+            0 => SanitizeStatus::NeverSanitized,
+            1 | 4 => SanitizeStatus::Completed,
+            2 => SanitizeStatus::InProgress(f64::from(progress) / 65536.0),
+            _ => SanitizeStatus::Failed,
+        })
+    }
+
+    fn nvme_sanitize_estimate(&mut self, command: SanitizeCommand) -> Result<Option<Duration>> {
+        //Estimated seconds per action, all ones when the controller doesn't say
+        let offset = match command {
+            SanitizeCommand::NvmeSanitizeOverwrite => 8,
+            SanitizeCommand::NvmeSanitizeBlock => 12,
+            SanitizeCommand::NvmeSanitizeCrypto => 16,
+            _ => return Ok(None),
+        };
+        let data = self.sanitize_log()?;
+        let seconds = u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+        Ok(Some(Duration::from_secs(u64::from(seconds))).filter(|_| seconds != u32::MAX))
+    }
+
+    fn discard_supported(&mut self) -> bool {
+        read_discard_max_bytes(&self.sys_path) > 0
+    }
+
+    fn discard(&mut self, secure: bool) -> Result<()> {
+        let mut size: u64 = 0;
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), BLKGETSIZE64, &mut size) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        //Range is [start, length] in bytes, the kernel splits it up to discard_max_bytes itself
+        let range: [u64; 2] = [0, size];
+        let request = if secure { BLKSECDISCARD } else { BLKDISCARD };
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), request, &range) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
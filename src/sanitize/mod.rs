@@ -0,0 +1,575 @@
+//Device-native sanitize commands. Overwriting flash is unreliable since the controller remaps
+//blocks behind our back, so for SSDs and NVMe drives we ask the firmware to erase itself instead.
+//The raw ioctls live in the os specific module behind the `SanitizeDevice` trait so the command
+//sequencing below can be tested without real hardware.
+
+use anyhow::Result;
+use crate::disk::Disk;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod linux;
+#[cfg(unix)]
+use linux as os;
+
+pub use os::open_sanitize_device;
+
+//Temporary password set before an ATA erase, the drive clears it again once the erase finishes
+const ATA_ERASE_PASSWORD: &[u8] = b"minuteman";
+//How long a background NVMe sanitize may run. Drives that estimate their runtime get twice that, but
+//never less than the minimum, the others get the default
+const SANITIZE_MIN_TIME: Duration = Duration::from_secs(10 * 60);
+const SANITIZE_DEFAULT_TIME: Duration = Duration::from_secs(48 * 60 * 60);
+
+/// Every firmware level erase the application knows how to issue.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SanitizeCommand {
+    AtaSecurityErase,
+    AtaEnhancedSecurityErase,
+    NvmeFormatSecureErase,
+    NvmeFormatCryptoErase,
+    NvmeSanitizeBlock,
+    NvmeSanitizeCrypto,
+    NvmeSanitizeOverwrite,
+    Discard,
+    SecureDiscard,
+}
+
+impl SanitizeCommand {
+    pub const ALL: [SanitizeCommand; 9] = [
+        SanitizeCommand::AtaSecurityErase,
+        SanitizeCommand::AtaEnhancedSecurityErase,
+        SanitizeCommand::NvmeFormatSecureErase,
+        SanitizeCommand::NvmeFormatCryptoErase,
+        SanitizeCommand::NvmeSanitizeBlock,
+        SanitizeCommand::NvmeSanitizeCrypto,
+        SanitizeCommand::NvmeSanitizeOverwrite,
+        SanitizeCommand::Discard,
+        SanitizeCommand::SecureDiscard,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SanitizeCommand::AtaSecurityErase => "ATA Security Erase (device firmware)",
+            SanitizeCommand::AtaEnhancedSecurityErase => "ATA Enhanced Security Erase (device firmware)",
+            SanitizeCommand::NvmeFormatSecureErase => "NVMe Format, User Data Erase (device firmware)",
+            SanitizeCommand::NvmeFormatCryptoErase => "NVMe Format, Cryptographic Erase (device firmware)",
+            SanitizeCommand::NvmeSanitizeBlock => "NVMe Sanitize, Block Erase (device firmware)",
+            SanitizeCommand::NvmeSanitizeCrypto => "NVMe Sanitize, Crypto Erase (device firmware)",
+            SanitizeCommand::NvmeSanitizeOverwrite => "NVMe Sanitize, Overwrite (device firmware)",
+            SanitizeCommand::Discard => "Discard all blocks (TRIM)",
+            SanitizeCommand::SecureDiscard => "Secure discard all blocks (secure TRIM)",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            SanitizeCommand::AtaSecurityErase => "Firmware overwrite of all user data areas, including remapped sectors on most drives.",
+            SanitizeCommand::AtaEnhancedSecurityErase => "Firmware erase of every block the drive has ever written to, including spare areas. Usually a crypto erase on self encrypting drives.",
+            SanitizeCommand::NvmeFormatSecureErase => "Formats the namespace, erasing all user data through the controller.",
+            SanitizeCommand::NvmeFormatCryptoErase => "Formats the namespace and throws away its media encryption key, instantly rendering the data unreadable.",
+            SanitizeCommand::NvmeSanitizeBlock => "Erases every block on the controller including caches and spare blocks. Runs in the background.",
+            SanitizeCommand::NvmeSanitizeCrypto => "Changes the media encryption key of the whole controller, including caches. Takes seconds.",
+            SanitizeCommand::NvmeSanitizeOverwrite => "Firmware overwrite of every block on the controller with zeroes. Slow and wears flash, prefer block or crypto erase.",
+            SanitizeCommand::Discard => "Tells the drive every block is unused. Most SSDs return zeroes afterwards but the cells may not be erased right away.",
+            SanitizeCommand::SecureDiscard => "Discards every block and asks the drive to erase them immediately, including copies left by wear levelling.",
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn from_name(name: &str) -> Option<SanitizeCommand> {
+        SanitizeCommand::ALL.iter().copied().find(|c| c.name() == name)
+    }
+
+    fn is_ata(&self) -> bool {
+        matches!(self, SanitizeCommand::AtaSecurityErase | SanitizeCommand::AtaEnhancedSecurityErase)
+    }
+
+    fn is_discard(&self) -> bool {
+        matches!(self, SanitizeCommand::Discard | SanitizeCommand::SecureDiscard)
+    }
+}
+
+/// ATA security state as reported by word 128 of IDENTIFY DEVICE.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AtaSecurity {
+    pub supported: bool,
+    pub enabled: bool,
+    pub locked: bool,
+    pub frozen: bool,
+    pub count_expired: bool,
+    pub enhanced_erase_supported: bool,
+    pub erase_time_minutes: u16,
+    pub enhanced_erase_time_minutes: u16,
+}
+
+/// Sanitize related capabilities from the NVMe identify controller data.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NvmeCapabilities {
+    pub format_supported: bool,
+    pub crypto_format_supported: bool,
+    pub sanitize_block: bool,
+    pub sanitize_crypto: bool,
+    pub sanitize_overwrite: bool,
+}
+
+/// State of the last sanitize operation from the NVMe sanitize status log page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SanitizeStatus {
+    NeverSanitized,
+    InProgress(f64), //Progress ratio between 0 and 1
+    Completed,
+    Failed,
+}
+
+/// Errors the sanitize sequencing can run into, returned wrapped in an `anyhow::Error`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SanitizeError {
+    Unsupported(SanitizeCommand),
+    Frozen,
+    Locked,
+    PasswordSet,
+    AttemptsExpired,
+    Failed(String),
+}
+
+impl std::fmt::Display for SanitizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SanitizeError::Unsupported(command) => write!(f, "{} is not supported by this drive", command.name()),
+            SanitizeError::Frozen => write!(
+                f,
+                "drive security is frozen by the BIOS, suspend and resume the machine or hot-plug the drive and try again"
+            ),
+            SanitizeError::Locked => write!(f, "drive is locked with an unknown password"),
+            SanitizeError::PasswordSet => write!(
+                f,
+                "drive already has a security password, disable it first (it is \"{}\" if an earlier erase was interrupted)",
+                String::from_utf8_lossy(ATA_ERASE_PASSWORD)
+            ),
+            SanitizeError::AttemptsExpired => write!(f, "drive password attempt counter expired, power cycle the drive"),
+            SanitizeError::Failed(reason) => write!(f, "sanitize failed: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SanitizeError {}
+
+/// Raw commands a drive has to support for firmware erasure. Implemented over ioctls for real
+/// devices and by a fake device in the tests.
+pub trait SanitizeDevice {
+    fn ata_identify(&mut self) -> Result<AtaSecurity>;
+    fn ata_set_password(&mut self, password: &[u8]) -> Result<()>;
+    fn ata_erase_prepare(&mut self) -> Result<()>;
+    fn ata_erase_unit(&mut self, password: &[u8], enhanced: bool, timeout: Duration) -> Result<()>;
+    fn ata_disable_password(&mut self, password: &[u8]) -> Result<()>;
+    fn nvme_identify(&mut self) -> Result<NvmeCapabilities>;
+    fn nvme_format(&mut self, crypto: bool) -> Result<()>;
+    fn nvme_sanitize(&mut self, command: SanitizeCommand) -> Result<()>;
+    fn nvme_sanitize_status(&mut self) -> Result<SanitizeStatus>;
+    fn nvme_sanitize_estimate(&mut self, command: SanitizeCommand) -> Result<Option<Duration>>;
+    fn discard_supported(&mut self) -> bool;
+    fn discard(&mut self, secure: bool) -> Result<()>;
+}
+
+/// Checks whether the drive can run `command` without changing anything on it.
+pub fn is_supported<D: SanitizeDevice>(device: &mut D, command: SanitizeCommand) -> bool {
+    //Whether a secure discard goes through is only known once we try it
+    if command.is_discard() {
+        return device.discard_supported();
+    }
+    if command.is_ata() {
+        return match device.ata_identify() {
+            Ok(security) => {
+                security.supported
+                    && (command == SanitizeCommand::AtaSecurityErase || security.enhanced_erase_supported)
+            }
+            Err(_) => false,
+        };
+    }
+    match device.nvme_identify() {
+        Ok(caps) => match command {
+            SanitizeCommand::NvmeFormatSecureErase => caps.format_supported,
+            SanitizeCommand::NvmeFormatCryptoErase => caps.format_supported && caps.crypto_format_supported,
+            SanitizeCommand::NvmeSanitizeBlock => caps.sanitize_block,
+            SanitizeCommand::NvmeSanitizeCrypto => caps.sanitize_crypto,
+            SanitizeCommand::NvmeSanitizeOverwrite => caps.sanitize_overwrite,
+            _ => false,
+        },
+        Err(_) => false,
+    }
+}
+
+///Probes which commands the drive at `path` accepts. Devices we can't open (usually for lack of
+/// root) can only be judged by their sysfs discard support.
+pub fn supported_commands(disk: &Disk) -> Vec<SanitizeCommand> {
+    match open_sanitize_device(&disk.name) {
+        Ok(mut device) => SanitizeCommand::ALL
+            .iter()
+            .copied()
+            .filter(|c| is_supported(&mut device, *c))
+            .collect(),
+        Err(_) if disk.supports_discard() => vec![SanitizeCommand::Discard],
+        Err(_) => Vec::new(),
+    }
+}
+
+///Runs a firmware erase to completion. `on_progress` is called with a ratio between 0 and 1 whenever
+/// the drive reports progress, ATA drives only report once they are done.
+pub fn run_sanitize<D, F>(device: &mut D, command: SanitizeCommand, poll_interval: Duration, mut on_progress: F) -> Result<()>
+where
+    D: SanitizeDevice,
+    F: FnMut(f64),
+{
+    if command.is_discard() {
+        if !device.discard_supported() {
+            return Err(SanitizeError::Unsupported(command).into());
+        }
+        device
+            .discard(command == SanitizeCommand::SecureDiscard)
+            .map_err(|e| SanitizeError::Failed(e.to_string()))?;
+    } else if command.is_ata() {
+        run_ata_erase(device, command)?;
+    } else {
+        run_nvme_erase(device, command, poll_interval, &mut on_progress)?;
+    }
+    on_progress(1.0);
+    Ok(())
+}
+
+fn run_ata_erase<D: SanitizeDevice>(device: &mut D, command: SanitizeCommand) -> Result<()> {
+    let enhanced = command == SanitizeCommand::AtaEnhancedSecurityErase;
+    let security = device.ata_identify()?;
+    if !security.supported || (enhanced && !security.enhanced_erase_supported) {
+        return Err(SanitizeError::Unsupported(command).into());
+    }
+    //A frozen drive rejects every security command until the next power cycle
+    if security.frozen {
+        return Err(SanitizeError::Frozen.into());
+    }
+    if security.locked {
+        return Err(SanitizeError::Locked.into());
+    }
+    if security.count_expired {
+        return Err(SanitizeError::AttemptsExpired.into());
+    }
+    //Somebody else's password would make the erase fail, or worse lock the drive after too many tries
+    if security.enabled {
+        return Err(SanitizeError::PasswordSet.into());
+    }
+    //Erase unit only works with security enabled, which means setting a user password first
+    log::warn!(
+        "Setting the temporary ATA password \"{}\", unlock the drive with it if the erase is interrupted",
+        String::from_utf8_lossy(ATA_ERASE_PASSWORD)
+    );
+    device.ata_set_password(ATA_ERASE_PASSWORD)?;
+    let minutes = if enhanced {
+        security.enhanced_erase_time_minutes
+    } else {
+        security.erase_time_minutes
+    };
+    //Drives report 0 when they don't know, give those a generous default. Always allow some slack.
+    let timeout = Duration::from_secs(u64::from(if minutes == 0 { 600 } else { minutes * 2 }) * 60);
+    device.ata_erase_prepare()?;
+    if let Err(e) = device.ata_erase_unit(ATA_ERASE_PASSWORD, enhanced, timeout) {
+        //Don't leave the drive locked behind our password
+        let _ = device.ata_disable_password(ATA_ERASE_PASSWORD);
+        return Err(SanitizeError::Failed(e.to_string()).into());
+    }
+    //A successful erase disables security again, anything else means the erase did not happen
+    let after = device.ata_identify()?;
+    if after.enabled {
+        let _ = device.ata_disable_password(ATA_ERASE_PASSWORD);
+        return Err(SanitizeError::Failed(String::from("drive security still enabled after erase")).into());
+    }
+    Ok(())
+}
+
+fn run_nvme_erase<D, F>(device: &mut D, command: SanitizeCommand, poll_interval: Duration, on_progress: &mut F) -> Result<()>
+where
+    D: SanitizeDevice,
+    F: FnMut(f64),
+{
+    if !is_supported(device, command) {
+        return Err(SanitizeError::Unsupported(command).into());
+    }
+    match command {
+        SanitizeCommand::NvmeFormatSecureErase | SanitizeCommand::NvmeFormatCryptoErase => {
+            //Format blocks until the controller is done
+            device
+                .nvme_format(command == SanitizeCommand::NvmeFormatCryptoErase)
+                .map_err(|e| SanitizeError::Failed(e.to_string()))?;
+            Ok(())
+        }
+        _ => {
+            //Not every drive fills in the estimates, they are only a hint
+            let estimate = device.nvme_sanitize_estimate(command).unwrap_or(None);
+            device.nvme_sanitize(command).map_err(|e| SanitizeError::Failed(e.to_string()))?;
+            poll_sanitize(device, sanitize_time_limit(estimate), poll_interval, on_progress)
+        }
+    }
+}
+
+fn sanitize_time_limit(estimate: Option<Duration>) -> Duration {
+    match estimate {
+        Some(estimate) => (estimate * 2).max(SANITIZE_MIN_TIME),
+        None => SANITIZE_DEFAULT_TIME,
+    }
+}
+
+//Sanitize runs in the background, poll the log page until it is done or `limit` has passed
+fn poll_sanitize<D, F>(device: &mut D, limit: Duration, poll_interval: Duration, on_progress: &mut F) -> Result<()>
+where
+    D: SanitizeDevice,
+    F: FnMut(f64),
+{
+    let started = Instant::now();
+    loop {
+        match device.nvme_sanitize_status()? {
+            SanitizeStatus::Completed => return Ok(()),
+            SanitizeStatus::Failed => return Err(SanitizeError::Failed(String::from("drive reported sanitize failure")).into()),
+            SanitizeStatus::InProgress(progress) => on_progress(progress),
+            SanitizeStatus::NeverSanitized => {}
+        }
+        if started.elapsed() >= limit {
+            return Err(SanitizeError::Failed(format!("drive did not finish sanitizing within {} minutes", limit.as_secs() / 60)).into());
+        }
+        thread::sleep(poll_interval);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    //Records every command issued to it and replays canned answers
+    #[derive(Default)]
+    struct FakeDevice {
+        security: AtaSecurity,
+        security_after_erase: AtaSecurity,
+        nvme: NvmeCapabilities,
+        fail_erase: bool,
+        statuses: Vec<SanitizeStatus>,
+        estimate: Option<Duration>,
+        discard: bool,
+        erased: bool,
+        calls: Vec<String>,
+    }
+
+    impl SanitizeDevice for FakeDevice {
+        fn ata_identify(&mut self) -> Result<AtaSecurity> {
+            self.calls.push(String::from("identify"));
+            Ok(if self.erased { self.security_after_erase } else { self.security })
+        }
+        fn ata_set_password(&mut self, _password: &[u8]) -> Result<()> {
+            self.calls.push(String::from("set_password"));
+            self.security.enabled = true;
+            Ok(())
+        }
+        fn ata_erase_prepare(&mut self) -> Result<()> {
+            self.calls.push(String::from("erase_prepare"));
+            Ok(())
+        }
+        fn ata_erase_unit(&mut self, _password: &[u8], enhanced: bool, _timeout: Duration) -> Result<()> {
+            self.calls.push(format!("erase_unit enhanced={}", enhanced));
+            if self.fail_erase {
+                return Err(anyhow!("aborted"));
+            }
+            self.erased = true;
+            Ok(())
+        }
+        fn ata_disable_password(&mut self, _password: &[u8]) -> Result<()> {
+            self.calls.push(String::from("disable_password"));
+            Ok(())
+        }
+        fn nvme_identify(&mut self) -> Result<NvmeCapabilities> {
+            Ok(self.nvme)
+        }
+        fn nvme_format(&mut self, crypto: bool) -> Result<()> {
+            self.calls.push(format!("format crypto={}", crypto));
+            Ok(())
+        }
+        fn nvme_sanitize(&mut self, command: SanitizeCommand) -> Result<()> {
+            self.calls.push(format!("sanitize {:?}", command));
+            Ok(())
+        }
+        fn nvme_sanitize_status(&mut self) -> Result<SanitizeStatus> {
+            Ok(self.statuses.remove(0))
+        }
+        fn nvme_sanitize_estimate(&mut self, _command: SanitizeCommand) -> Result<Option<Duration>> {
+            Ok(self.estimate)
+        }
+        fn discard_supported(&mut self) -> bool {
+            self.discard
+        }
+        fn discard(&mut self, secure: bool) -> Result<()> {
+            self.calls.push(format!("discard secure={}", secure));
+            Ok(())
+        }
+    }
+
+    fn ata_device() -> FakeDevice {
+        FakeDevice {
+            security: AtaSecurity {
+                supported: true,
+                enhanced_erase_supported: true,
+                ..AtaSecurity::default()
+            },
+            security_after_erase: AtaSecurity {
+                supported: true,
+                ..AtaSecurity::default()
+            },
+            ..FakeDevice::default()
+        }
+    }
+
+    fn error_of(result: Result<()>) -> SanitizeError {
+        result.unwrap_err().downcast::<SanitizeError>().unwrap()
+    }
+
+    #[test]
+    fn ata_erase_sets_password_then_erases() {
+        let mut device = ata_device();
+        run_sanitize(&mut device, SanitizeCommand::AtaEnhancedSecurityErase, Duration::from_millis(0), |_| {}).unwrap();
+        assert_eq!(
+            device.calls,
+            vec!["identify", "set_password", "erase_prepare", "erase_unit enhanced=true", "identify"]
+        );
+    }
+
+    #[test]
+    fn ata_erase_refuses_frozen_drive() {
+        let mut device = ata_device();
+        device.security.frozen = true;
+        let result = run_sanitize(&mut device, SanitizeCommand::AtaSecurityErase, Duration::from_millis(0), |_| {});
+        assert_eq!(error_of(result), SanitizeError::Frozen);
+        assert_eq!(device.calls, vec!["identify"]);
+    }
+
+    #[test]
+    fn ata_erase_refuses_locked_drive() {
+        let mut device = ata_device();
+        device.security.locked = true;
+        let result = run_sanitize(&mut device, SanitizeCommand::AtaSecurityErase, Duration::from_millis(0), |_| {});
+        assert_eq!(error_of(result), SanitizeError::Locked);
+    }
+
+    #[test]
+    fn ata_erase_refuses_drive_with_a_password() {
+        let mut device = ata_device();
+        device.security.enabled = true;
+        let result = run_sanitize(&mut device, SanitizeCommand::AtaSecurityErase, Duration::from_millis(0), |_| {});
+        assert_eq!(error_of(result), SanitizeError::PasswordSet);
+        assert_eq!(device.calls, vec!["identify"]);
+    }
+
+    #[test]
+    fn ata_enhanced_erase_requires_support() {
+        let mut device = ata_device();
+        device.security.enhanced_erase_supported = false;
+        let result = run_sanitize(&mut device, SanitizeCommand::AtaEnhancedSecurityErase, Duration::from_millis(0), |_| {});
+        assert_eq!(error_of(result), SanitizeError::Unsupported(SanitizeCommand::AtaEnhancedSecurityErase));
+    }
+
+    #[test]
+    fn failed_ata_erase_clears_password() {
+        let mut device = ata_device();
+        device.fail_erase = true;
+        let result = run_sanitize(&mut device, SanitizeCommand::AtaSecurityErase, Duration::from_millis(0), |_| {});
+        assert!(matches!(error_of(result), SanitizeError::Failed(_)));
+        assert_eq!(device.calls.last().unwrap(), "disable_password");
+    }
+
+    #[test]
+    fn ata_erase_fails_if_security_stays_enabled() {
+        let mut device = ata_device();
+        device.security_after_erase.enabled = true;
+        let result = run_sanitize(&mut device, SanitizeCommand::AtaSecurityErase, Duration::from_millis(0), |_| {});
+        assert!(matches!(error_of(result), SanitizeError::Failed(_)));
+        assert_eq!(device.calls.last().unwrap(), "disable_password");
+    }
+
+    #[test]
+    fn nvme_sanitize_polls_until_complete() {
+        let mut device = FakeDevice {
+            nvme: NvmeCapabilities {
+                sanitize_crypto: true,
+                ..NvmeCapabilities::default()
+            },
+            statuses: vec![
+                SanitizeStatus::InProgress(0.25),
+                SanitizeStatus::InProgress(0.75),
+                SanitizeStatus::Completed,
+            ],
+            ..FakeDevice::default()
+        };
+        let mut progress = Vec::new();
+        run_sanitize(&mut device, SanitizeCommand::NvmeSanitizeCrypto, Duration::from_millis(0), |p| progress.push(p)).unwrap();
+        assert_eq!(progress, vec![0.25, 0.75, 1.0]);
+        assert_eq!(device.calls, vec!["sanitize NvmeSanitizeCrypto"]);
+    }
+
+    #[test]
+    fn nvme_sanitize_reports_failure() {
+        let mut device = FakeDevice {
+            nvme: NvmeCapabilities {
+                sanitize_block: true,
+                ..NvmeCapabilities::default()
+            },
+            statuses: vec![SanitizeStatus::InProgress(0.5), SanitizeStatus::Failed],
+            ..FakeDevice::default()
+        };
+        let result = run_sanitize(&mut device, SanitizeCommand::NvmeSanitizeBlock, Duration::from_millis(0), |_| {});
+        assert!(matches!(error_of(result), SanitizeError::Failed(_)));
+    }
+
+    #[test]
+    fn nvme_sanitize_gives_up_eventually() {
+        let mut device = FakeDevice {
+            statuses: vec![SanitizeStatus::InProgress(0.5), SanitizeStatus::InProgress(0.5)],
+            ..FakeDevice::default()
+        };
+        let result = poll_sanitize(&mut device, Duration::ZERO, Duration::from_millis(0), &mut |_| {});
+        assert!(matches!(error_of(result), SanitizeError::Failed(_)));
+        assert_eq!(device.statuses.len(), 1);
+
+        //Finishing is still noticed right at the limit
+        device.statuses = vec![SanitizeStatus::Completed];
+        poll_sanitize(&mut device, Duration::ZERO, Duration::from_millis(0), &mut |_| {}).unwrap();
+    }
+
+    #[test]
+    fn sanitize_time_limit_follows_the_estimate() {
+        assert_eq!(sanitize_time_limit(Some(Duration::from_secs(3600))), Duration::from_secs(7200));
+        assert_eq!(sanitize_time_limit(Some(Duration::from_secs(5))), SANITIZE_MIN_TIME);
+        assert_eq!(sanitize_time_limit(None), SANITIZE_DEFAULT_TIME);
+    }
+
+    #[test]
+    fn nvme_unsupported_command_is_not_issued() {
+        let mut device = FakeDevice {
+            nvme: NvmeCapabilities {
+                format_supported: true,
+                ..NvmeCapabilities::default()
+            },
+            ..FakeDevice::default()
+        };
+        let result = run_sanitize(&mut device, SanitizeCommand::NvmeFormatCryptoErase, Duration::from_millis(0), |_| {});
+        assert_eq!(error_of(result), SanitizeError::Unsupported(SanitizeCommand::NvmeFormatCryptoErase));
+        assert!(device.calls.is_empty());
+        run_sanitize(&mut device, SanitizeCommand::NvmeFormatSecureErase, Duration::from_millis(0), |_| {}).unwrap();
+        assert_eq!(device.calls, vec!["format crypto=false"]);
+    }
+
+    #[test]
+    fn discard_requires_device_support() {
+        let mut device = FakeDevice::default();
+        let result = run_sanitize(&mut device, SanitizeCommand::Discard, Duration::from_millis(0), |_| {});
+        assert_eq!(error_of(result), SanitizeError::Unsupported(SanitizeCommand::Discard));
+        device.discard = true;
+        run_sanitize(&mut device, SanitizeCommand::SecureDiscard, Duration::from_millis(0), |_| {}).unwrap();
+        assert_eq!(device.calls, vec!["discard secure=true"]);
+    }
+}
@@ -0,0 +1,287 @@
+//Shredding of individual files and directories. Every file is overwritten in place with the passes
+//of a wipe method, renamed a few times to scrub its name from the directory, truncated and unlinked.
+
+use anyhow::{anyhow, Result};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::ffi::OsStr;
+use std::fs::{File, OpenOptions};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::disk::simulated::SimulatedDrive;
+use crate::wipe::engine::{run_passes, run_source, PatternSource, WipeProgress, CHUNK_SIZE};
+use crate::wipe::WipeMethod;
+
+//How many times a file is renamed before it gets unlinked
+const RENAME_ROUNDS: usize = 3;
+
+/// What a shred run went through, failures don't stop the remaining files from being shredded.
+#[derive(Debug, Default)]
+pub struct ShredSummary {
+    pub files: usize,
+    pub directories: usize,
+    pub bytes_written: u64,
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+///Overwriting in place only works when the filesystem writes back to the same blocks. Returns a warning
+/// for the filesystems we know don't, or only do so depending on their mount options.
+pub fn filesystem_warning(path: &Path) -> Option<String> {
+    let kind = match filesystem_type(path)? {
+        0x9123_683E => "btrfs is copy-on-write",
+        0x2FC1_2FC1 => "ZFS is copy-on-write",
+        0xF2F5_2010 => "F2FS is log-structured",
+        0x3434 => "NILFS is log-structured",
+        0xCA45_1A4E => "bcachefs is copy-on-write",
+        0x5846_5342 => "XFS may share blocks between reflinked files",
+        0xEF53 => "ext3/ext4 may keep copies of the data in its journal (data=journal) or snapshots",
+        _ => return None,
+    };
+    Some(format!(
+        "{}: overwriting {} in place may leave older copies of its contents on disk. \
+         Wipe the free space or the whole device if that matters.",
+        kind,
+        path.display()
+    ))
+}
+
+fn filesystem_type(path: &Path) -> Option<i64> {
+    let path_os: &OsStr = path.as_ref();
+    let mut cpath = path_os.as_bytes().to_vec();
+    cpath.push(0);
+    unsafe {
+        let mut stat: libc::statfs = std::mem::zeroed();
+        if libc::statfs(cpath.as_ptr() as *const _, &mut stat) == 0 {
+            #[allow(clippy::unnecessary_cast)]
+            return Some(stat.f_type as i64);
+        }
+    }
+    None
+}
+
+///Shreds a file, or with `recursive` set a whole directory tree. Symlinks are removed but never followed.
+/// A dry run overwrites simulated copies of the files and leaves the real ones alone.
+pub fn shred_path<F>(path: &Path, method: &WipeMethod, recursive: bool, dry_run: bool, mut on_progress: F) -> Result<ShredSummary>
+where
+    F: FnMut(&Path, &WipeProgress),
+{
+    if method.is_firmware() {
+        return Err(anyhow!("{} erases whole drives and can't shred files", method.name));
+    }
+    let metadata = std::fs::symlink_metadata(path)?;
+    let mut summary = ShredSummary::default();
+    if metadata.is_dir() {
+        if !recursive {
+            return Err(anyhow!("{} is a directory, shred it recursively", path.display()));
+        }
+        shred_directory(path, method, dry_run, &mut summary, &mut on_progress);
+    } else {
+        shred_entry(path, method, dry_run, &mut summary, &mut on_progress);
+    }
+    Ok(summary)
+}
+
+fn shred_directory<F>(path: &Path, method: &WipeMethod, dry_run: bool, summary: &mut ShredSummary, on_progress: &mut F)
+where
+    F: FnMut(&Path, &WipeProgress),
+{
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            summary.failures.push((path.to_path_buf(), e.to_string()));
+            return;
+        }
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                summary.failures.push((path.to_path_buf(), e.to_string()));
+                continue;
+            }
+        };
+        let entry_path = entry.path();
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => shred_directory(&entry_path, method, dry_run, summary, on_progress),
+            Ok(_) => shred_entry(&entry_path, method, dry_run, summary, on_progress),
+            Err(e) => summary.failures.push((entry_path, e.to_string())),
+        }
+    }
+    if dry_run {
+        summary.directories += 1;
+        return;
+    }
+    //Directory names leak too, scrub them the same way once they are empty
+    match scrub_name(path).and_then(|p| Ok(std::fs::remove_dir(p)?)) {
+        Ok(()) => summary.directories += 1,
+        Err(e) => summary.failures.push((path.to_path_buf(), e.to_string())),
+    }
+}
+
+fn shred_entry<F>(path: &Path, method: &WipeMethod, dry_run: bool, summary: &mut ShredSummary, on_progress: &mut F)
+where
+    F: FnMut(&Path, &WipeProgress),
+{
+    match shred_file(path, method, dry_run, on_progress) {
+        Ok(bytes) => {
+            summary.files += 1;
+            summary.bytes_written += bytes;
+        }
+        Err(e) => summary.failures.push((path.to_path_buf(), e.to_string())),
+    }
+}
+
+///Overwrites, renames, truncates and removes a single file. Returns the number of bytes written.
+fn shred_file<F>(path: &Path, method: &WipeMethod, dry_run: bool, on_progress: &mut F) -> Result<u64>
+where
+    F: FnMut(&Path, &WipeProgress),
+{
+    let metadata = std::fs::symlink_metadata(path)?;
+    if dry_run {
+        if !metadata.file_type().is_file() {
+            return Ok(0);
+        }
+        let source = PatternSource::new(&method.passes);
+        let mut file = SimulatedDrive::new(metadata.len(), CHUNK_SIZE, source.clone());
+        let outcome = run_source(&mut file, metadata.len(), &source, CHUNK_SIZE, |p| on_progress(path, p))?;
+        return Ok(outcome.bytes_written);
+    }
+    let mut bytes = 0;
+    //Only regular files have contents of their own, links and special files are just unlinked
+    if metadata.file_type().is_file() {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let outcome = run_passes(&mut file, metadata.len(), &method.passes, |p| on_progress(path, p))?;
+        if !outcome.verify_failures.is_empty() {
+            return Err(anyhow!("{} blocks did not verify", outcome.verify_failures.len()));
+        }
+        bytes = outcome.bytes_written;
+        drop(file);
+    }
+    let path = scrub_name(path)?;
+    if metadata.file_type().is_file() {
+        let file = OpenOptions::new().write(true).open(&path)?;
+        file.set_len(0)?;
+        file.sync_all()?;
+    }
+    std::fs::remove_file(&path)?;
+    sync_parent(&path)?;
+    Ok(bytes)
+}
+
+///Renames `path` through several random names of the same length so the original name is overwritten
+/// in the directory entry, returns the final path
+fn scrub_name(path: &Path) -> Result<PathBuf> {
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    let len = path.file_name().map_or(1, |n| n.len()).max(1);
+    let mut current = path.to_path_buf();
+    for _ in 0..RENAME_ROUNDS {
+        let name: String = rand::thread_rng().sample_iter(&Alphanumeric).take(len).map(char::from).collect();
+        let next = parent.join(name);
+        //Never clobber an existing file
+        if next.exists() {
+            continue;
+        }
+        std::fs::rename(&current, &next)?;
+        sync_parent(&next)?;
+        current = next;
+    }
+    Ok(current)
+}
+
+fn sync_parent(path: &Path) -> Result<()> {
+    let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wipe::{Pass, Pattern};
+
+    fn directory(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("minuteman-shred-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn method() -> WipeMethod {
+        WipeMethod::new("Test", "", vec![Pass::new(Pattern::Byte(0xAA)), Pass::verified(Pattern::Random)])
+    }
+
+    #[test]
+    fn files_are_overwritten_before_removal() {
+        let directory = directory("file");
+        let path = directory.join("secret.txt");
+        std::fs::write(&path, vec![b's'; 10_000]).unwrap();
+        //Look at the file while the first pass finishes
+        let mut first_pass = Vec::new();
+        let summary = shred_path(&path, &method(), false, false, |file, progress| {
+            if progress.pass == 1 && progress.bytes_done == progress.bytes_total {
+                first_pass = std::fs::read(file).unwrap();
+            }
+        })
+        .unwrap();
+        let left: Vec<_> = std::fs::read_dir(&directory).unwrap().collect();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(first_pass, vec![0xAA; 10_000]);
+        assert_eq!(summary.files, 1);
+        assert_eq!(summary.bytes_written, 20_000);
+        assert!(summary.failures.is_empty());
+        assert!(left.is_empty());
+    }
+
+    #[test]
+    fn names_are_scrubbed() {
+        let directory = directory("name");
+        let path = directory.join("secret.txt");
+        std::fs::write(&path, b"data").unwrap();
+        let scrubbed = scrub_name(&path).unwrap();
+        let contents = std::fs::read(&scrubbed).unwrap();
+        let original_exists = path.exists();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(!original_exists);
+        assert_eq!(scrubbed.parent(), Some(directory.as_path()));
+        assert_ne!(scrubbed.file_name(), path.file_name());
+        assert_eq!(scrubbed.file_name().unwrap().len(), "secret.txt".len());
+        assert_eq!(contents, b"data");
+    }
+
+    #[test]
+    fn directories_are_walked_and_links_never_followed() {
+        let directory = directory("tree");
+        let outside = directory.join("outside.txt");
+        std::fs::write(&outside, b"keep me").unwrap();
+        let tree = directory.join("tree");
+        std::fs::create_dir_all(tree.join("nested")).unwrap();
+        std::fs::write(tree.join("a.txt"), b"a").unwrap();
+        std::fs::write(tree.join("nested").join("b.txt"), b"bb").unwrap();
+        std::os::unix::fs::symlink(&outside, tree.join("nested").join("link")).unwrap();
+
+        let refused = shred_path(&tree, &method(), false, false, |_, _| {});
+        let summary = shred_path(&tree, &method(), true, false, |_, _| {}).unwrap();
+        let tree_exists = tree.exists();
+        let outside_contents = std::fs::read(&outside).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(refused.is_err());
+        assert!(!tree_exists);
+        assert_eq!(outside_contents, b"keep me");
+        assert_eq!((summary.files, summary.directories), (3, 2));
+        assert_eq!(summary.bytes_written, 3 * 2);
+        assert!(summary.failures.is_empty());
+    }
+
+    #[test]
+    fn dry_runs_leave_files_alone() {
+        let directory = directory("dry");
+        std::fs::write(directory.join("a.txt"), b"untouched").unwrap();
+        let summary = shred_path(&directory, &method(), true, true, |_, _| {}).unwrap();
+        let contents = std::fs::read(directory.join("a.txt")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(contents, b"untouched");
+        assert_eq!((summary.files, summary.directories), (1, 1));
+        assert_eq!(summary.bytes_written, 9 * 2);
+    }
+}
@@ -0,0 +1,295 @@
+//exFAT: same idea as FAT32, but file data may be stored without a FAT chain and entries come in sets
+use anyhow::{anyhow, Result};
+
+use crate::disk::DriveAccessor;
+use crate::slack::{le32, le64, CleanSummary, Volume};
+
+const END_OF_CHAIN: u32 = 0xFFFF_FFF7;
+const IN_USE: u8 = 0x80;
+const FILE_ENTRY: u8 = 0x85;
+const STREAM_EXTENSION: u8 = 0xC0;
+const ATTR_DIRECTORY: u16 = 0x10;
+const NO_FAT_CHAIN: u8 = 0x02;
+
+struct ExFat {
+    cluster_size: u64,
+    heap_start: u64,
+    cluster_count: u32,
+    fat: Vec<u8>,
+}
+
+//Where a file or directory keeps its data
+#[derive(Clone, Copy)]
+struct Extent {
+    first_cluster: u32,
+    length: u64,
+    contiguous: bool,
+}
+
+impl ExFat {
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.heap_start + u64::from(cluster - 2) * self.cluster_size
+    }
+
+    //Clusters holding an extent, which has to stay inside the cluster heap
+    fn clusters(&self, extent: Extent) -> Result<Vec<u32>> {
+        let end = u64::from(self.cluster_count) + 2;
+        if extent.first_cluster < 2 || u64::from(extent.first_cluster) >= end {
+            return Err(anyhow!("cluster {} is outside the exFAT volume", extent.first_cluster));
+        }
+        if extent.contiguous {
+            let count = extent.length.div_ceil(self.cluster_size);
+            if u64::from(extent.first_cluster) + count > end {
+                return Err(anyhow!("extent at cluster {} runs past the end of the exFAT volume", extent.first_cluster));
+            }
+            return Ok((extent.first_cluster..extent.first_cluster + count as u32).collect());
+        }
+        let mut chain = Vec::new();
+        let mut cluster = extent.first_cluster;
+        loop {
+            if cluster < 2 || u64::from(cluster) >= end {
+                return Err(anyhow!("cluster {} is outside the exFAT volume", cluster));
+            }
+            if chain.len() > self.cluster_count as usize {
+                return Err(anyhow!("loop in the exFAT allocation table at cluster {}", extent.first_cluster));
+            }
+            chain.push(cluster);
+            let next = le32(&self.fat, cluster as usize * 4);
+            if next >= END_OF_CHAIN {
+                return Ok(chain);
+            }
+            cluster = next;
+        }
+    }
+}
+
+pub(crate) fn clean<T, F>(volume: &mut Volume<T>, mut on_progress: F) -> Result<CleanSummary>
+where
+    T: DriveAccessor + ?Sized,
+    F: FnMut(u64, u64),
+{
+    let mut boot = [0u8; 512];
+    volume.read_at(0, &mut boot)?;
+    //Sectors are 512 bytes to 4 KiB and clusters at most 32 MiB
+    if !(9..=12).contains(&boot[108]) || boot[108] + boot[109] > 25 {
+        return Err(anyhow!("corrupt exFAT boot sector"));
+    }
+    let sector_size = 1u64 << boot[108];
+    let cluster_size = sector_size << boot[109];
+    let cluster_count = le32(&boot, 92);
+    //Only the part of the FAT covering the heap is read, the boot sector has to say there is that much
+    let fat_size = (u64::from(cluster_count) + 2) * 4;
+    if u64::from(le32(&boot, 84)) * sector_size < fat_size {
+        return Err(anyhow!("corrupt exFAT boot sector, the FAT is too short for {} clusters", cluster_count));
+    }
+    let mut fat = vec![0u8; fat_size as usize];
+    volume.read_at(u64::from(le32(&boot, 80)) * sector_size, &mut fat)?;
+    let fs = ExFat {
+        cluster_size,
+        heap_start: u64::from(le32(&boot, 88)) * sector_size,
+        cluster_count,
+        fat,
+    };
+
+    let mut summary = CleanSummary::default();
+    let root = Extent {
+        first_cluster: le32(&boot, 96),
+        length: 0,
+        contiguous: false,
+    };
+    let mut pending = vec![root];
+    let mut done = 0;
+    while let Some(directory) = pending.pop() {
+        clean_directory(volume, &fs, directory, &mut pending, &mut summary)?;
+        done += 1;
+        on_progress(done, done + pending.len() as u64);
+    }
+    Ok(summary)
+}
+
+fn clean_directory<T: DriveAccessor + ?Sized>(
+    volume: &mut Volume<T>,
+    fs: &ExFat,
+    directory: Extent,
+    pending: &mut Vec<Extent>,
+    summary: &mut CleanSummary,
+) -> Result<()> {
+    //Entry sets can cross cluster boundaries, so work on the whole directory at once
+    let clusters = fs.clusters(directory)?;
+    let size = fs.cluster_size as usize;
+    let mut data = vec![0u8; clusters.len() * size];
+    for (i, cluster) in clusters.iter().enumerate() {
+        volume.read_at(fs.cluster_offset(*cluster), &mut data[i * size..(i + 1) * size])?;
+    }
+    let mut modified = vec![false; clusters.len()];
+    let mut ended = false;
+    let mut i = 0;
+    while i + 32 <= data.len() {
+        let kind = data[i];
+        if ended || kind == 0x00 {
+            ended = true;
+            if data[i..i + 32].iter().any(|b| *b != 0) {
+                data[i..i + 32].iter_mut().for_each(|b| *b = 0);
+                summary.entries_cleared += 1;
+                modified[i / size] = true;
+            }
+        } else if kind & IN_USE == 0 {
+            //Deleted entry, keep its type so the directory still parses
+            if data[i + 1..i + 32].iter().any(|b| *b != 0) {
+                data[i + 1..i + 32].iter_mut().for_each(|b| *b = 0);
+                summary.entries_cleared += 1;
+                modified[i / size] = true;
+            }
+        } else if kind == FILE_ENTRY && i + 64 <= data.len() && data[i + 32] == STREAM_EXTENSION {
+            let attributes = u16::from_le_bytes([data[i + 4], data[i + 5]]);
+            let stream = &data[i + 32..i + 64];
+            let extent = Extent {
+                first_cluster: le32(stream, 20),
+                length: le64(stream, 24),
+                contiguous: stream[1] & NO_FAT_CHAIN != 0,
+            };
+            if attributes & ATTR_DIRECTORY != 0 {
+                pending.push(extent);
+            } else {
+                summary.files += 1;
+                summary.slack_bytes += clean_file_tail(volume, fs, extent)?;
+            }
+        }
+        i += 32;
+    }
+    for (index, cluster) in clusters.iter().enumerate() {
+        if modified[index] {
+            volume.write_at(fs.cluster_offset(*cluster), &data[index * size..(index + 1) * size])?;
+        }
+    }
+    Ok(())
+}
+
+fn clean_file_tail<T: DriveAccessor + ?Sized>(volume: &mut Volume<T>, fs: &ExFat, extent: Extent) -> Result<u64> {
+    let used = extent.length % fs.cluster_size;
+    if extent.length == 0 || used == 0 {
+        return Ok(0);
+    }
+    let last = ((extent.length - 1) / fs.cluster_size) as usize;
+    match fs.clusters(extent)?.get(last) {
+        Some(cluster) => {
+            let len = (fs.cluster_size - used) as usize;
+            let zeroed = volume.zero_range(fs.cluster_offset(*cluster) + used, len)?;
+            Ok(if zeroed { len as u64 } else { 0 })
+        }
+        None => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::slack::deep_clean;
+    use crate::slack::tests::{contents, image, put};
+
+    const SECTOR: usize = 512;
+    const FAT_START: usize = 24 * SECTOR;
+    const HEAP_START: usize = 32 * SECTOR;
+
+    fn cluster(n: usize) -> usize {
+        HEAP_START + (n - 2) * SECTOR
+    }
+
+    //File entry, stream extension and name entry of one file or directory
+    fn entry_set(directory: bool, first_cluster: u32, length: u64, contiguous: bool) -> [u8; 96] {
+        let mut set = [0u8; 96];
+        set[0] = 0x85;
+        set[1] = 2;
+        set[4] = if directory { 0x10 } else { 0x20 };
+        set[32] = 0xC0;
+        set[33] = if contiguous { 0x03 } else { 0x01 };
+        set[32 + 20..32 + 24].copy_from_slice(&first_cluster.to_le_bytes());
+        set[32 + 24..32 + 32].copy_from_slice(&length.to_le_bytes());
+        set[64] = 0xC1;
+        set[66..70].copy_from_slice(b"n\0a\0");
+        set
+    }
+
+    //One sector clusters: root at 2, a.txt chained over 3 and 4, b.txt contiguous over 5 and 6 and
+    //sub at 7 holding c.txt at 8
+    fn exfat() -> Vec<u8> {
+        let mut data = vec![0u8; cluster(30)];
+        put(&mut data, 3, b"EXFAT   ");
+        put(&mut data, 80, &((FAT_START / SECTOR) as u32).to_le_bytes());
+        put(&mut data, 84, &1u32.to_le_bytes());
+        put(&mut data, 88, &((HEAP_START / SECTOR) as u32).to_le_bytes());
+        put(&mut data, 92, &28u32.to_le_bytes());
+        put(&mut data, 96, &2u32.to_le_bytes());
+        data[108] = 9;
+        data[109] = 0;
+        put(&mut data, 510, &[0x55, 0xAA]);
+        for (index, next) in [(0, 0xFFFF_FFF8u32), (1, 0xFFFF_FFFF), (2, 0xFFFF_FFFF), (3, 4), (4, 0xFFFF_FFFF), (8, 0xFFFF_FFFF)] {
+            put(&mut data, FAT_START + index * 4, &next.to_le_bytes());
+        }
+
+        let root = cluster(2);
+        data[root] = 0x83; //Volume label
+        put(&mut data, root + 32, &entry_set(false, 3, 700, false));
+        put(&mut data, root + 128, &entry_set(false, 5, 600, true));
+        let mut deleted = entry_set(false, 12, 50, false);
+        for kind in [0, 32, 64] {
+            deleted[kind] &= 0x7F;
+        }
+        put(&mut data, root + 224, &deleted);
+        put(&mut data, root + 320, &entry_set(true, 7, 512, true));
+        //End of directory marker followed by a stale file entry
+        put(&mut data, root + 448, &entry_set(false, 13, 10, false)[..32]);
+        put(&mut data, cluster(7), &entry_set(false, 8, 5, false));
+
+        data[cluster(3)..cluster(7)].iter_mut().for_each(|b| *b = b'a');
+        data[cluster(8)..cluster(9)].iter_mut().for_each(|b| *b = b'c');
+        data
+    }
+
+    #[test]
+    fn clears_slack_and_deleted_entries() {
+        let original = exfat();
+        let mut drive = image(&original);
+        let summary = deep_clean(&mut drive, |_, _| {}).unwrap();
+        let data = contents(&mut drive, original.len());
+        assert_eq!(summary.files, 3);
+        assert_eq!(summary.slack_bytes, (512 - 188) + (512 - 88) + (512 - 5));
+        assert_eq!(summary.entries_cleared, 4);
+
+        for (first, last, used) in [(3, 4, 188), (5, 6, 88), (8, 8, 5)] {
+            assert_eq!(data[cluster(first)..cluster(last) + used], original[cluster(first)..cluster(last) + used]);
+            assert!(data[cluster(last) + used..cluster(last + 1)].iter().all(|b| *b == 0));
+        }
+
+        //Deleted entries keep their type, the stale entry past the end is gone
+        let root = cluster(2);
+        for kind in [224, 256, 288] {
+            assert_eq!(data[root + kind], original[root + kind]);
+            assert!(data[root + kind + 1..root + kind + 32].iter().all(|b| *b == 0));
+        }
+        assert!(data[root + 416..cluster(3)].iter().all(|b| *b == 0));
+        assert_eq!(data[..root + 224], original[..root + 224]);
+        assert_eq!(data[root + 320..root + 416], original[root + 320..root + 416]);
+        assert_eq!(data[cluster(7)..cluster(8)], original[cluster(7)..cluster(8)]);
+    }
+
+    #[test]
+    fn corrupt_volumes_fail_cleanly() {
+        //Shifts that used to overflow before being checked
+        let mut data = exfat();
+        data[108] = 200;
+        assert!(deep_clean(&mut image(&data), |_, _| {}).is_err());
+        let mut data = exfat();
+        data[109] = 60;
+        assert!(deep_clean(&mut image(&data), |_, _| {}).is_err());
+
+        //b.txt runs past the cluster heap
+        let mut data = exfat();
+        put(&mut data, cluster(2) + 128 + 32 + 24, &((1u64 << 40) + 1).to_le_bytes());
+        assert!(deep_clean(&mut image(&data), |_, _| {}).is_err());
+
+        //More clusters than the FAT has entries for
+        let mut data = exfat();
+        put(&mut data, 92, &1000u32.to_le_bytes());
+        assert!(deep_clean(&mut image(&data), |_, _| {}).is_err());
+    }
+}
@@ -0,0 +1,444 @@
+//ext4: zeroes file tails, the slack left behind deleted entries in directory blocks and unused inodes.
+//Directory blocks carry a checksum on metadata_csum filesystems which is updated after every change.
+use anyhow::{anyhow, Result};
+
+use crate::disk::DriveAccessor;
+use crate::slack::{le16, le32, CleanSummary, Volume};
+
+const INCOMPAT_RECOVER: u32 = 0x4;
+const INCOMPAT_META_BG: u32 = 0x10;
+const INCOMPAT_64BIT: u32 = 0x80;
+const INCOMPAT_CSUM_SEED: u32 = 0x2000;
+const RO_COMPAT_METADATA_CSUM: u32 = 0x400;
+const BG_INODE_UNINIT: u16 = 0x1;
+const EXTENTS_FL: u32 = 0x80000;
+const INDEX_FL: u32 = 0x1000;
+const INLINE_DATA_FL: u32 = 0x1000_0000;
+const EXTENT_MAGIC: u16 = 0xF30A;
+const MODE_DIRECTORY: u16 = 0x4000;
+const MODE_REGULAR: u16 = 0x8000;
+const DIRENT_TAIL_SIZE: usize = 12;
+
+struct Ext4 {
+    block_size: u64,
+    inode_size: usize,
+    inodes_per_group: u32,
+    first_inode: u32,
+    csum_seed: Option<u32>, //Only set with metadata_csum
+}
+
+//CRC32C as used by ext4, no pre or post inversion
+fn crc32c(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x82F6_3B78 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+impl Ext4 {
+    //Maps a logical block of an inode to its physical block, following extents or the old block map
+    fn map_block<T: DriveAccessor + ?Sized>(&self, volume: &mut Volume<T>, inode: &[u8], logical: u32) -> Result<Option<u64>> {
+        let flags = le32(inode, 32);
+        let i_block = &inode[40..100];
+        if flags & EXTENTS_FL != 0 {
+            return self.map_extent(volume, i_block, logical, 0);
+        }
+        //Block map: 12 direct blocks followed by single, double and triple indirect ones
+        let per_block = self.block_size / 4;
+        let mut index = u64::from(logical);
+        if index < 12 {
+            return Ok(Some(u64::from(le32(i_block, index as usize * 4))).filter(|b| *b != 0));
+        }
+        index -= 12;
+        let mut span = per_block;
+        for level in 0..3 {
+            if index < span {
+                let mut block = u64::from(le32(i_block, (12 + level) * 4));
+                let mut span = span;
+                for _ in 0..=level {
+                    if block == 0 {
+                        return Ok(None);
+                    }
+                    span /= per_block;
+                    let mut table = vec![0u8; self.block_size as usize];
+                    volume.read_at(block * self.block_size, &mut table)?;
+                    block = u64::from(le32(&table, ((index / span) % per_block) as usize * 4));
+                }
+                return Ok(Some(block).filter(|b| *b != 0));
+            }
+            index -= span;
+            span *= per_block;
+        }
+        Ok(None)
+    }
+
+    fn map_extent<T: DriveAccessor + ?Sized>(&self, volume: &mut Volume<T>, node: &[u8], logical: u32, depth_guard: u32) -> Result<Option<u64>> {
+        if le16(node, 0) != EXTENT_MAGIC || depth_guard > 5 {
+            return Ok(None);
+        }
+        let entries = le16(node, 2) as usize;
+        let depth = le16(node, 6);
+        let entry = |i: usize| &node[12 + i * 12..24 + i * 12];
+        if depth == 0 {
+            for i in 0..entries.min((node.len() - 12) / 12) {
+                let e = entry(i);
+                let start = le32(e, 0);
+                //Lengths above 32768 mark uninitialized extents
+                let raw_len = le16(e, 4);
+                let len = u32::from(if raw_len > 32768 { raw_len - 32768 } else { raw_len });
+                if logical >= start && u64::from(logical) < u64::from(start) + u64::from(len) {
+                    let physical = (u64::from(le16(e, 6)) << 32) | u64::from(le32(e, 8));
+                    return Ok(Some(physical + u64::from(logical - start)));
+                }
+            }
+            return Ok(None);
+        }
+        //Index node, descend into the last child starting at or before the block
+        let mut child = None;
+        for i in 0..entries.min((node.len() - 12) / 12) {
+            let e = entry(i);
+            if le32(e, 0) <= logical {
+                child = Some((u64::from(le16(e, 8)) << 32) | u64::from(le32(e, 4)));
+            }
+        }
+        match child {
+            Some(block) => {
+                let mut data = vec![0u8; self.block_size as usize];
+                volume.read_at(block * self.block_size, &mut data)?;
+                self.map_extent(volume, &data, logical, depth_guard + 1)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+pub(crate) fn clean<T, F>(volume: &mut Volume<T>, mut on_progress: F) -> Result<CleanSummary>
+where
+    T: DriveAccessor + ?Sized,
+    F: FnMut(u64, u64),
+{
+    let mut sb = vec![0u8; 1024];
+    volume.read_at(1024, &mut sb)?;
+    let incompat = le32(&sb, 96);
+    let ro_compat = le32(&sb, 100);
+    if incompat & INCOMPAT_RECOVER != 0 {
+        return Err(anyhow!("the ext4 journal needs recovery, run fsck first"));
+    }
+    //meta_bg spreads the group descriptors over the disk instead of keeping them after the superblock
+    if incompat & INCOMPAT_META_BG != 0 {
+        return Err(anyhow!("ext4 filesystems with meta_bg are not supported"));
+    }
+    if le32(&sb, 24) > 6 {
+        return Err(anyhow!("corrupt ext4 superblock"));
+    }
+    let block_size = 1024u64 << le32(&sb, 24);
+    let blocks = u64::from(le32(&sb, 4)) | if incompat & INCOMPAT_64BIT != 0 { u64::from(le32(&sb, 0x150)) << 32 } else { 0 };
+    let first_data_block = u64::from(le32(&sb, 20));
+    let blocks_per_group = u64::from(le32(&sb, 32));
+    let desc_size = if incompat & INCOMPAT_64BIT != 0 { le16(&sb, 254) as usize } else { 32 };
+    let csum_seed = if ro_compat & RO_COMPAT_METADATA_CSUM == 0 {
+        None
+    } else if incompat & INCOMPAT_CSUM_SEED != 0 {
+        Some(le32(&sb, 0x270))
+    } else {
+        Some(crc32c(!0, &sb[104..120]))
+    };
+    let fs = Ext4 {
+        block_size,
+        inode_size: if le32(&sb, 76) == 0 { 128 } else { le16(&sb, 88) as usize },
+        inodes_per_group: le32(&sb, 40),
+        first_inode: if le32(&sb, 76) == 0 { 11 } else { le32(&sb, 84) },
+        csum_seed,
+    };
+    //A group's inodes have to fit in its one block bitmap
+    if blocks_per_group == 0
+        || fs.inodes_per_group == 0
+        || u64::from(fs.inodes_per_group) > block_size * 8
+        || fs.inode_size < 128
+        || desc_size < 32
+        || blocks <= first_data_block
+    {
+        return Err(anyhow!("corrupt ext4 superblock"));
+    }
+
+    let groups = (blocks - first_data_block).div_ceil(blocks_per_group);
+    if groups * u64::from(fs.inodes_per_group) > u64::from(u32::MAX) {
+        return Err(anyhow!("corrupt ext4 superblock"));
+    }
+    let mut descriptors = vec![0u8; groups as usize * desc_size];
+    volume.read_at((first_data_block + 1) * block_size, &mut descriptors)?;
+
+    let mut summary = CleanSummary::default();
+    for group in 0..groups as usize {
+        let desc = &descriptors[group * desc_size..(group + 1) * desc_size];
+        let high = |offset: usize| if desc_size >= 64 { u64::from(le32(desc, offset)) << 32 } else { 0 };
+        let inode_bitmap = u64::from(le32(desc, 4)) | high(0x24);
+        let inode_table = u64::from(le32(desc, 8)) | high(0x28);
+        let flags = le16(desc, 18);
+
+        //Uninitialized groups have no inodes in use and their bitmap may not even be written yet
+        let mut bitmap = vec![0u8; block_size as usize];
+        if flags & BG_INODE_UNINIT == 0 {
+            volume.read_at(inode_bitmap * block_size, &mut bitmap)?;
+        }
+        let mut table = vec![0u8; fs.inodes_per_group as usize * fs.inode_size];
+        volume.read_at(inode_table * block_size, &mut table)?;
+
+        for index in 0..fs.inodes_per_group as usize {
+            let number = group as u32 * fs.inodes_per_group + index as u32 + 1;
+            let offset = index * fs.inode_size;
+            let inode = &table[offset..offset + fs.inode_size];
+            let in_use = bitmap[index / 8] & (1 << (index % 8)) != 0;
+            if !in_use {
+                //Reserved inodes are left alone even when unused
+                if number >= fs.first_inode && inode.iter().any(|b| *b != 0) {
+                    volume.write_at(inode_table * block_size + offset as u64, &vec![0u8; fs.inode_size])?;
+                    summary.inodes_cleared += 1;
+                }
+                continue;
+            }
+            match le16(inode, 0) & 0xF000 {
+                MODE_DIRECTORY => summary.entries_cleared += clean_directory(volume, &fs, inode, number)?,
+                MODE_REGULAR => {
+                    summary.files += 1;
+                    summary.slack_bytes += clean_file_tail(volume, &fs, inode)?;
+                }
+                _ => {}
+            }
+        }
+        on_progress(group as u64 + 1, groups);
+    }
+    Ok(summary)
+}
+
+fn inode_size_bytes(inode: &[u8]) -> u64 {
+    u64::from(le32(inode, 4)) | (u64::from(le32(inode, 108)) << 32)
+}
+
+fn clean_file_tail<T: DriveAccessor + ?Sized>(volume: &mut Volume<T>, fs: &Ext4, inode: &[u8]) -> Result<u64> {
+    let size = inode_size_bytes(inode);
+    let used = size % fs.block_size;
+    if size == 0 || used == 0 || le32(inode, 32) & INLINE_DATA_FL != 0 {
+        return Ok(0);
+    }
+    let last = ((size - 1) / fs.block_size) as u32;
+    match fs.map_block(volume, inode, last)? {
+        Some(block) => {
+            let len = (fs.block_size - used) as usize;
+            let zeroed = volume.zero_range(block * fs.block_size + used, len)?;
+            Ok(if zeroed { len as u64 } else { 0 })
+        }
+        None => Ok(0), //Sparse tail, nothing on disk
+    }
+}
+
+fn clean_directory<T: DriveAccessor + ?Sized>(volume: &mut Volume<T>, fs: &Ext4, inode: &[u8], number: u32) -> Result<u64> {
+    let flags = le32(inode, 32);
+    if flags & INLINE_DATA_FL != 0 {
+        return Ok(0);
+    }
+    let indexed = flags & INDEX_FL != 0;
+    let block_size = fs.block_size as usize;
+    let blocks = inode_size_bytes(inode).div_ceil(fs.block_size) as u32;
+    let mut cleared = 0;
+    for logical in 0..blocks {
+        //The root of a hashed directory holds the index, not entries
+        if indexed && logical == 0 {
+            continue;
+        }
+        let physical = match fs.map_block(volume, inode, logical)? {
+            Some(block) => block,
+            None => continue,
+        };
+        let mut data = vec![0u8; block_size];
+        volume.read_at(physical * fs.block_size, &mut data)?;
+        let tail = block_size - DIRENT_TAIL_SIZE;
+        let has_tail = le32(&data, tail) == 0 && le16(&data, tail + 4) == 12 && data[tail + 7] == 0xDE;
+        let limit = if has_tail { tail } else { block_size };
+        //Interior nodes of the hash tree look like one empty entry spanning the block, leave them be
+        if indexed && le32(&data, 0) == 0 && le16(&data, 4) as usize >= limit {
+            continue;
+        }
+
+        let mut modified = false;
+        let mut position = 0;
+        while position + 8 <= limit {
+            let entry_inode = le32(&data, position);
+            let record_length = le16(&data, position + 4) as usize;
+            if record_length < 8 || position + record_length > limit {
+                break; //Corrupt block, stop touching it
+            }
+            //Live entries keep their name, deleted names hide in the space up to the next record
+            let used = if entry_inode != 0 {
+                8 + ((data[position + 6] as usize + 3) & !3)
+            } else {
+                6
+            };
+            if used < record_length && data[position + used..position + record_length].iter().any(|b| *b != 0) {
+                data[position + used..position + record_length].iter_mut().for_each(|b| *b = 0);
+                cleared += 1;
+                modified = true;
+            }
+            position += record_length;
+        }
+        if !modified {
+            continue;
+        }
+        if let (true, Some(seed)) = (has_tail, fs.csum_seed) {
+            let seed = crc32c(seed, &number.to_le_bytes());
+            let seed = crc32c(seed, &inode[100..104]);
+            let checksum = crc32c(seed, &data[..tail]);
+            data[block_size - 4..].copy_from_slice(&checksum.to_le_bytes());
+        }
+        volume.write_at(physical * fs.block_size, &data)?;
+    }
+    Ok(cleared)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slack::deep_clean;
+    use crate::slack::tests::{contents, image, put};
+
+    const BLOCK: usize = 1024;
+    const INODE_SIZE: usize = 256;
+    const INODE_TABLE: usize = 5 * BLOCK;
+    const ROOT_BLOCK: usize = 20;
+    const UUID: [u8; 16] = *b"minuteman-test!!";
+
+    fn inode(number: usize) -> usize {
+        INODE_TABLE + (number - 1) * INODE_SIZE
+    }
+
+    fn dirent(data: &mut [u8], offset: usize, inode: u32, record_length: u16, name: &[u8]) {
+        put(data, offset, &inode.to_le_bytes());
+        put(data, offset + 4, &record_length.to_le_bytes());
+        data[offset + 6] = name.len() as u8;
+        data[offset + 7] = 1;
+        put(data, offset + 8, name);
+    }
+
+    //Tail checksum of a directory block the way the kernel computes it
+    fn dirent_checksum(data: &[u8], number: u32, generation: &[u8]) -> u32 {
+        let seed = crc32c(!0, &UUID);
+        let seed = crc32c(crc32c(seed, &number.to_le_bytes()), generation);
+        crc32c(seed, &data[..BLOCK - DIRENT_TAIL_SIZE])
+    }
+
+    //One group of 1 KiB blocks with metadata_csum. The root directory (inode 2) is at block 20, file
+    //12 uses an extent over blocks 21 and 22, file 14 the old block map at block 23 and inode 13 is
+    //unused but still holds a deleted file
+    fn ext4() -> Vec<u8> {
+        let mut data = vec![0u8; 64 * BLOCK];
+        let sb = 1024;
+        put(&mut data, sb + 4, &64u32.to_le_bytes());
+        put(&mut data, sb + 20, &1u32.to_le_bytes());
+        put(&mut data, sb + 32, &8192u32.to_le_bytes());
+        put(&mut data, sb + 40, &32u32.to_le_bytes());
+        put(&mut data, sb + 56, &0xEF53u16.to_le_bytes());
+        put(&mut data, sb + 76, &1u32.to_le_bytes());
+        put(&mut data, sb + 84, &11u32.to_le_bytes());
+        put(&mut data, sb + 88, &(INODE_SIZE as u16).to_le_bytes());
+        put(&mut data, sb + 96, &0x42u32.to_le_bytes()); //filetype and extents
+        put(&mut data, sb + 100, &RO_COMPAT_METADATA_CSUM.to_le_bytes());
+        put(&mut data, sb + 104, &UUID);
+        //Group descriptor: inode bitmap at block 4, inode table at 5
+        put(&mut data, 2 * BLOCK + 4, &4u32.to_le_bytes());
+        put(&mut data, 2 * BLOCK + 8, &5u32.to_le_bytes());
+        put(&mut data, 4 * BLOCK, &[0xFF, 0x2F]); //Inodes 1 to 12 and 14
+
+        let extent = |data: &mut Vec<u8>, number: usize, blocks: u16, start: u32| {
+            put(data, inode(number) + 32, &EXTENTS_FL.to_le_bytes());
+            put(data, inode(number) + 40, &EXTENT_MAGIC.to_le_bytes());
+            put(data, inode(number) + 42, &1u16.to_le_bytes());
+            put(data, inode(number) + 44, &4u16.to_le_bytes());
+            put(data, inode(number) + 56, &blocks.to_le_bytes());
+            put(data, inode(number) + 60, &start.to_le_bytes());
+        };
+        put(&mut data, inode(2), &0x41EDu16.to_le_bytes());
+        put(&mut data, inode(2) + 4, &(BLOCK as u32).to_le_bytes());
+        put(&mut data, inode(2) + 100, &0x1234_5678u32.to_le_bytes());
+        extent(&mut data, 2, 1, ROOT_BLOCK as u32);
+        put(&mut data, inode(12), &0x81A4u16.to_le_bytes());
+        put(&mut data, inode(12) + 4, &1500u32.to_le_bytes());
+        extent(&mut data, 12, 2, 21);
+        put(&mut data, inode(13), &0x81A4u16.to_le_bytes());
+        put(&mut data, inode(13) + 4, &40u32.to_le_bytes());
+        put(&mut data, inode(14), &0x81A4u16.to_le_bytes());
+        put(&mut data, inode(14) + 4, &100u32.to_le_bytes());
+        put(&mut data, inode(14) + 40, &23u32.to_le_bytes());
+
+        //file with the leftovers of a deleted entry it swallowed, then map and an emptied entry
+        let root = ROOT_BLOCK * BLOCK;
+        dirent(&mut data, root, 2, 12, b".");
+        dirent(&mut data, root + 12, 2, 12, b"..");
+        dirent(&mut data, root + 24, 12, 40, b"file");
+        dirent(&mut data, root + 36, 13, 28, b"secret.txt");
+        dirent(&mut data, root + 64, 14, 12, b"map");
+        dirent(&mut data, root + 76, 0, (BLOCK - DIRENT_TAIL_SIZE - 76) as u16, b"gone");
+        let tail = root + BLOCK - DIRENT_TAIL_SIZE;
+        put(&mut data, tail + 4, &12u16.to_le_bytes());
+        data[tail + 7] = 0xDE;
+        let checksum = dirent_checksum(&data[root..root + BLOCK], 2, &0x1234_5678u32.to_le_bytes());
+        put(&mut data, tail + 8, &checksum.to_le_bytes());
+
+        data[21 * BLOCK..24 * BLOCK].iter_mut().for_each(|b| *b = b'f');
+        data
+    }
+
+    #[test]
+    fn crc32c_matches_the_standard() {
+        assert_eq!(!crc32c(!0, b"123456789"), 0xE306_9283);
+    }
+
+    #[test]
+    fn clears_slack_deleted_entries_and_inodes() {
+        let original = ext4();
+        let mut drive = image(&original);
+        let summary = deep_clean(&mut drive, |_, _| {}).unwrap();
+        let data = contents(&mut drive, original.len());
+        assert_eq!(summary.files, 2);
+        assert_eq!(summary.slack_bytes, (BLOCK as u64 - 476) + (BLOCK as u64 - 100));
+        assert_eq!(summary.entries_cleared, 2);
+        assert_eq!(summary.inodes_cleared, 1);
+
+        for (first, last, used) in [(21, 22, 476), (23, 23, 100)] {
+            assert_eq!(data[first * BLOCK..last * BLOCK + used], original[first * BLOCK..last * BLOCK + used]);
+            assert!(data[last * BLOCK + used..(last + 1) * BLOCK].iter().all(|b| *b == 0));
+        }
+        assert!(data[inode(13)..inode(14)].iter().all(|b| *b == 0));
+        assert_eq!(data[INODE_TABLE..inode(13)], original[INODE_TABLE..inode(13)]);
+        assert_eq!(data[inode(14)..inode(15)], original[inode(14)..inode(15)]);
+
+        //Live entries are untouched, the names of deleted ones are gone
+        let root = ROOT_BLOCK * BLOCK;
+        let block = &data[root..root + BLOCK];
+        assert_eq!(block[..36], original[root..root + 36]);
+        assert!(block[36..64].iter().all(|b| *b == 0));
+        assert_eq!(block[64..82], original[root + 64..root + 82]);
+        assert!(block[82..BLOCK - DIRENT_TAIL_SIZE].iter().all(|b| *b == 0));
+        let checksum = dirent_checksum(block, 2, &0x1234_5678u32.to_le_bytes());
+        assert_eq!(le32(block, BLOCK - 4), checksum);
+        assert_ne!(checksum, le32(&original, root + BLOCK - 4));
+    }
+
+    #[test]
+    fn unsupported_and_corrupt_volumes_fail_cleanly() {
+        let mut data = ext4();
+        put(&mut data, 1024 + 96, &(0x42 | INCOMPAT_META_BG).to_le_bytes());
+        assert!(deep_clean(&mut image(&data), |_, _| {}).is_err());
+
+        let mut data = ext4();
+        put(&mut data, 1024 + 24, &40u32.to_le_bytes());
+        assert!(deep_clean(&mut image(&data), |_, _| {}).is_err());
+
+        //More inodes per group than the bitmap block can track
+        let mut data = ext4();
+        put(&mut data, 1024 + 40, &9000u32.to_le_bytes());
+        assert!(deep_clean(&mut image(&data), |_, _| {}).is_err());
+    }
+}
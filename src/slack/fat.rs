@@ -0,0 +1,270 @@
+//FAT32: zeroes the tail of each file's last cluster, scrubs deleted entries and anything left past
+//the end of directory marker
+use anyhow::{anyhow, Result};
+
+use crate::disk::DriveAccessor;
+use crate::slack::{le16, le32, CleanSummary, Volume};
+
+const END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+const MAX_FAT_SIZE: u64 = 4 << 28;
+const DELETED: u8 = 0xE5;
+const ATTR_LONG_NAME: u8 = 0x0F;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+
+struct Fat32 {
+    cluster_size: u64,
+    data_start: u64,
+    cluster_count: u32,
+    fat: Vec<u8>,
+}
+
+impl Fat32 {
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_start + u64::from(cluster - 2) * self.cluster_size
+    }
+
+    //Follows the allocation table, a chain leaving the volume or looping means the table is corrupt
+    fn chain(&self, first: u32) -> Result<Vec<u32>> {
+        let mut chain = Vec::new();
+        let mut cluster = first;
+        loop {
+            let entry = cluster as usize * 4;
+            if cluster < 2 || u64::from(cluster) >= u64::from(self.cluster_count) + 2 || entry + 4 > self.fat.len() {
+                return Err(anyhow!("cluster {} is outside the FAT32 volume", cluster));
+            }
+            if chain.len() > self.cluster_count as usize {
+                return Err(anyhow!("loop in the FAT32 allocation table at cluster {}", first));
+            }
+            chain.push(cluster);
+            let next = le32(&self.fat, entry) & 0x0FFF_FFFF;
+            if next >= END_OF_CHAIN {
+                return Ok(chain);
+            }
+            cluster = next;
+        }
+    }
+}
+
+pub(crate) fn clean<T, F>(volume: &mut Volume<T>, mut on_progress: F) -> Result<CleanSummary>
+where
+    T: DriveAccessor + ?Sized,
+    F: FnMut(u64, u64),
+{
+    let mut boot = [0u8; 512];
+    volume.read_at(0, &mut boot)?;
+    let bytes_per_sector = u64::from(le16(&boot, 11));
+    let sectors_per_cluster = u64::from(boot[13]);
+    let reserved = u64::from(le16(&boot, 14));
+    let fats = u64::from(boot[16]);
+    let total_sectors = u64::from(le32(&boot, 32));
+    let fat_sectors = u64::from(le32(&boot, 36));
+    let root_cluster = le32(&boot, 44);
+    //Sector sizes other than these would leave directory clusters that don't split into whole entries
+    if ![512, 1024, 2048, 4096].contains(&bytes_per_sector) || !sectors_per_cluster.is_power_of_two() {
+        return Err(anyhow!("corrupt FAT32 boot sector"));
+    }
+    //FAT32 addresses at most 2^28 clusters, a bigger table is garbage
+    if fat_sectors * bytes_per_sector > MAX_FAT_SIZE {
+        return Err(anyhow!("corrupt FAT32 boot sector, the FAT is {} sectors long", fat_sectors));
+    }
+
+    let fat_start = reserved * bytes_per_sector;
+    let data_start = (reserved + fats * fat_sectors) * bytes_per_sector;
+    let data_size = (total_sectors * bytes_per_sector)
+        .checked_sub(data_start)
+        .ok_or_else(|| anyhow!("corrupt FAT32 boot sector, the FATs run past the end of the volume"))?;
+    let mut fat = vec![0u8; (fat_sectors * bytes_per_sector) as usize];
+    volume.read_at(fat_start, &mut fat)?;
+    let fs = Fat32 {
+        cluster_size: sectors_per_cluster * bytes_per_sector,
+        data_start,
+        cluster_count: (data_size / (sectors_per_cluster * bytes_per_sector)) as u32,
+        fat,
+    };
+
+    let mut summary = CleanSummary::default();
+    let mut pending = vec![root_cluster];
+    let mut visited = Vec::new();
+    while let Some(directory) = pending.pop() {
+        if visited.contains(&directory) {
+            continue;
+        }
+        visited.push(directory);
+        clean_directory(volume, &fs, directory, &mut pending, &mut summary)?;
+        on_progress(visited.len() as u64, (visited.len() + pending.len()) as u64);
+    }
+    Ok(summary)
+}
+
+fn clean_directory<T: DriveAccessor + ?Sized>(
+    volume: &mut Volume<T>,
+    fs: &Fat32,
+    first_cluster: u32,
+    pending: &mut Vec<u32>,
+    summary: &mut CleanSummary,
+) -> Result<()> {
+    let mut ended = false;
+    for cluster in fs.chain(first_cluster)? {
+        let offset = fs.cluster_offset(cluster);
+        let mut data = vec![0u8; fs.cluster_size as usize];
+        volume.read_at(offset, &mut data)?;
+        let mut modified = false;
+        for entry in data.chunks_mut(32) {
+            //Everything after the end of directory marker is unused, whatever is there is leftover
+            if ended || entry[0] == 0x00 {
+                ended = true;
+                if entry.iter().any(|b| *b != 0) {
+                    entry.iter_mut().for_each(|b| *b = 0);
+                    summary.entries_cleared += 1;
+                    modified = true;
+                }
+                continue;
+            }
+            //Keep the deleted marker so the entry isn't mistaken for the end of the directory
+            if entry[0] == DELETED {
+                if entry[1..].iter().any(|b| *b != 0) {
+                    entry[1..].iter_mut().for_each(|b| *b = 0);
+                    summary.entries_cleared += 1;
+                    modified = true;
+                }
+                continue;
+            }
+            let attributes = entry[11];
+            if attributes == ATTR_LONG_NAME || attributes & ATTR_VOLUME_ID != 0 || entry[0] == b'.' {
+                continue;
+            }
+            let start = (u32::from(le16(entry, 20)) << 16) | u32::from(le16(entry, 26));
+            if attributes & ATTR_DIRECTORY != 0 {
+                pending.push(start);
+            } else {
+                summary.files += 1;
+                summary.slack_bytes += clean_file_tail(volume, fs, start, u64::from(le32(entry, 28)))?;
+            }
+        }
+        if modified {
+            volume.write_at(offset, &data)?;
+        }
+    }
+    Ok(())
+}
+
+fn clean_file_tail<T: DriveAccessor + ?Sized>(volume: &mut Volume<T>, fs: &Fat32, start: u32, size: u64) -> Result<u64> {
+    let used = size % fs.cluster_size;
+    if size == 0 || used == 0 {
+        return Ok(0);
+    }
+    let last = ((size - 1) / fs.cluster_size) as usize;
+    let chain = fs.chain(start)?;
+    match chain.get(last) {
+        Some(cluster) => {
+            let len = (fs.cluster_size - used) as usize;
+            let zeroed = volume.zero_range(fs.cluster_offset(*cluster) + used, len)?;
+            Ok(if zeroed { len as u64 } else { 0 })
+        }
+        None => Ok(0), //Chain shorter than the file size, leave a corrupt file alone
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::slack::deep_clean;
+    use crate::slack::tests::{contents, image, put};
+
+    const SECTOR: usize = 512;
+    const DATA_START: usize = 33 * SECTOR; //32 reserved sectors and one FAT
+
+    fn cluster(n: usize) -> usize {
+        DATA_START + (n - 2) * SECTOR
+    }
+
+    fn entry(name: &[u8; 11], attributes: u8, start: u32, size: u32) -> [u8; 32] {
+        let mut entry = [0u8; 32];
+        entry[..11].copy_from_slice(name);
+        entry[11] = attributes;
+        entry[20..22].copy_from_slice(&((start >> 16) as u16).to_le_bytes());
+        entry[26..28].copy_from_slice(&(start as u16).to_le_bytes());
+        entry[28..32].copy_from_slice(&size.to_le_bytes());
+        entry
+    }
+
+    //One sector clusters: root directory at 2, a.txt over 3 and 4, sub at 5 holding b.txt at 6
+    fn fat32() -> Vec<u8> {
+        let mut data = vec![0u8; cluster(20)];
+        put(&mut data, 11, &512u16.to_le_bytes());
+        data[13] = 1;
+        put(&mut data, 14, &32u16.to_le_bytes());
+        data[16] = 1;
+        put(&mut data, 32, &((cluster(20) / SECTOR) as u32).to_le_bytes());
+        put(&mut data, 36, &1u32.to_le_bytes());
+        put(&mut data, 44, &2u32.to_le_bytes());
+        put(&mut data, 82, b"FAT32   ");
+        put(&mut data, 510, &[0x55, 0xAA]);
+        for (index, next) in [(0, 0x0FFF_FFF8), (1, 0x0FFF_FFFF), (2, 0x0FFF_FFFF), (3, 4), (4, 0x0FFF_FFFF), (5, 0x0FFF_FFFF), (6, 0x0FFF_FFFF)] {
+            put(&mut data, 32 * SECTOR + index * 4, &(next as u32).to_le_bytes());
+        }
+
+        let root = cluster(2);
+        put(&mut data, root, &entry(b"USB STICK  ", 0x08, 0, 0));
+        put(&mut data, root + 32, &entry(b"A       TXT", 0x20, 3, 700));
+        let mut deleted = entry(b"SECRET  TXT", 0x20, 9, 100);
+        deleted[0] = 0xE5;
+        put(&mut data, root + 64, &deleted);
+        put(&mut data, root + 96, &entry(b"SUB        ", 0x10, 5, 0));
+        //End of directory marker followed by a stale entry
+        put(&mut data, root + 160, &entry(b"STALE   TXT", 0x20, 10, 5));
+
+        let sub = cluster(5);
+        put(&mut data, sub, &entry(b".          ", 0x10, 5, 0));
+        put(&mut data, sub + 32, &entry(b"..         ", 0x10, 0, 0));
+        put(&mut data, sub + 64, &entry(b"B       TXT", 0x20, 6, 10));
+
+        //File contents followed by junk in the rest of their last cluster
+        data[cluster(3)..cluster(5)].iter_mut().for_each(|b| *b = b'a');
+        data[cluster(6)..cluster(7)].iter_mut().for_each(|b| *b = b'b');
+        data
+    }
+
+    #[test]
+    fn clears_slack_and_deleted_entries() {
+        let original = fat32();
+        let mut drive = image(&original);
+        let summary = deep_clean(&mut drive, |_, _| {}).unwrap();
+        let data = contents(&mut drive, original.len());
+        assert_eq!(summary.files, 2);
+        assert_eq!(summary.slack_bytes, (512 - 188) + (512 - 10));
+        assert_eq!(summary.entries_cleared, 2);
+
+        //Live files keep their data, the rest of the last cluster is zeroed
+        assert_eq!(data[cluster(3)..cluster(4) + 188], original[cluster(3)..cluster(4) + 188]);
+        assert!(data[cluster(4) + 188..cluster(5)].iter().all(|b| *b == 0));
+        assert_eq!(data[cluster(6)..cluster(6) + 10], original[cluster(6)..cluster(6) + 10]);
+        assert!(data[cluster(6) + 10..cluster(7)].iter().all(|b| *b == 0));
+
+        //The deleted entry keeps only its marker, nothing survives past the end of the directory
+        let root = cluster(2);
+        assert_eq!(data[root + 64], 0xE5);
+        assert!(data[root + 65..root + 96].iter().all(|b| *b == 0));
+        assert!(data[root + 128..cluster(3)].iter().all(|b| *b == 0));
+        assert_eq!(data[..root + 64], original[..root + 64]);
+        assert_eq!(data[root + 96..root + 128], original[root + 96..root + 128]);
+        assert_eq!(data[cluster(5)..cluster(6)], original[cluster(5)..cluster(6)]);
+    }
+
+    #[test]
+    fn corrupt_volumes_fail_cleanly() {
+        //a.txt's chain leaves the volume
+        let mut data = fat32();
+        put(&mut data, 32 * SECTOR + 3 * 4, &0x0FF0_0000u32.to_le_bytes());
+        assert!(deep_clean(&mut image(&data), |_, _| {}).is_err());
+
+        //The FATs claim more sectors than the volume has
+        let mut data = fat32();
+        put(&mut data, 32, &10u32.to_le_bytes());
+        assert!(deep_clean(&mut image(&data), |_, _| {}).is_err());
+
+        let mut data = fat32();
+        put(&mut data, 11, &0u16.to_le_bytes());
+        assert!(deep_clean(&mut image(&data), |_, _| {}).is_err());
+    }
+}
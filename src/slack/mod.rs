@@ -0,0 +1,166 @@
+//Deep clean of unmounted FAT32, exFAT and ext4 partitions. Free space wiping can't reach the unused
+//tail of a file's last cluster or names left in deleted directory entries, so those are zeroed here by
+//editing the filesystem structures directly through a `DriveAccessor`.
+
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+use crate::disk::simulated::Overlay;
+use crate::disk::DriveAccessor;
+
+mod exfat;
+mod ext;
+mod fat;
+
+/// Filesystems the deep clean understands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileSystem {
+    Fat32,
+    ExFat,
+    Ext4,
+}
+
+/// What a deep clean changed on the partition.
+#[derive(Clone, Debug, Default)]
+pub struct CleanSummary {
+    pub files: u64,
+    pub slack_bytes: u64, //File tail bytes zeroed
+    pub entries_cleared: u64, //Deleted directory entries scrubbed
+    pub inodes_cleared: u64, //Unused ext4 inodes zeroed
+}
+
+//Positioned reads and writes on top of a `DriveAccessor`
+pub(crate) struct Volume<'a, T: DriveAccessor + ?Sized> {
+    device: &'a mut T,
+}
+
+impl<'a, T: DriveAccessor + ?Sized> Volume<'a, T> {
+    pub(crate) fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        self.device.seek(offset)?;
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match self.device.read(&mut buffer[filled..])? {
+                0 => return Err(anyhow!("read past the end of the partition at {}", offset)),
+                n => filled += n,
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<()> {
+        self.device.seek(offset)?;
+        self.device.write(data)
+    }
+
+    //Zeroes `len` bytes at `offset` only if something is there, so clean regions aren't rewritten
+    pub(crate) fn zero_range(&mut self, offset: u64, len: usize) -> Result<bool> {
+        let mut buffer = vec![0u8; len];
+        self.read_at(offset, &mut buffer)?;
+        if buffer.iter().all(|b| *b == 0) {
+            return Ok(false);
+        }
+        buffer.iter_mut().for_each(|b| *b = 0);
+        self.write_at(offset, &buffer)?;
+        Ok(true)
+    }
+}
+
+pub(crate) fn le16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+pub(crate) fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+pub(crate) fn le64(data: &[u8], offset: usize) -> u64 {
+    u64::from(le32(data, offset)) | (u64::from(le32(data, offset + 4)) << 32)
+}
+
+///Identifies the filesystem from its boot sector or superblock
+pub fn detect_filesystem<T: DriveAccessor + ?Sized>(device: &mut T) -> Result<Option<FileSystem>> {
+    let mut volume = Volume { device };
+    let mut head = vec![0u8; 2048];
+    volume.read_at(0, &mut head)?;
+    if &head[3..11] == b"EXFAT   " {
+        return Ok(Some(FileSystem::ExFat));
+    }
+    if &head[82..90] == b"FAT32   " && head[510] == 0x55 && head[511] == 0xAA {
+        return Ok(Some(FileSystem::Fat32));
+    }
+    if le16(&head, 1024 + 56) == 0xEF53 {
+        return Ok(Some(FileSystem::Ext4));
+    }
+    Ok(None)
+}
+
+///Deep cleans the filesystem on `device`. The partition must not be mounted, the kernel would
+/// overwrite our changes with its cached copy of the metadata.
+pub fn deep_clean<T, F>(device: &mut T, on_progress: F) -> Result<CleanSummary>
+where
+    T: DriveAccessor + ?Sized,
+    F: FnMut(u64, u64),
+{
+    let filesystem = detect_filesystem(device)?.ok_or_else(|| anyhow!("no FAT32, exFAT or ext4 filesystem found"))?;
+    let mut volume = Volume { device };
+    let summary = match filesystem {
+        FileSystem::Fat32 => fat::clean(&mut volume, on_progress)?,
+        FileSystem::ExFat => exfat::clean(&mut volume, on_progress)?,
+        FileSystem::Ext4 => ext::clean(&mut volume, on_progress)?,
+    };
+    volume.device.flush()?;
+    Ok(summary)
+}
+
+///Opens an unmounted partition and deep cleans it. A dry run opens it read only and keeps the writes
+/// in memory.
+pub fn deep_clean_partition<F>(path: &Path, dry_run: bool, on_progress: F) -> Result<CleanSummary>
+where
+    F: FnMut(u64, u64),
+{
+    let mounts = std::fs::read_to_string("/proc/mounts")?;
+    let name = path.to_string_lossy();
+    if mounts.lines().any(|l| l.split_whitespace().next() == Some(name.as_ref())) {
+        return Err(anyhow!("{} is mounted, unmount it before deep cleaning", name));
+    }
+    if dry_run {
+        let mut device = Overlay::new(std::fs::File::open(path)?);
+        return deep_clean(&mut device, on_progress);
+    }
+    let mut device = std::fs::OpenOptions::new().read(true).write(true).open(path)?;
+    deep_clean(&mut device, on_progress)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::disk::simulated::SimulatedDrive;
+    use crate::wipe::engine::PatternSource;
+
+    pub(crate) type Image = Overlay<SimulatedDrive>;
+
+    //Puts a filesystem built in memory on a drive, the blank drive underneath reads back zeroes
+    pub(crate) fn image(data: &[u8]) -> Image {
+        let mut image = Overlay::new(SimulatedDrive::new(data.len() as u64, 4096, PatternSource::new(&[])));
+        image.write(data).unwrap();
+        image
+    }
+
+    pub(crate) fn contents(image: &mut Image, len: usize) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        Volume { device: image }.read_at(0, &mut data).unwrap();
+        data
+    }
+
+    pub(crate) fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    #[test]
+    fn unknown_filesystems_are_refused() {
+        let mut blank = image(&[0u8; 4096]);
+        assert_eq!(detect_filesystem(&mut blank).unwrap(), None);
+        assert!(deep_clean(&mut blank, |_, _| {}).is_err());
+        assert_eq!(blank.bytes_written(), 4096);
+    }
+}
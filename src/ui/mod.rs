@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod ui;
 
 pub use ui as Ui;
\ No newline at end of file
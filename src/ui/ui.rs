@@ -1,42 +1,332 @@
 //Where ui portion of app is handled such as
 //drawing graphical cli text, update, etc.
 
-use crate::App;
-use crate::disk::Disk;
+use crate::app::{App, Mode, State};
+use crate::clone::device_size;
+use crate::job::{JobKind, JobStatus, WipeScope};
+use crate::disk::{Disk, PartitionInfo, Slave};
+use crate::hexview::{format_line, BYTES_PER_LINE};
+use log::Level;
+use crate::sanitize::SanitizeCommand;
+use crate::wipe::WipeMethod;
+use std::time::{Duration, Instant};
 use tui::layout::Rect;
-use tui::widgets::Gauge;
+use tui::widgets::{Gauge, Sparkline};
 use tui::widgets::Tabs;
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
     Frame,
 };
 
-const FG_THEME: Color = Color::Rgb(129, 66, 38);
-const BG_THEME: Color = Color::Rgb(184, 102, 64);
+//Marker in front of the highlighted list entry
+fn highlight_symbol(app: &App) -> &'static str {
+    if app.enhanced_graphics {
+        "▶ "
+    } else {
+        ">> "
+    }
+}
 
 pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
         .split(f.size());
-    //TODO main graphic here
+    if app.show_log {
+        draw_log(f, app, chunks[0]);
+    } else {
+        draw_layout_canvas(f, app, chunks[0]);
+    }
+
+    match &app.state {
+        State::SelectMode => draw_mode_selection(f, app, chunks[1]),
+        State::SelectDrive { .. } => draw_drive_selection(f, app, chunks[1]),
+        State::SelectImage => draw_image_selection(f, app, chunks[1]),
+        State::SelectScope { .. } => draw_scope_selection(f, app, chunks[1]),
+        State::SelectMethod { .. } => draw_wipe_method_selection(f, app, chunks[1]),
+        State::Options { job } => draw_options(f, job, chunks[1]),
+        State::Confirm { jobs } => draw_confirmation(f, app, jobs, chunks[1]),
+        State::Countdown { jobs, until } => draw_countdown(f, app, jobs, until, chunks[1]),
+        State::Progress { jobs } => draw_deletion_progress(f, app, jobs, chunks[1]),
+        State::Result { jobs } => draw_status(f, app, jobs, chunks[1]),
+        State::HexView { .. } => draw_hex_view(f, app, chunks[1]),
+    }
+}
+
+/// Stretch of a disk on the layout bar, a partition or the unallocated space between them.
+#[derive(Clone, Debug, PartialEq)]
+struct Segment {
+    start: u64,
+    len: u64,
+    partition: Option<usize>, //Index into the partitions, None for unallocated space
+}
+
+//Gaps smaller than this are alignment padding rather than space anyone could use
+const MIN_GAP: u64 = 1 << 20;
+//Partitions take turns in these colors on the layout bar
+const PARTITION_COLORS: [Color; 4] = [Color::Blue, Color::Magenta, Color::Cyan, Color::Green];
+
+//Partitions in the order they sit on the disk with the unallocated stretches between them
+fn disk_segments(capacity: u64, partitions: &[PartitionInfo]) -> Vec<Segment> {
+    let mut order: Vec<usize> = (0..partitions.len()).collect();
+    order.sort_by_key(|i| partitions[*i].start);
+    let mut segments = Vec::new();
+    let mut position = 0;
+    for index in order {
+        let partition = &partitions[index];
+        if partition.start >= position + MIN_GAP {
+            segments.push(Segment { start: position, len: partition.start - position, partition: None });
+        }
+        segments.push(Segment { start: partition.start, len: partition.size, partition: Some(index) });
+        position = position.max(partition.start + partition.size);
+    }
+    if capacity >= position + MIN_GAP {
+        segments.push(Segment { start: position, len: capacity - position, partition: None });
+    }
+    segments
+}
+
+//Which segment each of `width` columns shows, going by the byte in the middle of the column
+fn segment_columns(capacity: u64, segments: &[Segment], width: usize) -> Vec<Option<usize>> {
+    (0..width)
+        .map(|column| {
+            let byte = ((column as f64 + 0.5) / width as f64 * capacity as f64) as u64;
+            segments.iter().position(|s| byte >= s.start && byte < s.start + s.len)
+        })
+        .collect()
+}
+
+//How far into the disk a job got, for jobs that go through it from start to end. Free space and
+//slack wipes go by files so there's nothing to show for them.
+fn processed_bytes(job: &JobKind, status: &JobStatus, capacity: u64) -> Option<u64> {
+    if let JobKind::Wipe { scope, .. } = job {
+        if *scope != WipeScope::EntireDrive {
+            return None;
+        }
+    }
+    if status.finished {
+        return if status.error.is_none() { Some(capacity) } else { None };
+    }
+    let total = if status.stage_total > 0 { status.stage_total } else { capacity };
+    Some((status.progress.clamp(0.0, 1.0) * total as f64) as u64)
+}
+
+//Drives the current screen is about, together with the job running on them
+fn canvas_disks<'a>(app: &'a App) -> Vec<(&'a Disk, Option<(&'a JobKind, &'a JobStatus)>)> {
+    match &app.state {
+        State::SelectMode | State::SelectImage => Vec::new(),
+        State::SelectDrive { .. } => app.selected_drive().into_iter().map(|d| (d, None)).collect(),
+        State::SelectScope { disks } | State::SelectMethod { disks, .. } => disks.iter().map(|d| (d, None)).collect(),
+        State::Options { job } => vec![(job.disk(), None)],
+        State::Confirm { jobs } | State::Countdown { jobs, .. } => jobs.iter().map(|j| (j.disk(), None)).collect(),
+        State::Progress { jobs } | State::Result { jobs } => jobs
+            .iter()
+            .enumerate()
+            .map(|(i, j)| (j.disk(), app.job_statuses.get(i).map(|s| (j, s))))
+            .collect(),
+        State::HexView { .. } => match &app.viewer {
+            Some(Ok(viewer)) => app.drives.items.iter().filter(|d| d.name == viewer.path).map(|d| (d, None)).collect(),
+            _ => Vec::new(),
+        },
+    }
+}
+
+//Top of the screen, the partitions of the drives in use laid out along the disk with whatever a job
+//already went over shaded
+fn draw_layout_canvas<B>(f: &mut Frame<B>, app: &App, area: Rect)
+where
+    B: Backend,
+{
+    let mut title = if app.debug_mode { format!("{} (dry run)", app.title) } else { String::from(app.title) };
+    match app.log.problems() {
+        0 => {}
+        1 => title.push_str(" - 1 warning, l shows the log"),
+        problems => title.push_str(&format!(" - {} warnings, l shows the log", problems)),
+    }
     let block = Block::default()
         .borders(Borders::ALL)
-        .title("Main canvas")
-        .style(Style::default().bg(Color::Rgb(32,32,32)));
-    f.render_widget(block, chunks[0]);
+        .title(title)
+        .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32,32,32)));
+    let width = area.width.saturating_sub(2) as usize;
+    let mut text = Vec::new();
+    for (disk, job) in canvas_disks(app) {
+        let capacity = if disk.capacity > 0 { disk.capacity } else { disk.total_space };
+        let partitions = disk.describe_partitions();
+        let segments = disk_segments(capacity, &partitions);
+        let processed = job.and_then(|(kind, status)| processed_bytes(kind, status, capacity));
+        let mut header = format!(
+            "{}  {}  {}  {}",
+            disk.name.display(),
+            disk.model,
+            format_capacity(capacity),
+            disk.partition_table.as_deref().unwrap_or("no partition table")
+        );
+        if let Some((_, status)) = job {
+            header.push_str(&format!("  {} {:.0}%", status.stage, status.progress * 100.0));
+        }
+        text.push(Spans::from(Span::styled(header, Style::default().add_modifier(Modifier::BOLD))));
+        if capacity == 0 || width == 0 {
+            text.push(Spans::from("Size unknown"));
+            text.push(Spans::from(""));
+            continue;
+        }
+        let columns = segment_columns(capacity, &segments, width);
 
-    match app.status.index {
-        0 => draw_drive_selection(f, app, chunks[1]),
-        1 => draw_wipe_method_selection(f, app, chunks[1]),
-        2 => draw_confirmation(f, app, chunks[1]),
-        3 => draw_deletion_progress(f, app, chunks[1]),
-        _ => {}
+        //Each segment is named from its first column for as long as it lasts
+        let mut labels = vec![' '; width];
+        for (index, segment) in segments.iter().enumerate() {
+            let first = match columns.iter().position(|c| *c == Some(index)) {
+                Some(first) => first,
+                None => continue,
+            };
+            let span = columns[first..].iter().take_while(|c| **c == Some(index)).count();
+            let name = match segment.partition.map(|p| &partitions[p]) {
+                Some(partition) => {
+                    let device = partition.device.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                    let label = partition.label.as_deref().or(partition.file_system.as_deref()).unwrap_or("");
+                    format!("{} {}", device, label)
+                }
+                None => String::from("unallocated"),
+            };
+            for (slot, c) in labels[first..first + span].iter_mut().zip(name.trim().chars()) {
+                *slot = c;
+            }
+        }
+        text.push(Spans::from(labels.into_iter().collect::<String>()));
+
+        let done_columns = processed.map(|p| ((p as f64 / capacity as f64) * width as f64).round() as usize).unwrap_or(0);
+        let bar = columns
+            .iter()
+            .enumerate()
+            .map(|(column, segment)| {
+                let color = match segment.and_then(|s| segments[s].partition) {
+                    Some(p) => PARTITION_COLORS[p % PARTITION_COLORS.len()],
+                    None => Color::Rgb(64, 64, 64),
+                };
+                if column < done_columns {
+                    Span::styled(if app.enhanced_graphics { "█" } else { "#" }, Style::default().fg(Color::Rgb(229, 83, 0)).bg(color))
+                } else {
+                    Span::styled(" ", Style::default().bg(color))
+                }
+            })
+            .collect::<Vec<Span>>();
+        text.push(Spans::from(bar.clone()));
+        text.push(Spans::from(bar));
+        text.push(Spans::from(""));
+    }
+    if text.is_empty() {
+        text.push(Spans::from("The partition layout of the selected drive shows up here, press l for the log"));
     }
+    f.render_widget(Paragraph::new(text).block(block), area);
+}
+
+//List on the left two thirds with a details panel on the right, shared by the simple selection screens
+fn draw_list_with_details<B>(f: &mut Frame<B>, title: &str, symbol: &str, entries: Vec<ListItem>, state: &mut ListState, details: Vec<Spans>, area: Rect)
+where
+    B: Backend,
+{
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(67), Constraint::Percentage(33)].as_ref())
+        .split(area);
+    let items = List::new(entries)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32, 32, 32)))
+                .title(title.to_string())
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::Rgb(229, 83, 0))
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(symbol);
+    f.render_stateful_widget(items, chunks[0], state);
+
+    let info = Paragraph::new(details)
+        .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32,32,32)))
+        .block(Block::default().borders(Borders::ALL).title("Details"))
+        .wrap(Wrap { trim: true });
+    f.render_widget(info, chunks[1]);
+}
+
+fn draw_mode_selection<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
+where
+    B: Backend,
+{
+    let entries = app.modes.items.iter().map(|m| ListItem::new(m.name())).collect();
+    let details = match app.modes.state.selected() {
+        Some(i) => vec![Spans::from(app.modes.items[i].description())],
+        None => vec![Spans::from("Select a mode, press e to continue and c to go back")],
+    };
+    let title = app.state.title();
+    draw_list_with_details(f, title, highlight_symbol(app), entries, &mut app.modes.state, details, area);
+}
+
+fn draw_image_selection<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
+where
+    B: Backend,
+{
+    let entries = app
+        .images
+        .items
+        .iter()
+        .map(|i| ListItem::new(i.display().to_string()))
+        .collect();
+    let details = match app.selected_image() {
+        Some(image) => vec![
+            Spans::from(format!("Image: {}", image.display())),
+            Spans::from(format!("Size: {}", device_size(image).map(format_capacity).unwrap_or_default())),
+            Spans::from("Press v to view its contents"),
+        ],
+        None if app.images.items.is_empty() => vec![Spans::from("No .iso or .img files found in the current directory")],
+        None => vec![Spans::from("Select an image for more information")],
+    };
+    let title = app.state.title();
+    draw_list_with_details(f, title, highlight_symbol(app), entries, &mut app.images.state, details, area);
+}
+
+fn draw_scope_selection<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
+where
+    B: Backend,
+{
+    let entries = app.scopes.items.iter().map(|s| ListItem::new(s.name())).collect();
+    let details = match app.selected_scope() {
+        Some(WipeScope::EntireDrive) => "Overwrites or sanitizes every block of the drive, destroying all partitions and files.",
+        Some(WipeScope::FreeSpace) => "Fills the free space of every mounted partition with the wipe pattern and deletes it again. Existing files are kept, previously deleted ones are overwritten.",
+        Some(WipeScope::Slack) => "Zeroes the unused tail of each file's last cluster, deleted directory entries and unused inodes. Partitions have to be unmounted, files are kept.",
+        None => "Select what to wipe for more information",
+    };
+    let title = app.state.title();
+    draw_list_with_details(f, title, highlight_symbol(app), entries, &mut app.scopes.state, vec![Spans::from(details)], area);
+}
+
+fn draw_options<B>(f: &mut Frame<B>, job: &JobKind, area: Rect)
+where
+    B: Backend,
+{
+    let verify = match job {
+        JobKind::Clone { verify, .. } | JobKind::Burn { verify, .. } => *verify,
+        JobKind::Wipe { .. } => false,
+    };
+    let mut text = vec![
+        Spans::from(format!("[{}] Verify after writing (space to toggle)", if verify { "x" } else { " " })),
+        Spans::from(""),
+    ];
+    if let JobKind::Clone { destination, .. } = job {
+        text.push(Spans::from(format!("Image file: {}", destination.display())));
+    }
+    text.push(Spans::from("Press e to continue"));
+    let options = Paragraph::new(text)
+        .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32,32,32)))
+        .block(Block::default().borders(Borders::ALL).title("Options"));
+    f.render_widget(options, area);
 }
 
 fn draw_drive_selection<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
@@ -48,15 +338,31 @@ where
         .constraints([Constraint::Percentage(67), Constraint::Percentage(33)].as_ref())
         .split(area);
     // Iterate through all elements in the `items` app and append some debug text to it.
+    //Only wipes can run on several drives, they get a checkbox to mark them with
+    let marking = matches!(app.state, State::SelectDrive { mode: Mode::Destroy, .. });
     let entries: Vec<ListItem> = app
         .drives
         .items
         .iter()
-        .map(|i| {
-            let lines = vec![Spans::from(i.name.to_str().unwrap())];
+        .enumerate()
+        .map(|(index, i)| {
+            let name = i.name.to_str().unwrap();
+            let lines = if marking {
+                let mark = if app.marked_drives.contains(&index) { "[x]" } else { "[ ]" };
+                vec![Spans::from(format!("{} {}", mark, name))]
+            } else {
+                vec![Spans::from(name)]
+            };
             ListItem::new(lines).style(Style::default())
         })
         .collect();
+    let title = if marking && !app.marked_drives.is_empty() {
+        format!("{} ({} marked, space to mark more)", app.state.title(), app.marked_drives.len())
+    } else if marking {
+        format!("{} (space to mark several)", app.state.title())
+    } else {
+        String::from(app.state.title())
+    };
 
     // Create a List from all list items and highlight the currently selected one
     let items = List::new(entries)
@@ -64,7 +370,7 @@ where
             Block::default()
                 .borders(Borders::ALL)
                 .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32, 32, 32)))
-                .title(app.status.titles[app.status.index])
+                .title(title)
         )
         .highlight_style(
             Style::default()
@@ -72,67 +378,107 @@ where
                 .fg(Color::White)
                 .add_modifier(Modifier::BOLD),
         )
-        .highlight_symbol(">> ");
+        .highlight_symbol(highlight_symbol(app));
     f.render_stateful_widget(items, chunks[0], &mut app.drives.state);
 
 
-    let current_index = app.drives.state.selected();
-    if current_index != None {
-        let selected_drive: &Disk = &app.drives.items[current_index.unwrap()];
-        let text = vec![
-            
-            Spans::from(Span::styled(
-                format!("Total space: {}", selected_drive.total_space.to_string()),
-                Style::default().bg(Color::Green).fg(Color::White),
-            )),
-            Spans::from(Span::styled(
-                format!(
-                    "Free space: {}",
-                    selected_drive.free_space.to_string()
-                ),
-                Style::default().bg(Color::Yellow).fg(Color::White),
-            )),
-            // Spans::from(Span::styled(
-            //     format!("Mount: {}", selected_drive.mount_point),
-            //     Style::default().bg(Color::Magenta).fg(Color::White),
-            // )),
-            // Spans::from(Span::styled(
-            //     format!(
-            //         "File System: {}",
-            //         selected_drive.file_system
-            //     ),
-            //     Style::default().bg(Color::Blue).fg(Color::White),
-            // )),
-            Spans::from(Span::styled(
-                format!("Used space: {}", selected_drive.used_space.to_string()),
-                Style::default().bg(Color::Green).fg(Color::White),
-            )),
-            Spans::from(Span::styled(
-                format!("Type: {}", selected_drive.disk_type),
-                Style::default().bg(Color::Green).fg(Color::White),
-            )),
-        ];
-        let paragraph = Paragraph::new(text.clone()).style(Style::default()).block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Drive Information")
-                .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32,32,32))),
-        );
+    let info_block = Block::default()
+        .borders(Borders::ALL)
+        .title("Drive Information")
+        .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32,32,32)));
+    let selected_drive: &Disk = match app.drives.state.selected() {
+        Some(index) => &app.drives.items[index],
+        None => {
+            let paragraph = Paragraph::new("Select a drive for more information").style(Style::default()).block(info_block);
+            f.render_widget(paragraph, chunks[1]);
+            return;
+        }
+    };
+    //Usage bar under the details
+    let info_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)].as_ref())
+        .split(chunks[1]);
+    let mut text = vec![
+        Spans::from(format!("Model: {}", selected_drive.model)),
+        Spans::from(format!("Serial: {}", selected_drive.serial_number)),
+        Spans::from(format!("Type: {}    Bus: {}", selected_drive.disk_type, selected_drive.bus)),
+        Spans::from(Span::styled(
+            format!("Capacity: {}", format_capacity(selected_drive.capacity)),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Spans::from(format!(
+            "Sectors: {} B logical, {} B physical",
+            selected_drive.logical_block_size, selected_drive.physical_block_size
+        )),
+        Spans::from(format!(
+            "Partition table: {}",
+            selected_drive.partition_table.as_deref().unwrap_or("none")
+        )),
+    ];
+    if let Some(backing_file) = &selected_drive.backing_file {
+        text.push(Spans::from(Span::styled(
+            format!("Backing file: {}", backing_file.display()),
+            Style::default().bg(Color::Magenta).fg(Color::White),
+        )));
+    }
+    text.push(Spans::from(""));
+    text.push(Spans::from("Partitions:"));
+    for partition in selected_drive.describe_partitions().iter() {
+        let name = partition.device.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let label = partition.label.as_deref().map(|l| format!(" \"{}\"", l)).unwrap_or_default();
+        let file_system = partition.file_system.as_deref().unwrap_or("unknown filesystem");
+        text.push(Spans::from(format!("  {}{}  {}  {}", name, label, file_system, format_size(partition.size))));
+        //Mounted partitions get a red line since wiping them takes the files in use along
+        match (&partition.mount_point, partition.used) {
+            (Some(mount_point), Some(used)) => text.push(Spans::from(Span::styled(
+                format!("    on {}, {} used", mount_point.display(), format_size(used)),
+                Style::default().fg(Color::Red),
+            ))),
+            _ => text.push(Spans::from("    not mounted")),
+        }
+    }
+    //Show what the drive is stacked on so users know which physical disks are affected
+    if !selected_drive.slaves.is_empty() {
+        text.push(Spans::from("Stacked on:"));
+        slave_lines(&selected_drive.slaves, 1, &mut text);
+    }
+    text.push(Spans::from(""));
+    text.push(Spans::from("Press v to view its contents"));
+    let paragraph = Paragraph::new(text).style(Style::default()).block(info_block).wrap(Wrap { trim: false });
+    f.render_widget(paragraph, info_chunks[0]);
 
-        //Render right side selection and corresponding info
-        f.render_widget(paragraph, chunks[1]);
+    //Only mounted filesystems can say how much of them is in use
+    let (ratio, label) = if selected_drive.total_space > 0 {
+        let ratio = selected_drive.used_space as f64 / selected_drive.total_space as f64;
+        (ratio, format!(
+            "{} of {} used, {} free",
+            format_size(selected_drive.used_space),
+            format_size(selected_drive.total_space),
+            format_size(selected_drive.free_space)
+        ))
     } else {
-        let paragraph = Paragraph::new("Select a drive for more information").style(Style::default()).block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Drive Information")
-                .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32,32,32))),
-        );
+        (0.0, String::from("Nothing mounted"))
+    };
+    let usage = Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title("Usage"))
+        .gauge_style(Style::default().fg(Color::Green).bg(Color::Rgb(32, 32, 32)))
+        .ratio(ratio.clamp(0.0, 1.0))
+        .label(label);
+    f.render_widget(usage, info_chunks[1]);
+}
 
-        //Render right side selection and corresponding info
-        f.render_widget(paragraph, chunks[1]);
+//Recursively appends one indented line per slave device, e.g. "  └ /dev/sda1 (Partition)"
+fn slave_lines(slaves: &[Slave], depth: usize, text: &mut Vec<Spans>) {
+    for slave in slaves.iter() {
+        text.push(Spans::from(format!(
+            "{}└ {} ({})",
+            "  ".repeat(depth),
+            slave.name.display(),
+            slave.disk_type
+        )));
+        slave_lines(&slave.slaves, depth + 1, text);
     }
-    
 }
 
 fn draw_wipe_method_selection<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
@@ -144,13 +490,24 @@ where
         .constraints([Constraint::Percentage(67), Constraint::Percentage(33)].as_ref())
         .split(area);
 
+    //Overwriting flash only reaches the logical blocks, point users at the firmware erases instead
+    let is_flash = match &app.state {
+        State::SelectMethod { disks, .. } => disks.iter().any(|d| d.is_flash()),
+        _ => false,
+    };
     let items: Vec<ListItem> = app
         .deletion_methods
         .items
         .iter()
         .map(|i| {
-            let lines = vec![Spans::from(*i)];
-            ListItem::new(lines).style(Style::default())
+            let mut spans = vec![Span::raw(i.name.as_str())];
+            if is_flash && i.is_firmware() {
+                spans.push(Span::styled(" (recommended)", Style::default().fg(Color::Green)));
+            }
+            if let Some(estimate) = app.wipe_estimate(i) {
+                spans.push(Span::styled(format!(" ~{}", format_duration(estimate)), Style::default().fg(Color::Gray)));
+            }
+            ListItem::new(vec![Spans::from(spans)]).style(Style::default())
         })
         .collect();
 
@@ -168,19 +525,92 @@ where
                 .bg(Color::Rgb(229, 83, 0))
                 .add_modifier(Modifier::BOLD),
         )
-        .highlight_symbol(">> ");
+        .highlight_symbol(highlight_symbol(app));
 
-    let s = "Lorem ipsem dolor ipset deler runtime ";
-    s.repeat(4);
-    let info = Paragraph::new(s.clone())
+    let details_area = if is_flash {
+        let right = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(chunks[1]);
+        draw_flash_warning(f, app, right[0]);
+        right[1]
+    } else {
+        chunks[1]
+    };
+
+    let details = match app.selected_method() {
+        Some(method) => {
+            let mut text = method_details(method, &app.sanitize_support);
+            if let State::SelectMethod { disks, scope: WipeScope::EntireDrive } = &app.state {
+                if !method.is_firmware() {
+                    text.push(Spans::from(""));
+                    text.push(Spans::from(format!(
+                        "[{}] Discard every block afterwards (space to toggle)",
+                        if method.discard { "x" } else { " " }
+                    )));
+                    if method.discard && disks.iter().any(|d| !d.supports_discard()) {
+                        text.push(Spans::from("Drives without discard support skip it"));
+                    }
+                }
+            }
+            text
+        }
+        None => vec![Spans::from("Select a method for more information")],
+    };
+    let info = Paragraph::new(details)
         .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32,32,32)))
-        .block(Block::default().borders(Borders::ALL).title("Details"));
-    f.render_widget(info, chunks[1]);
+        .block(Block::default().borders(Borders::ALL).title("Details"))
+        .wrap(Wrap { trim: true });
+    f.render_widget(info, details_area);
     // We can now render the item list
     f.render_stateful_widget(items, chunks[0], &mut app.deletion_methods.state);
 }
 
-fn draw_confirmation<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
+//Short human readable duration like 2h 05m, 12m 30s or 45s
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+//Description of a method followed by its pass list or the firmware command it will run
+fn method_details<'a>(method: &'a WipeMethod, supported: &[SanitizeCommand]) -> Vec<Spans<'a>> {
+    let mut text = vec![Spans::from(method.description.as_str()), Spans::from("")];
+    if let Some(command) = method.sanitize_command(supported) {
+        text.push(Spans::from(format!("Runs: {}", command.name())));
+    }
+    for (i, pass) in method.passes.iter().enumerate() {
+        let verify = if pass.verify { " + verify" } else { "" };
+        text.push(Spans::from(format!("Pass {}: {}{}", i + 1, pass.pattern, verify)));
+    }
+    text
+}
+
+fn draw_flash_warning<B>(f: &mut Frame<B>, app: &App, area: Rect)
+where
+    B: Backend,
+{
+    let recommendation = if app.sanitize_support.is_empty() {
+        String::from("This drive accepts no firmware erase commands (are you root?), consider physical destruction if the data is sensitive.")
+    } else {
+        let names: Vec<&str> = app.sanitize_support.iter().map(|c| c.name()).collect();
+        format!("Recommended: {}.", names.join(", "))
+    };
+    let text = format!(
+        "Multi-pass overwrites are ineffective on SSDs and flash storage, wear levelling and spare blocks keep old data out of reach. {}",
+        recommendation
+    );
+    let warning = Paragraph::new(text)
+        .style(Style::default().fg(Color::Red).bg(Color::Rgb(32,32,32)))
+        .block(Block::default().borders(Borders::ALL).title("Warning"))
+        .wrap(Wrap { trim: true });
+    f.render_widget(warning, area);
+}
+
+fn draw_confirmation<B>(f: &mut Frame<B>, app: &App, jobs: &[JobKind], area: Rect)
 where
     B: Backend,
 {
@@ -188,6 +618,8 @@ where
         .constraints(
             [
                 Constraint::Length(4),
+                Constraint::Min(3),
+                Constraint::Length(1),
                 Constraint::Length(1),
                 Constraint::Length(1),
             ]
@@ -199,22 +631,78 @@ where
     f.render_widget(block, area);
 
 
-    let current_index = app.drives.state.selected();
-    let selected_drive: &Disk = &app.drives.items[current_index.unwrap()];
-    let warning_message = format!(
-        "Warning! You are about to permanently erase \"{}\" this action cannot be undone!
+    let names: Vec<&str> = jobs.iter().map(|j| j.disk().name.to_str().unwrap()).collect();
+    let (warning_message, question) = match &jobs[0] {
+        JobKind::Wipe { .. } if jobs.len() > 1 => (
+            format!(
+                "Warning! You are about to permanently erase {} drives at once: {}. This action cannot be undone!
+        Leave this window open until every drive is done. Proceed with caution",
+                jobs.len(),
+                names.join(", ")
+            ),
+            "Are you sure you want to delete all of these drives?",
+        ),
+        JobKind::Clone { disk, destination, .. } => (
+            format!(
+                "You are about to copy \"{}\" into \"{}\". Cloning may take some time, leave this window open until the process is completed!",
+                disk.name.to_str().unwrap(),
+                destination.display()
+            ),
+            "Are you sure you want to clone this drive?",
+        ),
+        JobKind::Burn { image, disk, .. } => (
+            format!(
+                "Warning! You are about to overwrite \"{}\" with \"{}\", everything on the drive will be lost and this action cannot be undone!",
+                disk.name.to_str().unwrap(),
+                image.display()
+            ),
+            "Are you sure you want to burn this image?",
+        ),
+        JobKind::Wipe { disk, .. } => (
+            format!(
+                "Warning! You are about to permanently erase \"{}\" this action cannot be undone!
         Disk deletion may take some time, leave this window open until the process is completed! 
         If you need to ensure a zero chance of data recovery, consider physical destruction of the drive afterwards. 
         Proceed with caution",
-        selected_drive.name.to_str().unwrap()
-    );
+                disk.name.to_str().unwrap()
+            ),
+            "Are you sure you want yo delete this drive?",
+        ),
+    };
 
-    let prompt = Paragraph::new("Are you sure you want yo delete this drive?");
+    let prompt = Paragraph::new(question);
+    let warning_message = if app.debug_mode {
+        format!("Dry run, the drive is only read and nothing will be written. {}", warning_message)
+    } else {
+        warning_message
+    };
 
     
     let info = Paragraph::new(warning_message.clone())
         .style(Style::default().fg(Color::Red).bg(Color::Rgb(32,32,32)));
 
+    let disks: Vec<&Disk> = jobs.iter().map(|j| j.disk()).collect();
+    let details = Paragraph::new(drive_summary(app, &disks)).wrap(Wrap { trim: false });
+
+    //Typing the code is what unlocks the action, arrows and e alone are not enough
+    let code = match app.confirmation_code() {
+        Some(code) => {
+            let color = if app.code_matches(&code) { Color::Green } else { Color::White };
+            let source = if disks.len() > 1 {
+                "every drive's code, in order"
+            } else if disks[0].name.file_name().is_some_and(|n| n.to_string_lossy() == code) {
+                "device name"
+            } else {
+                "end of the serial number"
+            };
+            Spans::from(vec![
+                Span::raw(format!("Type {} ({}) and press enter: ", code, source)),
+                Span::styled(format!("{}_", app.typed), Style::default().fg(color).add_modifier(Modifier::BOLD)),
+            ])
+        }
+        None => Spans::from(""),
+    };
+
     let titles = app
         .confirmation
         .titles
@@ -225,55 +713,366 @@ where
         .highlight_style(Style::default().fg(Color::Red))
         .select(app.confirmation.index);
     f.render_widget(info, chunks[0]);
-    f.render_widget(prompt, chunks[1]);
-    f.render_widget(tabs, chunks[2]);
+    f.render_widget(details, chunks[1]);
+    f.render_widget(Paragraph::new(code), chunks[2]);
+    f.render_widget(prompt, chunks[3]);
+    f.render_widget(tabs, chunks[4]);
+}
+
+//Model, size and partitions of the drives about to be used, so a wrong one is easy to spot
+fn drive_summary<'a>(app: &App, disks: &[&Disk]) -> Vec<Spans<'a>> {
+    let mut lines = Vec::new();
+    for disk in disks.iter() {
+        let size = device_size(&disk.name).unwrap_or(disk.capacity);
+        lines.push(Spans::from(format!(
+            "Drive: {}    Model: {}    Serial: {}    Size: {}",
+            disk.name.display(),
+            disk.model,
+            disk.serial_number,
+            format_capacity(size)
+        )));
+    }
+    for partition in app.target_partitions.iter() {
+        let label = partition.label.as_deref().map(|l| format!("\"{}\"", l)).unwrap_or_else(|| String::from("no label"));
+        let mounted = match &partition.mount_point {
+            Some(mount_point) => format!("mounted on {}", mount_point.display()),
+            None => String::from("not mounted"),
+        };
+        let style = if partition.mount_point.is_some() { Style::default().fg(Color::Red) } else { Style::default() };
+        lines.push(Spans::from(Span::styled(
+            format!("  {}  {}  {}", partition.device.display(), label, mounted),
+            style,
+        )));
+    }
+    lines
+}
+
+fn draw_countdown<B>(f: &mut Frame<B>, app: &App, jobs: &[JobKind], until: &Instant, area: Rect)
+where
+    B: Backend,
+{
+    let remaining = until.saturating_duration_since(Instant::now());
+    let names: Vec<String> = jobs.iter().filter_map(|j| j.target()).map(|d| d.name.display().to_string()).collect();
+    let disk = names.join(", ");
+    let text = vec![
+        Spans::from(Span::styled(
+            format!("Writing to {} starts in {} seconds", disk, remaining.as_secs() + 1),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        )),
+        Spans::from(""),
+        Spans::from("Press c to go back or q to quit, nothing has been written yet"),
+    ];
+    let paragraph = Paragraph::new(text)
+        .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32,32,32)))
+        .block(Block::default().borders(Borders::ALL).title(app.state.title()));
+    f.render_widget(paragraph, area);
 }
 
-fn draw_deletion_progress<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
+fn draw_deletion_progress<B>(f: &mut Frame<B>, app: &App, jobs: &[JobKind], area: Rect)
 where
     B: Backend,
 {
+    //A line for the message, then per drive a gauge, two lines of numbers and a throughput sparkline
+    let mut constraints = vec![Constraint::Length(1)];
+    for _ in jobs.iter() {
+        constraints.extend([Constraint::Length(1), Constraint::Length(2), Constraint::Length(3)]);
+    }
+    constraints.push(Constraint::Min(0));
     let chunks = Layout::default()
-        .constraints(
-            [
-                Constraint::Length(1),
-                Constraint::Length(1),
-                Constraint::Length(2),
-            ]
-            .as_ref(),
-        )
+        .constraints(constraints)
         .margin(1)
         .split(area);
     let block = Block::default().borders(Borders::ALL).style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32,32,32)));
     f.render_widget(block, area);
 
-    let message = Paragraph::new("Deletion in progress, do not close this window!")
+    let message = match jobs.first() {
+        Some(JobKind::Clone { .. }) => "Clone in progress, do not close this window!",
+        Some(JobKind::Burn { .. }) => "Burn in progress, do not close this window!",
+        _ => "Deletion in progress, do not close this window!",
+    };
+    let message = Paragraph::new(message)
         .style(Style::default().fg(Color::Yellow));
     f.render_widget(message, chunks[0]);
 
-    
+    let default_status = JobStatus::default();
+    for (index, job) in jobs.iter().enumerate() {
+        let status = app.job_statuses.get(index).unwrap_or(&default_status);
+        let rows = &chunks[1 + index * 3..4 + index * 3];
+        //A failed drive stays on screen in red while the others keep going
+        let (label, color) = match &status.error {
+            Some(error) => (format!("{} failed: {}", job.disk().name.display(), error), Color::Red),
+            None if status.finished => (format!("{} [done]", job.disk().name.display()), Color::Green),
+            None => (
+                format!("{} [{:.2}%, {}]", job.disk().name.display(), status.progress * 100.0, status.stage),
+                Color::Yellow,
+            ),
+        };
+        let gauge = Gauge::default()
+            .block(Block::default())
+            .gauge_style(
+                Style::default()
+                    .bg(Color::Black)
+                    .fg(color)
+                    .add_modifier(Modifier::ITALIC | Modifier::BOLD),
+            )
+            .label(label)
+            .ratio(status.progress.clamp(0.0, 1.0));
+        f.render_widget(gauge, rows[0]);
 
-    let label = format!("[{:.2}%, round 1 of 7, pass 1 of 3]", app.deletion_progress * 100.0);
-    let gauge = Gauge::default()
-        .block(Block::default())
-        .gauge_style(
-            Style::default()
-                .bg(Color::Black)
-                .fg(Color::Yellow)
-                .add_modifier(Modifier::ITALIC | Modifier::BOLD),
-        )
-        .label(label)
-        .ratio(app.deletion_progress);
-    f.render_widget(gauge, chunks[1]);
+        let details = Paragraph::new(progress_details(status)).style(Style::default().fg(Color::Yellow));
+        f.render_widget(details, rows[1]);
+
+        //kB/s so slow drives don't flatten to zero, only as many samples as fit
+        let width = rows[2].width as usize;
+        let samples: Vec<u64> = status.history[status.history.len().saturating_sub(width)..]
+            .iter()
+            .map(|rate| (rate / 1e3) as u64)
+            .collect();
+        let sparkline = Sparkline::default()
+            .data(&samples)
+            .style(Style::default().fg(color));
+        f.render_widget(sparkline, rows[2]);
+    }
 }
 
-fn draw_status<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
+//What is being done and how fast, below a job's gauge
+fn progress_details(status: &JobStatus) -> Vec<Spans<'static>> {
+    let (stage, stage_name) = match &status.wipe {
+        Some(wipe) => (
+            format!(
+                "Pass {} of {}, {}{}",
+                wipe.pass,
+                wipe.total_passes,
+                wipe.pattern,
+                if wipe.verifying { ", verifying" } else { "" }
+            ),
+            "pass",
+        ),
+        None => (status.stage.clone(), "stage"),
+    };
+    let total = if status.bytes_total > 0 { format!(" of {}", format_size(status.bytes_total)) } else { String::new() };
+    let rate = |rate: Option<f64>| rate.map(|r| format!("{}/s", format_size(r as u64))).unwrap_or_else(|| String::from("-"));
+    let eta = |eta: Option<Duration>| eta.map(format_duration).unwrap_or_else(|| String::from("-"));
+    vec![
+        Spans::from(format!(
+            "{}, {} written, {}{} done",
+            stage,
+            format_size(status.bytes_written),
+            format_size(status.bytes_done),
+            total
+        )),
+        Spans::from(format!(
+            "Now {}, average {}, elapsed {}, ETA {} this {}, {} in total",
+            rate(status.current_rate()),
+            rate(status.average_rate()),
+            format_duration(status.running_time()),
+            eta(status.stage_eta()),
+            stage_name,
+            eta(status.eta())
+        )),
+    ]
+}
+
+//Size in SI units like 1.5 GB, which is what drives are sold by
+fn format_size(bytes: u64) -> String {
+    scaled_size(bytes, 1000.0, &["B", "kB", "MB", "GB", "TB", "PB"])
+}
+
+//Size in IEC units like 1.4 GiB, which is what most tools and filesystems report
+fn format_size_iec(bytes: u64) -> String {
+    scaled_size(bytes, 1024.0, &["B", "KiB", "MiB", "GiB", "TiB", "PiB"])
+}
+
+//Both, e.g. 62.0 GB (57.8 GiB), so the size matches the label on the drive and what other tools say
+fn format_capacity(bytes: u64) -> String {
+    if bytes < 1000 {
+        return format_size(bytes);
+    }
+    format!("{} ({})", format_size(bytes), format_size_iec(bytes))
+}
+
+fn scaled_size(bytes: u64, step: f64, units: &[&str]) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= step && unit < units.len() - 1 {
+        value /= step;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+//Latest log records in place of the partition layout, scrolled up `log_scroll` lines from the bottom
+fn draw_log<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
+where
+    B: Backend,
+{
+    let lines = app.log.lines();
+    let rows = area.height.saturating_sub(2) as usize;
+    app.log_scroll = app.log_scroll.min(lines.len().saturating_sub(rows));
+    let end = lines.len() - app.log_scroll;
+    let text: Vec<Spans> = lines[end.saturating_sub(rows)..end]
+        .iter()
+        .map(|line| {
+            let color = match line.level {
+                Level::Error => Color::Red,
+                Level::Warn => Color::LightRed,
+                Level::Info => Color::Yellow,
+                Level::Debug | Level::Trace => Color::Gray,
+            };
+            Spans::from(Span::styled(line.to_string(), Style::default().fg(color)))
+        })
+        .collect();
+    let title = if app.log_scroll > 0 {
+        format!("Log, {} newer lines below (PgUp/PgDn to scroll, l to hide)", app.log_scroll)
+    } else {
+        String::from("Log (PgUp/PgDn to scroll, l to hide)")
+    };
+    let log = Paragraph::new(text)
+        .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32, 32, 32)))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(log, area);
+}
+
+//Dump of the page of the drive or image on screen with a prompt or what happened last below it
+fn draw_hex_view<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
+where
+    B: Backend,
+{
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(4)].as_ref())
+        .split(area);
+    let style = Style::default().fg(Color::Yellow).bg(Color::Rgb(32, 32, 32));
+    let viewer = match &mut app.viewer {
+        Some(Ok(viewer)) => viewer,
+        Some(Err(e)) => {
+            let text = vec![Spans::from(e.to_string()), Spans::from("Press c to go back")];
+            f.render_widget(Paragraph::new(text).style(style).block(Block::default().borders(Borders::ALL).title("Hex Viewer")), area);
+            return;
+        }
+        None => return,
+    };
+    viewer.set_rows(chunks[0].height.saturating_sub(2) as u64);
+
+    let found = viewer.found.map(|start| start..start + viewer.pattern_len() as u64);
+    let lines: Vec<Spans> = viewer
+        .data
+        .chunks(BYTES_PER_LINE as usize)
+        .enumerate()
+        .map(|(i, bytes)| {
+            let offset = viewer.offset + i as u64 * BYTES_PER_LINE;
+            let line = format_line(offset, bytes);
+            let end = offset + bytes.len() as u64;
+            //Matches stand out, zeroed lines fade so whatever a wipe missed is easy to spot
+            if found.as_ref().is_some_and(|found| found.start < end && offset < found.end) {
+                Spans::from(Span::styled(line, Style::default().bg(Color::Rgb(229, 83, 0)).fg(Color::White)))
+            } else if bytes.iter().all(|b| *b == 0) {
+                Spans::from(Span::styled(line, Style::default().fg(Color::DarkGray)))
+            } else {
+                Spans::from(line)
+            }
+        })
+        .collect();
+    let title = format!(
+        "{}  {}  offset {} (0x{:x}), sector {} of {} B",
+        viewer.path.display(),
+        format_capacity(viewer.size),
+        viewer.offset,
+        viewer.offset,
+        viewer.offset / viewer.sector_size,
+        viewer.sector_size
+    );
+    f.render_widget(Paragraph::new(lines).style(style).block(Block::default().borders(Borders::ALL).title(title)), chunks[0]);
+
+    let status = match (viewer.prompt, viewer.search_progress()) {
+        (Some(prompt), _) => Spans::from(format!("{}: {}_", prompt.label(), viewer.input)),
+        (None, Some(progress)) => Spans::from(format!("Searching... {:.0}%, c to cancel", progress * 100.0)),
+        (None, None) => Spans::from(viewer.message.clone().unwrap_or_default()),
+    };
+    let help = Spans::from("↑↓ line  PgUp/PgDn page  g offset  s sector  p next partition  / search  n next match  c back");
+    f.render_widget(Paragraph::new(vec![status, help]).style(style).block(Block::default().borders(Borders::ALL)), chunks[1]);
+}
+
+fn draw_status<B>(f: &mut Frame<B>, app: &App, jobs: &[JobKind], area: Rect)
 where
     B: Backend,
 {
-    //TODO check status show either success page or error
-    // if app.status.errors {
-    // }
-    // else if app.status.success {
-    // }
+    let mut text = Vec::new();
+    for line in app.session_summary.iter() {
+        text.push(Spans::from(line.as_str()));
+    }
+    if !app.session_summary.is_empty() {
+        text.push(Spans::from(""));
+    }
+    //Check status show either success page or error, for every drive
+    for (job, status) in jobs.iter().zip(app.job_statuses.iter()) {
+        let (headline, color) = match &status.error {
+            Some(error) => (format!("Failed: {}", error), Color::Red),
+            None if !status.verify_failures.is_empty() => (
+                format!("Finished, but {} blocks did not verify!", status.verify_failures.len()),
+                Color::Red,
+            ),
+            None => (String::from("Finished successfully"), Color::Green),
+        };
+        let headline = match app.state.mode() {
+            _ if jobs.len() > 1 => format!("{}: {}", job.disk().name.display(), headline),
+            Some(mode) => format!("{}: {}", mode.name(), headline),
+            None => headline,
+        };
+        text.push(Spans::from(Span::styled(headline, Style::default().fg(color))));
+        if jobs.len() == 1 {
+            text.push(Spans::from(""));
+        }
+        text.extend(status.summary.iter().map(|line| Spans::from(line.as_str())));
+    }
+    text.push(Spans::from(""));
+    text.push(Spans::from("Press e to start over, v to look at the drive or q to quit"));
+    let paragraph = Paragraph::new(text)
+        .style(Style::default().fg(Color::Yellow).bg(Color::Rgb(32,32,32)))
+        .block(Block::default().borders(Borders::ALL).title(app.state.title()))
+        .wrap(Wrap { trim: true });
+    f.render_widget(paragraph, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn partition(start: u64, size: u64) -> PartitionInfo {
+        PartitionInfo {
+            device: PathBuf::from("/dev/test1"),
+            label: None,
+            file_system: None,
+            mount_point: None,
+            start,
+            size,
+            used: None,
+        }
+    }
+
+    #[test]
+    fn layout_shows_unallocated_gaps() {
+        let mib = 1 << 20;
+        //A sliver of alignment padding up front, then a real gap between the partitions and at the end
+        let partitions = vec![partition(50 * mib, 50 * mib), partition(4096, 10 * mib)];
+        let segments = disk_segments(200 * mib, &partitions);
+        let parts: Vec<(u64, Option<usize>)> = segments.iter().map(|s| (s.start, s.partition)).collect();
+        assert_eq!(parts, vec![(4096, Some(1)), (10 * mib + 4096, None), (50 * mib, Some(0)), (100 * mib, None)]);
+        //Columns are proportional to size
+        let columns = segment_columns(200 * mib, &segments, 20);
+        assert_eq!(columns.iter().filter(|c| **c == Some(2)).count(), 5);
+        assert_eq!(columns.iter().filter(|c| **c == Some(3)).count(), 10);
+    }
+
+    #[test]
+    fn sizes_read_in_both_units() {
+        assert_eq!(format_size(999), "999 B");
+        assert_eq!(format_capacity(62_008_590_336), "62.0 GB (57.8 GiB)");
+        assert_eq!(format_size_iec(1 << 20), "1.0 MiB");
+    }
 }
@@ -1,8 +1,6 @@
 // #[cfg(feature = "termion")]
 pub mod event;
-use crate::App;
-use termion::event::Key;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ffi::OsStr;
 use tui::widgets::ListState;
 
@@ -16,7 +14,7 @@ pub struct TabsState<'a> {
 }
 
 impl<'a> TabsState<'a> {
-    pub fn new(titles: Vec<&'a str>) -> TabsState {
+    pub fn new(titles: Vec<&'a str>) -> TabsState<'a> {
         TabsState { titles, index: 0 }
     }
     pub fn next(&mut self) {
@@ -40,6 +38,7 @@ pub struct StatefulList<T> {
 }
 
 impl<T> StatefulList<T> {
+    #[allow(dead_code)]
     pub fn new() -> StatefulList<T> {
         StatefulList {
             state: ListState::default(),
@@ -55,6 +54,9 @@ impl<T> StatefulList<T> {
     }
 
     pub fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.items.len() - 1 {
@@ -69,6 +71,9 @@ impl<T> StatefulList<T> {
     }
 
     pub fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -91,12 +96,21 @@ impl<T> StatefulList<T> {
 
 
 
-//Todo, method to handle global events. From here then handle events through blocks.
-pub fn handle_app(key: Key, app: &mut App) {
-    match key {
-        Key::Char('c') => {
-
-        }
-        _ => {}
-    }
-} 
\ No newline at end of file
+///Image files (.iso and .img) in `directory` which can be burned to a drive
+pub fn find_images(directory: &Path) -> Vec<PathBuf> {
+    let mut images: Vec<PathBuf> = match std::fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file())
+            .filter(|p| {
+                p.extension()
+                    .and_then(OsStr::to_str)
+                    .is_some_and(|e| e.eq_ignore_ascii_case("iso") || e.eq_ignore_ascii_case("img"))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    images.sort();
+    images
+}
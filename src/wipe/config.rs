@@ -0,0 +1,260 @@
+//User defined wipe methods loaded from a TOML file, for example:
+//
+// [[method]]
+// name = "ACME internal spec v2"
+// description = "Zeroes, ones, then verified random data"
+// passes = [
+//     { type = "byte", value = 0x00 },
+//     { type = "complement" },
+//     { type = "sequence", value = [0x27, 0xFF, 0xFF, 0xFF] },
+//     { type = "random", verify = true },
+// ]
+// discard = true   # TRIM every block afterwards, on drives that support it
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::wipe::{Pass, Pattern, WipeMethod};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MethodsFile {
+    #[serde(default)]
+    method: Vec<MethodEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MethodEntry {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    passes: Vec<PassEntry>,
+    #[serde(default)]
+    discard: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PassEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    value: Option<toml::Value>,
+    #[serde(default)]
+    verify: bool,
+}
+
+///Where the methods file is looked up when none is given on the command line
+pub fn default_methods_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("minuteman").join("methods.toml"))
+}
+
+///Reads and validates every method in the file. All problems are reported at once, one per line,
+/// so a broken spec can be fixed in a single go.
+pub fn load_methods(path: &Path) -> Result<Vec<WipeMethod>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    parse_methods(&contents, path)
+}
+
+//Everything `load_methods` does once the file is read, `path` only labels the errors
+fn parse_methods(contents: &str, path: &Path) -> Result<Vec<WipeMethod>> {
+    let file: MethodsFile = toml::from_str(contents).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+
+    let mut methods = Vec::new();
+    let mut errors = Vec::new();
+    for (i, entry) in file.method.iter().enumerate() {
+        match parse_method(entry) {
+            Ok(method) => methods.push(method),
+            Err(problems) => {
+                let label = if entry.name.trim().is_empty() {
+                    format!("method #{}", i + 1)
+                } else {
+                    format!("method \"{}\"", entry.name)
+                };
+                errors.extend(problems.into_iter().map(|p| format!("{}: {}: {}", path.display(), label, p)));
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(methods)
+    } else {
+        Err(anyhow!(errors.join("\n")))
+    }
+}
+
+fn parse_method(entry: &MethodEntry) -> std::result::Result<WipeMethod, Vec<String>> {
+    let mut problems = Vec::new();
+    if entry.name.trim().is_empty() {
+        problems.push(String::from("name must not be empty"));
+    }
+    if entry.passes.is_empty() {
+        problems.push(String::from("at least one pass is required"));
+    }
+    let mut passes = Vec::new();
+    for (i, pass) in entry.passes.iter().enumerate() {
+        match parse_pattern(pass, i) {
+            Ok(pattern) => passes.push(Pass {
+                pattern,
+                verify: pass.verify,
+            }),
+            Err(problem) => problems.push(format!("pass {}: {}", i + 1, problem)),
+        }
+    }
+    if problems.is_empty() {
+        let mut method = WipeMethod::new(entry.name.trim(), &entry.description, passes);
+        method.discard = entry.discard;
+        Ok(method)
+    } else {
+        Err(problems)
+    }
+}
+
+fn parse_byte(value: &toml::Value) -> std::result::Result<u8, String> {
+    match value.as_integer() {
+        Some(n) if (0..=255).contains(&n) => Ok(n as u8),
+        _ => Err(format!("{} is not a byte between 0 and 255", value)),
+    }
+}
+
+fn parse_pattern(pass: &PassEntry, index: usize) -> std::result::Result<Pattern, String> {
+    let value = pass.value.as_ref();
+    match pass.kind.as_str() {
+        "byte" => parse_byte(value.ok_or("byte passes need a value")?).map(Pattern::Byte),
+        "sequence" => {
+            let items = value
+                .and_then(|v| v.as_array())
+                .ok_or("sequence passes need an array of bytes as value")?;
+            if items.is_empty() {
+                return Err(String::from("sequence must not be empty"));
+            }
+            items.iter().map(parse_byte).collect::<std::result::Result<Vec<u8>, String>>().map(Pattern::Sequence)
+        }
+        "random" | "complement" if value.is_some() => Err(format!("{} passes don't take a value", pass.kind)),
+        "random" => Ok(Pattern::Random),
+        "complement" if index == 0 => Err(String::from("complement can't be the first pass, there is nothing to invert")),
+        "complement" => Ok(Pattern::Complement),
+        other => Err(format!(
+            "unknown pass type \"{}\", expected byte, sequence, random or complement",
+            other
+        )),
+    }
+}
+
+///Appends the custom methods after the built in ones, names have to stay unique so the list
+/// stays unambiguous
+pub fn merge_methods(builtin: Vec<WipeMethod>, custom: Vec<WipeMethod>) -> Result<Vec<WipeMethod>> {
+    let mut methods = builtin;
+    let mut errors = Vec::new();
+    for method in custom {
+        if methods.iter().any(|m| m.name == method.name) {
+            errors.push(format!("method \"{}\" is defined more than once", method.name));
+        } else {
+            methods.push(method);
+        }
+    }
+    if errors.is_empty() {
+        Ok(methods)
+    } else {
+        Err(anyhow!(errors.join("\n")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wipe::builtin_methods;
+
+    fn parse(contents: &str) -> Result<Vec<WipeMethod>> {
+        parse_methods(contents, Path::new("methods.toml"))
+    }
+
+    #[test]
+    fn valid_methods_parse() {
+        let methods = parse(
+            r#"
+            [[method]]
+            name = " ACME v2 "
+            passes = [
+                { type = "byte", value = 0xFF },
+                { type = "complement" },
+                { type = "sequence", value = [0x27, 0xFF] },
+                { type = "random", verify = true },
+            ]
+            discard = true
+            [[method]]
+            name = "Zero"
+            passes = [{ type = "byte", value = 0 }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(methods.len(), 2);
+        assert_eq!(methods[0].name, "ACME v2");
+        assert!(methods[0].discard);
+        assert!(!methods[1].discard);
+        let patterns: Vec<&Pattern> = methods[0].passes.iter().map(|p| &p.pattern).collect();
+        assert_eq!(
+            patterns,
+            vec![&Pattern::Byte(0xFF), &Pattern::Complement, &Pattern::Sequence(vec![0x27, 0xFF]), &Pattern::Random]
+        );
+        assert!(methods[0].passes[3].verify);
+    }
+
+    #[test]
+    fn broken_methods_are_explained() {
+        let cases = [
+            (r#"passes = [{ type = "shuffle" }]"#, "pass 1: unknown pass type \"shuffle\""),
+            (r#"passes = []"#, "at least one pass is required"),
+            ("", "at least one pass is required"),
+            (r#"passes = [{ type = "byte", value = 0x1FF }]"#, "pass 1: 511 is not a byte"),
+            (r#"passes = [{ type = "byte", value = 0xGG }]"#, "invalid"),
+            (r#"passes = [{ type = "byte", value = "0xFF" }]"#, "pass 1: \"0xFF\" is not a byte"),
+            (r#"passes = [{ type = "sequence", value = [0x00, -1] }]"#, "pass 1: -1 is not a byte"),
+            (r#"passes = [{ type = "complement" }, { type = "random" }]"#, "pass 1: complement can't be the first pass"),
+            (r#"passes = [{ type = "random", value = 1 }]"#, "pass 1: random passes don't take a value"),
+            (r#"passes = [{ type = "random", verfy = true }]"#, "unknown field"),
+        ];
+        for (passes, expected) in cases {
+            let contents = format!("[[method]]\nname = \"a\"\n{}\n", passes);
+            let error = parse(&contents).unwrap_err().to_string();
+            assert!(error.contains(expected), "expected \"{}\" in \"{}\"", expected, error);
+            assert!(error.starts_with("methods.toml: "), "{}", error);
+        }
+        let nameless = parse("[[method]]\nname = \" \"\npasses = [{ type = \"random\" }]").unwrap_err();
+        assert_eq!(nameless.to_string(), "methods.toml: method #1: name must not be empty");
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let error = parse(
+            r#"
+            [[method]]
+            name = "a"
+            passes = [{ type = "complement" }, { type = "byte" }]
+            [[method]]
+            name = "b"
+            "#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(error.lines().count(), 3, "{}", error);
+    }
+
+    #[test]
+    fn names_stay_unique() {
+        let custom = |name: &str| WipeMethod::new(name, "", vec![Pass { pattern: Pattern::Random, verify: false }]);
+        let builtin = builtin_methods();
+        let shadowing = merge_methods(builtin.clone(), vec![custom(&builtin[0].name)]).unwrap_err();
+        assert!(shadowing.to_string().contains("is defined more than once"));
+        let duplicate = merge_methods(builtin.clone(), vec![custom("a"), custom("b"), custom("a")]).unwrap_err();
+        assert_eq!(duplicate.to_string(), "method \"a\" is defined more than once");
+        let merged = merge_methods(builtin.clone(), vec![custom("a")]).unwrap();
+        assert_eq!(merged.len(), builtin.len() + 1);
+        assert_eq!(merged.last().unwrap().name, "a");
+    }
+}
@@ -0,0 +1,278 @@
+//Runs the overwrite passes of a `WipeMethod` against anything implementing `DriveAccessor`
+
+use anyhow::{anyhow, Result};
+
+use crate::disk::direct::{pipeline, AlignedBuffer};
+use crate::disk::DriveAccessor;
+use crate::wipe::random::RandomStream;
+use crate::wipe::{Pass, Pattern};
+
+//Chunk size is ~ 1mb
+pub const CHUNK_SIZE: usize = 1048576;
+
+/// Snapshot of a running wipe handed to the progress callback.
+#[derive(Clone, Debug)]
+pub struct WipeProgress {
+    pub pass: usize, //1 based
+    pub total_passes: usize,
+    pub pattern: Pattern,
+    pub verifying: bool,
+    pub bytes_done: u64, //Within the current pass or verify
+    pub bytes_total: u64,
+}
+
+/// Result of a finished wipe.
+#[derive(Clone, Debug, Default)]
+pub struct WipeOutcome {
+    pub bytes_written: u64,
+    pub verified: bool, //Whether any pass was read back
+    pub verify_failures: Vec<u64>, //Offsets of the chunks that did not read back as written
+}
+
+/// Generates the bytes every pass writes at a given offset, passes stay reproducible so they can be
+/// verified and complemented without keeping a copy of what was written.
+#[derive(Clone)]
+pub struct PatternSource {
+    passes: Vec<Pass>,
+    streams: Vec<RandomStream>, //One per pass, only used by random ones
+}
+
+impl PatternSource {
+    pub fn new(passes: &[Pass]) -> PatternSource {
+        PatternSource {
+            passes: passes.to_vec(),
+            streams: passes.iter().map(|_| RandomStream::new()).collect(),
+        }
+    }
+
+    ///Fills `buffer` with what pass `index` writes starting at byte `offset` of the target
+    pub fn fill(&self, index: usize, offset: u64, buffer: &mut [u8]) {
+        match &self.passes[index].pattern {
+            Pattern::Byte(b) => buffer.iter_mut().for_each(|x| *x = *b),
+            Pattern::Sequence(sequence) => {
+                let len = sequence.len() as u64;
+                for (i, x) in buffer.iter_mut().enumerate() {
+                    *x = sequence[((offset + i as u64) % len) as usize];
+                }
+            }
+            Pattern::Random => {
+                self.streams[index].fill(offset, buffer);
+            }
+            Pattern::Complement => {
+                if index == 0 {
+                    buffer.iter_mut().for_each(|x| *x = 0xFF);
+                } else {
+                    self.fill(index - 1, offset, buffer);
+                    buffer.iter_mut().for_each(|x| *x = !*x);
+                }
+            }
+        }
+    }
+}
+
+///Writes every pass over the first `size` bytes of `target`, flushing between passes and reading back
+/// the ones flagged for verification
+pub fn run_passes<T, F>(target: &mut T, size: u64, passes: &[Pass], on_progress: F) -> Result<WipeOutcome>
+where
+    T: DriveAccessor + ?Sized,
+    F: FnMut(&WipeProgress),
+{
+    run_source(target, size, &PatternSource::new(passes), CHUNK_SIZE, on_progress)
+}
+
+///Same as `run_passes` with the pattern stream and block size given. The next block is generated while
+/// the current one is written, verify failures are reported per block.
+pub fn run_source<T, F>(target: &mut T, size: u64, source: &PatternSource, block_size: usize, mut on_progress: F) -> Result<WipeOutcome>
+where
+    T: DriveAccessor + ?Sized,
+    F: FnMut(&WipeProgress),
+{
+    let passes = &source.passes;
+    let mut outcome = WipeOutcome::default();
+    let mut actual = AlignedBuffer::new(block_size);
+
+    for (index, pass) in passes.iter().enumerate() {
+        let mut progress = WipeProgress {
+            pass: index + 1,
+            total_passes: passes.len(),
+            pattern: pass.pattern.clone(),
+            verifying: false,
+            bytes_done: 0,
+            bytes_total: size,
+        };
+        on_progress(&progress);
+
+        let generate = |offset, buffer: &mut [u8]| {
+            source.fill(index, offset, buffer);
+            Ok(buffer.len())
+        };
+        target.seek(0)?;
+        pipeline(size, block_size, generate, |offset, data| {
+            target.write(data)?;
+            outcome.bytes_written += data.len() as u64;
+            progress.bytes_done = offset + data.len() as u64;
+            on_progress(&progress);
+            Ok(())
+        })?;
+        //Make sure the pass actually reached the media before starting the next one
+        target.flush()?;
+
+        if pass.verify {
+            outcome.verified = true;
+            progress.verifying = true;
+            progress.bytes_done = 0;
+            on_progress(&progress);
+
+            target.seek(0)?;
+            pipeline(size, block_size, generate, |offset, expected| {
+                let actual = &mut actual[..expected.len()];
+                read_exact(target, actual)?;
+                if actual != expected {
+                    outcome.verify_failures.push(offset);
+                }
+                progress.bytes_done = offset + expected.len() as u64;
+                on_progress(&progress);
+                Ok(())
+            })?;
+        }
+    }
+    Ok(outcome)
+}
+
+fn read_exact<T: DriveAccessor + ?Sized>(target: &mut T, buffer: &mut [u8]) -> Result<()> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match target.read(&mut buffer[filled..])? {
+            0 => return Err(anyhow!("unexpected end of device while verifying")),
+            n => filled += n,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{File, OpenOptions};
+    use std::path::PathBuf;
+
+    const BLOCK: usize = 4096;
+
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, size: usize) -> (TempFile, File) {
+            let path = std::env::temp_dir().join(format!("minuteman-engine-{}-{}", name, std::process::id()));
+            std::fs::write(&path, vec![0x5A; size]).unwrap();
+            let file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+            (TempFile(path), file)
+        }
+
+        fn contents(&self) -> Vec<u8> {
+            std::fs::read(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    //Reads back one byte wrong, like a failing sector would
+    struct Flipped {
+        inner: File,
+        bad: u64,
+    }
+
+    impl DriveAccessor for Flipped {
+        fn position(&mut self) -> Result<u64> {
+            self.inner.position()
+        }
+
+        fn seek(&mut self, position: u64) -> Result<u64> {
+            self.inner.seek(position)
+        }
+
+        fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+            let start = self.inner.position()?;
+            let len = self.inner.read(buffer)?;
+            if (start..start + len as u64).contains(&self.bad) {
+                buffer[(self.bad - start) as usize] ^= 1;
+            }
+            Ok(len)
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<()> {
+            self.inner.write(data)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            DriveAccessor::flush(&mut self.inner)
+        }
+    }
+
+    #[test]
+    fn passes_run_in_order() {
+        let size = BLOCK * 3 + 100;
+        let (temp, mut file) = TempFile::new("order", size);
+        let passes = vec![
+            Pass::new(Pattern::Byte(0x11)),
+            Pass::verified(Pattern::Sequence(vec![1, 2, 3])),
+            Pass::new(Pattern::Random),
+            Pass::verified(Pattern::Complement),
+        ];
+        let source = PatternSource::new(&passes);
+        let mut seen = Vec::new();
+        let outcome = run_source(&mut file, size as u64, &source, BLOCK, |p| {
+            if p.bytes_done == 0 {
+                seen.push((p.pass, p.pattern.clone(), p.verifying));
+            }
+        })
+        .unwrap();
+        assert_eq!(
+            seen,
+            vec![
+                (1, Pattern::Byte(0x11), false),
+                (2, Pattern::Sequence(vec![1, 2, 3]), false),
+                (2, Pattern::Sequence(vec![1, 2, 3]), true),
+                (3, Pattern::Random, false),
+                (4, Pattern::Complement, false),
+                (4, Pattern::Complement, true),
+            ]
+        );
+        assert_eq!(outcome.bytes_written, size as u64 * 4);
+        assert!(outcome.verified);
+        assert!(outcome.verify_failures.is_empty());
+
+        //The last pass inverts the random one
+        let mut random = vec![0; size];
+        source.fill(2, 0, &mut random);
+        let expected: Vec<u8> = random.iter().map(|b| !b).collect();
+        assert!(temp.contents() == expected);
+        assert!(random.iter().any(|b| *b != random[0]));
+    }
+
+    #[test]
+    fn sequences_continue_across_blocks() {
+        let size = BLOCK + 10;
+        let (temp, mut file) = TempFile::new("sequence", size);
+        run_source(&mut file, size as u64, &PatternSource::new(&[Pass::new(Pattern::Sequence(vec![1, 2, 3]))]), BLOCK, |_| {}).unwrap();
+        let contents = temp.contents();
+        assert!(contents.iter().enumerate().all(|(i, b)| *b == [1, 2, 3][i % 3]));
+    }
+
+    #[test]
+    fn verify_failures_are_reported_per_block() {
+        let size = BLOCK * 3 + 100;
+        let (_temp, file) = TempFile::new("verify", size);
+        let mut drive = Flipped { inner: file, bad: BLOCK as u64 * 2 + 5 };
+        let passes = [Pass::verified(Pattern::Byte(0)), Pass::new(Pattern::Random), Pass::verified(Pattern::Complement)];
+        let outcome = run_source(&mut drive, size as u64, &PatternSource::new(&passes), BLOCK, |_| {}).unwrap();
+        assert_eq!(outcome.verify_failures, vec![BLOCK as u64 * 2, BLOCK as u64 * 2]);
+
+        drive.bad = size as u64 - 1;
+        let outcome = run_source(&mut drive, size as u64, &PatternSource::new(&passes[..1]), BLOCK, |_| {}).unwrap();
+        assert_eq!(outcome.verify_failures, vec![BLOCK as u64 * 3]);
+    }
+}
@@ -0,0 +1,225 @@
+//Free space wiping for mounted filesystems. Files filled with the wipe pattern are created until the
+//filesystem runs out of space, which overwrites the blocks of previously deleted files while leaving
+//live files alone. The filler files are removed again after every pass.
+
+use anyhow::{anyhow, Result};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::disk::{calculate_partition_size, Partition};
+use crate::wipe::engine::{PatternSource, WipeOutcome, WipeProgress};
+use crate::wipe::WipeMethod;
+
+//Filler files stay below the 4GiB FAT32 file size limit
+const FILE_SIZE: u64 = 1 << 30;
+const CHUNK_SIZE: usize = 1048576;
+//Once a full chunk no longer fits, keep writing smaller ones down to a single block
+const MIN_CHUNK_SIZE: usize = 4096;
+
+//Directory holding the filler files, removed with everything in it when dropped. That includes returning
+//early with an error, so no filler file outlives the wipe
+struct FillDirectory {
+    path: PathBuf,
+    files: Vec<(PathBuf, u64, u64)>, //Path, offset in the pattern stream, length
+}
+
+impl FillDirectory {
+    fn create(mount_point: &Path) -> Result<FillDirectory> {
+        let name: String = rand::thread_rng().sample_iter(&Alphanumeric).take(8).map(char::from).collect();
+        let path = mount_point.join(format!(".minuteman-free-space-{}", name));
+        std::fs::create_dir(&path)?;
+        Ok(FillDirectory { path, files: Vec::new() })
+    }
+}
+
+impl Drop for FillDirectory {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+        if let Some(parent) = self.path.parent() {
+            let _ = File::open(parent).and_then(|d| d.sync_all());
+        }
+    }
+}
+
+//Evicts the synced pages of a filler file, reading it back right after writing would otherwise only
+//check the page cache and never the drive
+fn drop_cache(file: &File) -> Result<()> {
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+        0 => Ok(()),
+        error => Err(std::io::Error::from_raw_os_error(error).into()),
+    }
+}
+
+fn is_out_of_space(e: &std::io::Error) -> bool {
+    //EFBIG means the filesystem's file size limit was hit before running out of space
+    matches!(e.raw_os_error(), Some(libc::ENOSPC) | Some(libc::EFBIG) | Some(libc::EDQUOT))
+}
+
+///Overwrites all free space of a mounted partition with every pass of `method`. Progress is measured
+/// against the free space statvfs reports, root can usually write a little past it into reserved blocks.
+pub fn wipe_free_space<F>(partition: &Partition, method: &WipeMethod, on_progress: F) -> Result<WipeOutcome>
+where
+    F: FnMut(&WipeProgress),
+{
+    fill_free_space(partition, method, u64::MAX, on_progress)
+}
+
+//Same as `wipe_free_space`, the filesystem counts as full once a pass wrote `limit` bytes
+fn fill_free_space<F>(partition: &Partition, method: &WipeMethod, limit: u64, mut on_progress: F) -> Result<WipeOutcome>
+where
+    F: FnMut(&WipeProgress),
+{
+    if method.is_firmware() {
+        return Err(anyhow!("{} erases whole drives and can't wipe free space", method.name));
+    }
+    if partition.read_only {
+        return Err(anyhow!("{} is mounted read only", partition.name));
+    }
+    let source = PatternSource::new(&method.passes);
+    let mut outcome = WipeOutcome::default();
+    let mut buffer: Vec<u8> = vec![0; CHUNK_SIZE];
+
+    for (index, pass) in method.passes.iter().enumerate() {
+        let (_, free) = calculate_partition_size(&partition.mount_point)?;
+        let mut progress = WipeProgress {
+            pass: index + 1,
+            total_passes: method.passes.len(),
+            pattern: pass.pattern.clone(),
+            verifying: false,
+            bytes_done: 0,
+            bytes_total: free.min(limit),
+        };
+        on_progress(&progress);
+
+        let mut directory = FillDirectory::create(&partition.mount_point)?;
+        let mut offset: u64 = 0;
+        let mut chunk = CHUNK_SIZE;
+        'fill: loop {
+            let path = directory.path.join(format!("{:06}", directory.files.len()));
+            //Even creating another file fails once the filesystem is full
+            let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => file,
+                Err(ref e) if is_out_of_space(e) => break 'fill,
+                Err(e) => return Err(e.into()),
+            };
+            let start = offset;
+            let mut written: u64 = 0;
+            let mut full = false;
+            while written < FILE_SIZE && !full {
+                let len = (chunk as u64).min(limit - offset) as usize;
+                if len == 0 {
+                    full = true;
+                    break;
+                }
+                source.fill(index, offset, &mut buffer[..len]);
+                match file.write(&buffer[..len]) {
+                    Ok(0) => full = true,
+                    Ok(n) => {
+                        offset += n as u64;
+                        written += n as u64;
+                        outcome.bytes_written += n as u64;
+                        progress.bytes_done = offset.min(progress.bytes_total);
+                        on_progress(&progress);
+                    }
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(ref e) if is_out_of_space(e) && chunk > MIN_CHUNK_SIZE => chunk /= 2,
+                    Err(ref e) if is_out_of_space(e) => full = true,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            //With delayed allocation the sync can still run into ENOSPC or EDQUOT, the filesystem is full then too
+            match file.sync_all() {
+                Ok(()) => {}
+                Err(ref e) if is_out_of_space(e) => full = true,
+                Err(e) => return Err(e.into()),
+            }
+            if pass.verify {
+                drop_cache(&file)?;
+            }
+            directory.files.push((path, start, written));
+            if full {
+                break;
+            }
+        }
+
+        if pass.verify {
+            outcome.verified = true;
+            progress.verifying = true;
+            progress.bytes_done = 0;
+            progress.bytes_total = offset;
+            on_progress(&progress);
+            let mut expected: Vec<u8> = vec![0; CHUNK_SIZE];
+            for (path, start, len) in directory.files.iter() {
+                let mut file = File::open(path)?;
+                let mut done: u64 = 0;
+                while done < *len {
+                    let n = (*len - done).min(CHUNK_SIZE as u64) as usize;
+                    file.read_exact(&mut buffer[..n])?;
+                    source.fill(index, start + done, &mut expected[..n]);
+                    if buffer[..n] != expected[..n] {
+                        outcome.verify_failures.push(start + done);
+                    }
+                    done += n as u64;
+                    progress.bytes_done = start + done;
+                    on_progress(&progress);
+                }
+            }
+        }
+        //Give the space back before the next pass
+        drop(directory);
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wipe::{Pass, Pattern};
+
+    fn fillers(directory: &Path) -> Vec<PathBuf> {
+        std::fs::read_dir(directory).unwrap().map(|e| e.unwrap().path()).collect()
+    }
+
+    #[test]
+    fn fillers_are_verified_and_removed() {
+        let directory = std::env::temp_dir().join(format!("minuteman-free-space-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let partition = Partition {
+            name: String::from("test"),
+            mount_point: directory.clone(),
+            file_system: String::from("tmpfs"),
+            free: 0,
+            total: 0,
+            read_only: false,
+        };
+        let method = WipeMethod::new("Zero", "", vec![Pass::verified(Pattern::Byte(0))]);
+        let limit = CHUNK_SIZE as u64 * 3 + 1234;
+        let mut seen = Vec::new();
+        let outcome = fill_free_space(&partition, &method, limit, |p| {
+            if p.bytes_done == p.bytes_total {
+                seen.push(fillers(&directory).len());
+            }
+        })
+        .unwrap();
+        let left = fillers(&directory);
+
+        //Dropping the directory is what cleans up after errors
+        let mut failed = FillDirectory::create(&directory).unwrap();
+        std::fs::write(failed.path.join("000000"), b"data").unwrap();
+        failed.files.push((failed.path.join("000000"), 0, 4));
+        drop(failed);
+        let left_after_error = fillers(&directory);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(outcome.bytes_written, limit);
+        assert!(outcome.verified);
+        assert!(outcome.verify_failures.is_empty());
+        assert_eq!(seen.last(), Some(&1)); //The filler directory existed while verifying
+        assert!(left.is_empty(), "{:?}", left);
+        assert!(left_after_error.is_empty(), "{:?}", left_after_error);
+    }
+}
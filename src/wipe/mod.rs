@@ -0,0 +1,225 @@
+//Wipe methods, each one is an ordered list of overwrite passes or a firmware erase (see `sanitize`)
+
+use crate::sanitize::SanitizeCommand;
+
+pub mod config;
+pub mod engine;
+pub mod free_space;
+pub mod random;
+
+/// What gets written to every byte of the target during a pass.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+    Byte(u8),
+    Sequence(Vec<u8>), //Repeated from the start of the device
+    Random,
+    Complement, //Bitwise inverse of whatever the previous pass wrote
+}
+
+/// A single overwrite of the whole target, optionally read back afterwards.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pass {
+    pub pattern: Pattern,
+    pub verify: bool,
+}
+
+impl Pass {
+    pub fn new(pattern: Pattern) -> Pass {
+        Pass { pattern, verify: false }
+    }
+
+    pub fn verified(pattern: Pattern) -> Pass {
+        Pass { pattern, verify: true }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Pattern::Byte(b) => write!(f, "{:#04x}", b),
+            Pattern::Sequence(s) => {
+                let bytes: Vec<String> = s.iter().map(|b| format!("{:02x}", b)).collect();
+                write!(f, "0x{}", bytes.join(""))
+            }
+            Pattern::Random => write!(f, "random"),
+            Pattern::Complement => write!(f, "complement"),
+        }
+    }
+}
+
+/// `WipeMethod` is an entry of the method selection list. Software methods are made of `passes`,
+/// firmware methods instead list the `sanitize` commands they can map to in order of preference.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WipeMethod {
+    pub name: String,
+    pub description: String,
+    pub passes: Vec<Pass>,
+    pub sanitize: Vec<SanitizeCommand>,
+    pub discard: bool, //Discard every block once the passes are done, entire drive wipes only
+}
+
+impl WipeMethod {
+    pub fn new(name: &str, description: &str, passes: Vec<Pass>) -> WipeMethod {
+        WipeMethod {
+            name: name.to_string(),
+            description: description.to_string(),
+            passes,
+            sanitize: Vec::new(),
+            discard: false,
+        }
+    }
+
+    pub fn firmware(name: &str, description: &str, sanitize: Vec<SanitizeCommand>) -> WipeMethod {
+        WipeMethod {
+            name: name.to_string(),
+            description: description.to_string(),
+            passes: Vec::new(),
+            sanitize,
+            discard: false,
+        }
+    }
+
+    /// Whether the method runs on the drive firmware instead of overwriting from the host
+    pub fn is_firmware(&self) -> bool {
+        !self.sanitize.is_empty()
+    }
+
+    /// How many times the method goes over the whole target, every pass plus the ones read back
+    pub fn rounds(&self) -> u64 {
+        (self.passes.len() + self.passes.iter().filter(|p| p.verify).count()) as u64
+    }
+
+    /// First firmware command of this method that the drive accepts
+    pub fn sanitize_command(&self, supported: &[SanitizeCommand]) -> Option<SanitizeCommand> {
+        self.sanitize.iter().copied().find(|c| supported.contains(c))
+    }
+}
+
+use Pattern::{Byte, Complement, Random, Sequence};
+
+fn passes(patterns: Vec<Pattern>) -> Vec<Pass> {
+    patterns.into_iter().map(Pass::new).collect()
+}
+
+//Peter Gutmann's 35 passes, random passes around patterns aimed at MFM/RLL encodings
+fn gutmann_passes() -> Vec<Pass> {
+    let mut patterns = vec![Random, Random, Random, Random, Byte(0x55), Byte(0xAA)];
+    patterns.push(Sequence(vec![0x92, 0x49, 0x24]));
+    patterns.push(Sequence(vec![0x49, 0x24, 0x92]));
+    patterns.push(Sequence(vec![0x24, 0x92, 0x49]));
+    patterns.extend((0..16).map(|n| Byte(n * 0x11)));
+    patterns.push(Sequence(vec![0x92, 0x49, 0x24]));
+    patterns.push(Sequence(vec![0x49, 0x24, 0x92]));
+    patterns.push(Sequence(vec![0x24, 0x92, 0x49]));
+    patterns.push(Sequence(vec![0x6D, 0xB6, 0xDB]));
+    patterns.push(Sequence(vec![0xB6, 0xDB, 0x6D]));
+    patterns.push(Sequence(vec![0xDB, 0x6D, 0xB6]));
+    patterns.extend(vec![Random, Random, Random, Random]);
+    passes(patterns)
+}
+
+///All methods shipped with the application, modern single pass ones first
+pub fn builtin_methods() -> Vec<WipeMethod> {
+    let mut methods = vec![
+        WipeMethod::new(
+            "NIST SP 800-88 Clear (1 rewrite and 1 verify)",
+            "Overwrites every user addressable block with zeroes and reads them back. \
+             Sufficient against software recovery tools on hard drives, NIST's recommendation for reuse within the organisation.",
+            vec![Pass::verified(Byte(0x00))],
+        ),
+        WipeMethod::firmware(
+            "NIST SP 800-88 Purge (device sanitize or crypto erase)",
+            "Asks the drive firmware to sanitize itself, preferring a cryptographic erase, then block erase, then a \
+             firmware overwrite. Reaches spare and remapped blocks that host writes can't, use this for SSDs leaving the organisation.",
+            vec![
+                SanitizeCommand::NvmeSanitizeCrypto,
+                SanitizeCommand::NvmeSanitizeBlock,
+                SanitizeCommand::NvmeFormatCryptoErase,
+                SanitizeCommand::AtaEnhancedSecurityErase,
+                SanitizeCommand::AtaSecurityErase,
+                SanitizeCommand::NvmeSanitizeOverwrite,
+                SanitizeCommand::NvmeFormatSecureErase,
+            ],
+        ),
+        WipeMethod::new(
+            "Zero fill (1 rewrite)",
+            "Writes zeroes over the whole drive once without reading them back. The fastest software method.",
+            passes(vec![Byte(0x00)]),
+        ),
+        WipeMethod::new(
+            "Random data (1 rewrite)",
+            "Writes a single pass of random data, leaves the drive looking unused to anyone inspecting it.",
+            passes(vec![Random]),
+        ),
+        WipeMethod::new(
+            "British HMG IS5 (1 rewrite and 1 verify)",
+            "UK government baseline standard. Overwrites the drive with zeroes and verifies the write.",
+            vec![Pass::verified(Byte(0x00))],
+        ),
+        WipeMethod::new(
+            "Russian GOST P50739-95 (2 rewrites)",
+            "Russian standard, a pass of zeroes followed by a pass of random data.",
+            passes(vec![Byte(0x00), Random]),
+        ),
+        WipeMethod::new(
+            "NAVSO P-5239-26 (RLL), (3 rewrites and 1 verify)",
+            "US Navy standard for RLL encoded drives. Writes 0x01, then 0x27FFFFFF, then random data and verifies the last pass.",
+            vec![
+                Pass::new(Byte(0x01)),
+                Pass::new(Sequence(vec![0x27, 0xFF, 0xFF, 0xFF])),
+                Pass::verified(Random),
+            ],
+        ),
+        WipeMethod::new(
+            "NAVSO P-5239-26 (ALT), (3 rewrites and 1 verify)",
+            "US Navy standard for MFM encoded drives. Writes 0x01, then 0x7FFFFFFF, then random data and verifies the last pass.",
+            vec![
+                Pass::new(Byte(0x01)),
+                Pass::new(Sequence(vec![0x7F, 0xFF, 0xFF, 0xFF])),
+                Pass::verified(Random),
+            ],
+        ),
+        WipeMethod::new(
+            "Department of Defense (DoD, USA 5220.22-M) (3 rewrites and 3 verify)",
+            "US Department of Defense standard. Writes zeroes, their complement and random data, verifying each pass.",
+            vec![Pass::verified(Byte(0x00)), Pass::verified(Complement), Pass::verified(Random)],
+        ),
+        WipeMethod::new(
+            "Department of Defense (DoD, USA 5220.22-M ECE) (7 rewrites)",
+            "Extended DoD standard. Runs the three DoD passes, a random pass, then the three DoD passes again.",
+            passes(vec![Byte(0x00), Complement, Random, Random, Byte(0x00), Complement, Random]),
+        ),
+        WipeMethod::new(
+            "Canadian RCMP TSSIT OPS-II (7 rewrites)",
+            "Royal Canadian Mounted Police standard. Alternates zeroes and ones six times, finishing with a verified random pass.",
+            vec![
+                Pass::new(Byte(0x00)),
+                Pass::new(Byte(0xFF)),
+                Pass::new(Byte(0x00)),
+                Pass::new(Byte(0xFF)),
+                Pass::new(Byte(0x00)),
+                Pass::new(Byte(0xFF)),
+                Pass::verified(Random),
+            ],
+        ),
+        WipeMethod::new(
+            "German VSITR (7 rewrites)",
+            "German federal standard. Alternates zeroes and ones six times and finishes with 0xAA.",
+            passes(vec![Byte(0x00), Byte(0xFF), Byte(0x00), Byte(0xFF), Byte(0x00), Byte(0xFF), Byte(0xAA)]),
+        ),
+        WipeMethod::new(
+            "Bruce Schneier (7 rewrites)",
+            "From Applied Cryptography. A pass of ones, a pass of zeroes, then five passes of random data.",
+            passes(vec![Byte(0xFF), Byte(0x00), Random, Random, Random, Random, Random]),
+        ),
+        WipeMethod::new(
+            "Peter Gutmann (35 rewrites)",
+            "Covers every historical hard drive encoding with 27 fixed patterns wrapped in 8 random passes. \
+             Overkill for any drive made this century and very slow, kept for compliance requirements.",
+            gutmann_passes(),
+        ),
+    ];
+    //Individual firmware commands for users who want a specific one
+    methods.extend(SanitizeCommand::ALL.iter().map(|c| WipeMethod::firmware(c.name(), c.description(), vec![*c])));
+    methods
+}
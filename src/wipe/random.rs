@@ -0,0 +1,111 @@
+//Keystream for random passes. ChaCha20 is seekable, so any byte of a pass can be regenerated from the
+//seed and its offset alone, which is what lets verify passes and complement passes work without a copy
+//of what was written.
+
+use rand::rngs::OsRng;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+//Buffers larger than this are split up and filled on several threads
+const PARALLEL_THRESHOLD: usize = 1 << 20;
+const SEGMENT_SIZE: usize = 256 << 10;
+
+/// Seeded random byte stream, position `n` always holds the same byte for the same seed.
+#[derive(Clone)]
+pub struct RandomStream {
+    seed: [u8; 32],
+}
+
+impl RandomStream {
+    /// Stream with a fresh seed from the operating system
+    pub fn new() -> RandomStream {
+        let mut seed = [0; 32];
+        OsRng.fill_bytes(&mut seed);
+        RandomStream { seed }
+    }
+
+    ///Fills `buffer` with the stream starting at byte `offset`
+    pub fn fill(&self, offset: u64, buffer: &mut [u8]) {
+        let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1).min(8);
+        if buffer.len() < PARALLEL_THRESHOLD || threads == 1 {
+            self.fill_segment(offset, buffer);
+        } else {
+            self.fill_parallel(offset, buffer, threads);
+        }
+    }
+
+    fn fill_parallel(&self, offset: u64, buffer: &mut [u8], threads: usize) {
+        let segment = buffer.len().div_ceil(threads).next_multiple_of(SEGMENT_SIZE);
+        std::thread::scope(|scope| {
+            for (i, part) in buffer.chunks_mut(segment).enumerate() {
+                scope.spawn(move || self.fill_segment(offset + (i * segment) as u64, part));
+            }
+        });
+    }
+
+    fn fill_segment(&self, offset: u64, buffer: &mut [u8]) {
+        let mut rng = ChaCha20Rng::from_seed(self.seed);
+        //The generator seeks in 32 bit words, unaligned starts drop the head of the first word
+        rng.set_word_pos((offset / 4) as u128);
+        let skip = (offset % 4) as usize;
+        if skip == 0 {
+            rng.fill_bytes(buffer);
+            return;
+        }
+        let mut word = [0; 4];
+        rng.fill_bytes(&mut word);
+        let head = (4 - skip).min(buffer.len());
+        buffer[..head].copy_from_slice(&word[skip..skip + head]);
+        rng.fill_bytes(&mut buffer[head..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream() -> RandomStream {
+        RandomStream { seed: [7; 32] }
+    }
+
+    #[test]
+    fn any_offset_regenerates_the_same_bytes() {
+        let mut whole = vec![0; 4096];
+        stream().fill(0, &mut whole);
+        for offset in [1usize, 3, 4, 5, 1000, 4095] {
+            let mut part = vec![0; 4096 - offset];
+            stream().fill(offset as u64, &mut part);
+            assert_eq!(part, whole[offset..]);
+        }
+    }
+
+    #[test]
+    fn parallel_fill_matches_sequential() {
+        let size = PARALLEL_THRESHOLD * 3 + 17;
+        let mut parallel = vec![0; size];
+        stream().fill_parallel(5, &mut parallel, 3);
+        let mut sequential = vec![0; size];
+        stream().fill_segment(5, &mut sequential);
+        assert!(parallel == sequential);
+    }
+
+    #[test]
+    fn seeds_give_different_streams() {
+        let (mut a, mut b) = ([0; 64], [0; 64]);
+        stream().fill(0, &mut a);
+        RandomStream { seed: [8; 32] }.fill(0, &mut b);
+        assert_ne!(a, b);
+        assert_ne!(a, [0; 64]);
+    }
+
+    #[test]
+    #[ignore]
+    fn throughput() {
+        let mut buffer = vec![0; 1 << 20];
+        let started = std::time::Instant::now();
+        for i in 0..1024u64 {
+            stream().fill(i << 20, &mut buffer);
+        }
+        eprintln!("{:.2} GB/s", (1u64 << 30) as f64 / started.elapsed().as_secs_f64() / 1e9);
+    }
+}